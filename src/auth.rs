@@ -0,0 +1,234 @@
+//! Application level client authentication right after connecting.
+//! The server opens with a challenge naming the method and carrying a nonce, the client answers with its credential
+//! and the server replies whether it was accepted. A rejected client is disconnected before any handler sees it.
+//! <br>In byte read mode every message is prefixed with its u16 big endian length.
+//! <br>Either side waits at most the auth timeout for the other's next message, [`DEFAULT_AUTH_TIMEOUT`] unless told
+//! otherwise, so a peer that never answers can't hold up a server's worker or a client's `open`.
+
+use super::*;
+use ::std::fmt;
+use win::Win32::Foundation::{ERROR_ACCESS_DENIED, ERROR_INVALID_DATA};
+
+const MAGIC: [u8; 4] = *b"WNPA";
+const SHARED_SECRET: u8 = 0;
+#[cfg(feature = "hmac")]
+const HMAC_SHA256: u8 = 1;
+const NONCE_LEN: usize = 32;
+/// longest credential a client may send
+const MAX_CREDENTIAL_LEN: usize = 1024;
+
+/// How long either side of the authentication waits for the other unless told otherwise, 10 seconds.
+pub const DEFAULT_AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Decides whether a freshly connected client may talk to the server, see
+/// [`PipeServerBuilder::authenticator`] and [`DuplexServer::connect_authenticated`].
+/// <br>Closures taking the connected server and returning whether to accept are authenticators too.
+pub trait Authenticator: Send + Sync {
+        /// Run the server side of the exchange, `Ok(true)` accepts the client and anything else rejects it.
+        fn authenticate(&self, pipe: &mut ConnectedDuplexServer<'_>) -> io::Result<bool>;
+}
+impl<F: Fn(&mut ConnectedDuplexServer<'_>) -> io::Result<bool> + Send + Sync> Authenticator for F {
+        fn authenticate(&self, pipe: &mut ConnectedDuplexServer<'_>) -> io::Result<bool> { self(pipe) }
+}
+
+/// Accepts clients sending the same secret, configured with [`Credential::SharedSecret`].
+/// <br>The secret crosses the pipe as is, prefer [`HmacChallenge`] on pipes that accept remote clients.
+#[derive(Clone)]
+pub struct SharedSecret {
+        secret: Vec<u8>,
+        timeout: Option<Duration>,
+}
+impl SharedSecret {
+        pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+                Self {
+                        secret: secret.into(),
+                        timeout: Some(DEFAULT_AUTH_TIMEOUT),
+                }
+        }
+        /// How long to wait for the client's answer, [`DEFAULT_AUTH_TIMEOUT`] by default and `None` waits forever.
+        pub fn timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+                self.timeout = timeout.map(|timeout| timeout.max(Duration::from_millis(1)));
+                self
+        }
+}
+impl Authenticator for SharedSecret {
+        fn authenticate(&self, pipe: &mut ConnectedDuplexServer<'_>) -> io::Result<bool> {
+                let message = pipe.handle_state()?.read_mode == ReadMode::Message;
+                let response = challenge(pipe, message, SHARED_SECRET, &[0u8; NONCE_LEN], self.timeout)?;
+                let accepted = response.first() == Some(&SHARED_SECRET) && constant_time_eq(&response[1..], &self.secret);
+                send(pipe, message, &[accepted as u8])?;
+                Ok(accepted)
+        }
+}
+impl fmt::Debug for SharedSecret {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.debug_struct("SharedSecret").finish_non_exhaustive() }
+}
+
+/// Accepts clients proving they know the key by answering a random challenge with its HMAC-SHA256, feature `hmac`.
+/// Clients are configured with [`Credential::Hmac`], the key itself never crosses the pipe.
+#[cfg(feature = "hmac")]
+#[derive(Clone)]
+pub struct HmacChallenge {
+        key: Vec<u8>,
+        timeout: Option<Duration>,
+}
+#[cfg(feature = "hmac")]
+impl HmacChallenge {
+        pub fn new(key: impl Into<Vec<u8>>) -> Self {
+                Self {
+                        key: key.into(),
+                        timeout: Some(DEFAULT_AUTH_TIMEOUT),
+                }
+        }
+        /// How long to wait for the client's answer, [`DEFAULT_AUTH_TIMEOUT`] by default and `None` waits forever.
+        pub fn timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+                self.timeout = timeout.map(|timeout| timeout.max(Duration::from_millis(1)));
+                self
+        }
+}
+#[cfg(feature = "hmac")]
+impl Authenticator for HmacChallenge {
+        fn authenticate(&self, pipe: &mut ConnectedDuplexServer<'_>) -> io::Result<bool> {
+                use hmac::Mac;
+                let message = pipe.handle_state()?.read_mode == ReadMode::Message;
+                let mut nonce = [0u8; NONCE_LEN];
+                getrandom::getrandom(&mut nonce).map_err(io::Error::from)?;
+                let response = challenge(pipe, message, HMAC_SHA256, &nonce, self.timeout)?;
+                let accepted = response.first() == Some(&HMAC_SHA256) && hmac_sha256(&self.key, &nonce).verify_slice(&response[1..]).is_ok();
+                send(pipe, message, &[accepted as u8])?;
+                Ok(accepted)
+        }
+}
+#[cfg(feature = "hmac")]
+impl fmt::Debug for HmacChallenge {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.debug_struct("HmacChallenge").finish_non_exhaustive() }
+}
+
+/// What a client answers the server's challenge with, see [`ClientBuilder::credential`](crate::builder::ClientBuilder::credential).
+#[derive(Clone)]
+pub enum Credential {
+        /// sent as is, for a [`SharedSecret`] server
+        SharedSecret(Vec<u8>),
+        /// key for the HMAC-SHA256 of the challenge, for an [`HmacChallenge`] server, feature `hmac`
+        #[cfg(feature = "hmac")]
+        Hmac(Vec<u8>),
+}
+impl Credential {
+        /// Answer the server's challenge on a freshly opened duplex client, `Ok(false)` if the server rejected it.
+        /// <br>Each read waits at most `timeout`, then the pipe gets its own read timeout back.
+        pub(crate) fn present(
+                &self,
+                pipe: &mut crate::clients::unsafes::UnsafeClient,
+                message: bool,
+                timeout: Option<Duration>,
+        ) -> win::core::Result<bool> {
+                let previous = ::std::mem::replace(&mut pipe.read_timeout, timeout);
+                let res = self.answer(pipe, message);
+                pipe.read_timeout = previous;
+                res
+        }
+        fn answer(&self, pipe: &mut crate::clients::unsafes::UnsafeClient, message: bool) -> win::core::Result<bool> {
+                let challenge = recv(pipe, message, MAGIC.len() + 1 + NONCE_LEN).map_err(win_error)?;
+                if challenge.len() != MAGIC.len() + 1 + NONCE_LEN || challenge[..MAGIC.len()] != MAGIC {
+                        return Err(ERROR_INVALID_DATA.into());
+                }
+                let method = challenge[MAGIC.len()];
+                let mut response = vec![method];
+                match (self, method) {
+                        (Credential::SharedSecret(secret), SHARED_SECRET) => response.extend_from_slice(secret),
+                        #[cfg(feature = "hmac")]
+                        (Credential::Hmac(key), HMAC_SHA256) => {
+                                use hmac::Mac;
+                                response.extend_from_slice(&hmac_sha256(key, &challenge[MAGIC.len() + 1..]).finalize().into_bytes())
+                        }
+                        // the server asks for something this client can't answer, it rejects the empty response
+                        _ => {}
+                }
+                send(pipe, message, &response).map_err(win_error)?;
+                Ok(recv(pipe, message, 1).map_err(win_error)? == [1])
+        }
+}
+impl fmt::Debug for Credential {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                        Credential::SharedSecret(_) => f.debug_tuple("SharedSecret").finish_non_exhaustive(),
+                        #[cfg(feature = "hmac")]
+                        Credential::Hmac(_) => f.debug_tuple("Hmac").finish_non_exhaustive(),
+                }
+        }
+}
+
+impl DuplexServer {
+        /// Connect the next client and run `authenticator` on it, a rejected client is disconnected and fails
+        /// with `ERROR_ACCESS_DENIED`.
+        pub fn connect_authenticated(&mut self, authenticator: &dyn Authenticator) -> Result<ConnectedDuplexServer<'_>, NamedPipeWinError> {
+                let msg = format!(
+                        r#"windows_named_pipes::DuplexServer::connect_authenticated("{}") failed with"#,
+                        self.path().display()
+                );
+                let mut connected = self.connect()?;
+                match authenticator.authenticate(&mut connected) {
+                        Ok(true) => Ok(connected),
+                        Ok(false) => Err(NamedPipeWinError::from_inner(ERROR_ACCESS_DENIED.into(), msg)),
+                        Err(err) => Err(NamedPipeWinError::from_inner(win_error(err), msg)),
+                }
+        }
+}
+
+/// send the challenge and wait for the client's response for at most `timeout`
+fn challenge(
+        pipe: &mut ConnectedDuplexServer<'_>,
+        message: bool,
+        method: u8,
+        nonce: &[u8; NONCE_LEN],
+        timeout: Option<Duration>,
+) -> io::Result<Vec<u8>> {
+        send(pipe, message, &[&MAGIC[..], &[method], nonce].concat())?;
+        let previous = pipe.read_timeout();
+        pipe.set_read_timeout(timeout)?;
+        let response = recv(pipe, message, 1 + MAX_CREDENTIAL_LEN);
+        pipe.set_read_timeout(previous)?;
+        response
+}
+fn send<P: Write>(pipe: &mut P, message: bool, bytes: &[u8]) -> io::Result<()> {
+        match message {
+                true => write_message(pipe, bytes),
+                false => {
+                        let len = u16::try_from(bytes.len())
+                                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "authentication message is longer than 65535 bytes"))?;
+                        pipe.write_all(&[&len.to_be_bytes()[..], bytes].concat())
+                }
+        }
+}
+fn recv<P: Read>(pipe: &mut P, message: bool, max: usize) -> io::Result<Vec<u8>> {
+        match message {
+                true => read_message(pipe, max),
+                false => {
+                        let mut len = [0u8; 2];
+                        pipe.read_exact(&mut len)?;
+                        let len = u16::from_be_bytes(len) as usize;
+                        if len > max {
+                                return Err(io::Error::new(io::ErrorKind::InvalidData, "authentication message is too long"));
+                        }
+                        let mut bytes = vec![0u8; len];
+                        pipe.read_exact(&mut bytes)?;
+                        Ok(bytes)
+                }
+        }
+}
+/// compare without returning early, so the time taken tells nothing about where the secrets differ
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool { a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0 }
+#[cfg(feature = "hmac")]
+fn hmac_sha256(key: &[u8], nonce: &[u8]) -> hmac::Hmac<sha2::Sha256> {
+        use hmac::Mac;
+        let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+        mac.update(nonce);
+        mac
+}
+/// back from the io error of a read or write to the win32 error it came from
+fn win_error(err: io::Error) -> WinError {
+        match err.raw_os_error() {
+                Some(code) => WIN32_ERROR(code as u32).into(),
+                None => ERROR_INVALID_DATA.into(),
+        }
+}
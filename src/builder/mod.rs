@@ -19,10 +19,15 @@ pub struct ServerBuilder<Server: ServerImpl> {
         /// How read operations are handled. Forced Byte Mode on Byte Type.
         pub(crate) read_mode: ReadMode, // INFO: must be Byte when pipe type is Byte
 
-        // overlapped: bool, // NOT IMPLEMENTED: Win32 API Asynchronous I/O, let rust async handle sync as async
-        /// Fails if pipe already exists.
+        /// `FILE_FLAG_OVERLAPPED`, off by default.
+        pub(crate) overlapped: bool,
+
+        /// Fails if pipe already exists. Only has an effect with `secure` off.
         pub(crate) create_new: bool, // CreateNamedPipe opens or creates if it doesn't exist, unless create_new is true
 
+        /// Forces `create_new`, on by default against pipe name squatting.
+        pub(crate) secure: bool,
+
         /// accept or automatically reject remote clients.
         pub(crate) remote_clients: bool,
 
@@ -34,8 +39,8 @@ pub struct ServerBuilder<Server: ServerImpl> {
         /// inbound buffer size (client -> server)
         pub(crate) buffer_size_in: u32,
 
-        /// default timeout for IO and connection requests
-        pub(crate) timeout: u32,
+        /// default timeout for clients waiting on a busy pipe
+        pub(crate) timeout: Timeout,
 
         pub(crate) security: SECURITY_ATTRIBUTES, // *const windows::Win32::Security::SECURITY_ATTRIBUTES
 }
@@ -48,10 +53,28 @@ impl<Server: ServerImpl> ServerBuilder<Server> {
                 self.read_mode = read_mode;
                 self
         }
+        /// Fail if the pipe already exists instead of opening another instance of it.
+        /// <br>Ignored while [`secure`](Self::secure) is on, which already refuses existing pipes. Further instances of a
+        /// pipe this process created come from `new_instance()` on its first instance.
         pub fn create_new(&mut self, create_new: bool) -> &mut Self {
                 self.create_new = create_new;
                 self
         }
+        /// On by default: the pipe must not exist yet, whatever [`create_new`](Self::create_new) says, so no other process can have
+        /// created it first.
+        /// <br>It only guards the first instance, `new_instance()` on that server opens further ones without it. Turn it off
+        /// only to add instances to a pipe some other code created.
+        pub fn secure(&mut self, secure: bool) -> &mut Self {
+                self.secure = secure;
+                self
+        }
+        /// Off by default. Create the pipe with `FILE_FLAG_OVERLAPPED`, so a read blocked on one thread doesn't hold up a write on
+        /// another, which [`split`](ConnectedDuplexServer::split) and [`Mux`] need.
+        /// <br>std only does synchronous I/O, so don't turn an overlapped server into a [`fs::File`] or [`Stdio`](std::process::Stdio).
+        pub fn overlapped(&mut self, overlapped: bool) -> &mut Self {
+                self.overlapped = overlapped;
+                self
+        }
         pub fn remote_clients(&mut self, remote_clients: bool) -> &mut Self {
                 self.remote_clients = remote_clients;
                 self
@@ -68,7 +91,7 @@ impl<Server: ServerImpl> ServerBuilder<Server> {
                 self.buffer_size_in = buffersize_in;
                 self
         }
-        pub fn timeout(&mut self, timeout: u32) -> &mut Self {
+        pub fn timeout(&mut self, timeout: Timeout) -> &mut Self {
                 self.timeout = timeout;
                 self
         }
@@ -87,7 +110,7 @@ impl<Server: ServerImpl> ServerBuilder<Server> {
                                 self.max_instances,
                                 self.buffer_size_out,
                                 self.buffer_size_in,
-                                self.timeout,
+                                self.timeout.into(),
                                 self.security,
                         ) {
                                 Ok(inner) => Ok(Server::from_inner(inner)),
@@ -105,8 +128,11 @@ impl<Server: ServerImpl> ServerBuilder<Server> {
                 }
         }
         fn get_open_mode(&self) -> FILE_FLAGS_AND_ATTRIBUTES {
-                let pre = Server::ACCESS_MODE.into();
-                if self.create_new {
+                let pre = match self.overlapped {
+                        true => Server::ACCESS_MODE.into() | FILE_FLAG_OVERLAPPED,
+                        false => Server::ACCESS_MODE.into(),
+                };
+                if self.create_new || self.secure {
                         pre | FILE_FLAG_FIRST_PIPE_INSTANCE | FILE_FLAG_WRITE_THROUGH
                 } else {
                         pre | FILE_FLAG_WRITE_THROUGH
@@ -122,6 +148,25 @@ impl<Server: ServerImpl> ServerBuilder<Server> {
         }
 }
 
+impl<Server: ServerImpl> Clone for ServerBuilder<Server> {
+        fn clone(&self) -> Self {
+                ServerBuilder {
+                        __server: PhantomData::<Server>,
+                        type_mode: self.type_mode,
+                        read_mode: self.read_mode,
+                        create_new: self.create_new,
+                        secure: self.secure,
+                        overlapped: self.overlapped,
+                        remote_clients: self.remote_clients,
+                        max_instances: self.max_instances,
+                        buffer_size_out: self.buffer_size_out,
+                        buffer_size_in: self.buffer_size_in,
+                        timeout: self.timeout,
+                        security: self.security,
+                }
+        }
+}
+
 impl<Server: ServerImpl> Default for ServerBuilder<Server> {
         fn default() -> Self {
                 // REVIEW: good defaults?
@@ -130,11 +175,13 @@ impl<Server: ServerImpl> Default for ServerBuilder<Server> {
                         type_mode: TypeMode::Message,
                         read_mode: ReadMode::Message,
                         create_new: false,
+                        secure: true,
+                        overlapped: false,
                         remote_clients: false,
                         max_instances: 1,
                         buffer_size_out: DEFAULT_BUFSIZE,
                         buffer_size_in: DEFAULT_BUFSIZE,
-                        timeout: NMPWAIT_WAIT_FOREVER,
+                        timeout: Timeout::Forever,
                         security: SECURITY_ATTRIBUTES::default(),
                 }
         }
@@ -147,6 +194,21 @@ pub struct ClientBuilder<Client: ClientImpl> {
         pub(crate) read_mode: ReadMode, // INFO: must be Byte when pipe type is Byte
 
         pub(crate) security_token: Option<ImpersonationLevel>,
+
+        /// how long to wait while every instance of the pipe is busy, `None` fails right away.
+        pub(crate) timeout: Option<Timeout>,
+
+        /// who must be serving the pipe, checked right after opening.
+        pub(crate) expect_server: Option<ServerIdentity>,
+
+        /// answer to the server's authentication challenge, sent right after opening.
+        pub(crate) credential: Option<Credential>,
+
+        /// how long to wait for each message of the server while authenticating.
+        pub(crate) auth_timeout: Option<Duration>,
+
+        /// `FILE_FLAG_OVERLAPPED`, off by default.
+        pub(crate) overlapped: bool,
 }
 impl<Client: ClientImpl> ClientBuilder<Client> {
         pub fn read_mode(&mut self, read_mode: ReadMode) -> &mut Self {
@@ -158,21 +220,79 @@ impl<Client: ClientImpl> ClientBuilder<Client> {
                 self.security_token = Some(security_token);
                 self
         }
+        pub fn timeout(&mut self, timeout: Timeout) -> &mut Self {
+                self.timeout = Some(timeout);
+                self
+        }
+        /// Off by default. Open the pipe with `FILE_FLAG_OVERLAPPED`, so a read blocked on one thread doesn't hold up a write on
+        /// another, which [`split`](DuplexClient::split), `RpcClient` and [`Mux`] need.
+        /// <br>std only does synchronous I/O, so don't turn an overlapped client into a [`fs::File`] or [`Stdio`](std::process::Stdio).
+        pub fn overlapped(&mut self, overlapped: bool) -> &mut Self {
+                self.overlapped = overlapped;
+                self
+        }
+        /// Check who serves the pipe before anything is sent, `open` closes the client again and fails with an
+        /// [`UntrustedServer`] error (see [`NamedPipeWinError::untrusted_server`]) if it is someone else.
+        pub fn expect_server(&mut self, identity: ServerIdentity) -> &mut Self {
+                self.expect_server = Some(identity);
+                self
+        }
         pub fn new() -> Self { Self::default() }
         pub fn open(&self, path: &Path) -> Result<Client, NamedPipeWinError> {
-                unsafe {
-                        match UnsafeClient::open(path, Client::ACCESS_MODE, self.read_mode, self.security_token) {
-                                Ok(inner) => Ok(Client::from_inner(inner)),
-                                Err(inner) => Err(NamedPipeWinError::from_inner(
-                                        inner,
-                                        format!(
-                                                r#"windows_named_pipes::ClientBuilder::<{}>::open("{}") failed with"#,
-                                                ::std::any::type_name::<Client>(),
-                                                path.display(),
-                                        ),
-                                )),
+                let msg = || {
+                        format!(
+                                r#"windows_named_pipes::ClientBuilder::<{}>::open("{}") failed with"#,
+                                ::std::any::type_name::<Client>(),
+                                path.display(),
+                        )
+                };
+                let mut inner = unsafe {
+                        UnsafeClient::open_timeout(
+                                path,
+                                Client::ACCESS_MODE,
+                                self.read_mode,
+                                self.security_token,
+                                self.timeout,
+                                self.overlapped,
+                        )
+                }
+                .map_err(|inner| NamedPipeWinError::from_inner(inner, msg()))?;
+                if let Some(expected) = &self.expect_server {
+                        // dropping `inner` closes the client, nothing has been written yet
+                        match verify_server(&inner.handle, expected) {
+                                Ok(None) => {}
+                                Ok(Some(untrusted)) => return Err(NamedPipeWinError::from_untrusted(untrusted, msg())),
+                                Err(err) => return Err(NamedPipeWinError::from_inner(err, msg())),
                         }
                 }
+                if let Some(credential) = &self.credential {
+                        match credential.present(&mut inner, self.read_mode == ReadMode::Message, self.auth_timeout) {
+                                Ok(true) => {}
+                                Ok(false) => {
+                                        return Err(NamedPipeWinError::from_inner(
+                                                win::Win32::Foundation::ERROR_ACCESS_DENIED.into(),
+                                                format!("{} a rejected credential", msg()),
+                                        ))
+                                }
+                                Err(err) => return Err(NamedPipeWinError::from_inner(err, msg())),
+                        }
+                }
+                Ok(unsafe { Client::from_inner(inner) })
+        }
+}
+/// Only duplex clients can answer an authentication challenge.
+impl ClientBuilder<DuplexClient> {
+        /// Authenticate with `credential` to a server checking clients with an [`Authenticator`], `open` fails with
+        /// `ERROR_ACCESS_DENIED` if the server rejects it.
+        pub fn credential(&mut self, credential: Credential) -> &mut Self {
+                self.credential = Some(credential);
+                self
+        }
+        /// How long to wait for each message of the server while authenticating, [`DEFAULT_AUTH_TIMEOUT`] by default and
+        /// `None` waits forever. `open` fails with `ERROR_TIMEOUT` once it elapses.
+        pub fn auth_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+                self.auth_timeout = timeout.map(|timeout| timeout.max(Duration::from_millis(1)));
+                self
         }
 }
 
@@ -183,6 +303,11 @@ impl<Client: ClientImpl> Default for ClientBuilder<Client> {
                         __client: PhantomData::<Client>,
                         read_mode: ReadMode::Message,
                         security_token: None,
+                        timeout: None,
+                        expect_server: None,
+                        credential: None,
+                        auth_timeout: Some(DEFAULT_AUTH_TIMEOUT),
+                        overlapped: false,
                 }
         }
 }
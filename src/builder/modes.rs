@@ -144,3 +144,75 @@ impl ImpersonationLevel {
                 }
         }
 }
+
+/// How long to wait for a pipe instance to become available.
+/// <br>`CreateNamedPipe::nDefaultTimeOut`, `WaitNamedPipe::nTimeOut`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Timeout {
+        /// wait until an instance is available, no matter how long.
+        Forever,
+        /// use the server's default timeout, 50 milliseconds when the server was created with `UseDefault`.
+        UseDefault,
+        /// wait at most the given duration, truncated to milliseconds and clamped to at least 1 millisecond.
+        Duration(Duration),
+}
+impl Timeout {
+        pub fn into(self) -> u32 {
+                match self {
+                        Timeout::Forever => NMPWAIT_WAIT_FOREVER,
+                        Timeout::UseDefault => NMPWAIT_USE_DEFAULT_WAIT,
+                        Timeout::Duration(d) => d.as_millis().clamp(1, (NMPWAIT_WAIT_FOREVER - 1) as u128) as u32,
+                }
+        }
+        pub fn from(millis: u32) -> Self {
+                match millis {
+                        NMPWAIT_WAIT_FOREVER => Timeout::Forever,
+                        NMPWAIT_USE_DEFAULT_WAIT => Timeout::UseDefault,
+                        millis => Timeout::Duration(Duration::from_millis(millis as u64)),
+                }
+        }
+}
+
+/// Which end of the pipe a handle belongs to.
+/// <br>`GetNamedPipeInfo::lpFlags`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[repr(u32)]
+pub enum PipeEnd {
+        Client = PIPE_CLIENT_END.0,
+        Server = PIPE_SERVER_END.0,
+}
+impl PipeEnd {
+        pub fn into(self) -> NAMED_PIPE_MODE { NAMED_PIPE_MODE(self as u32) }
+        pub fn from(nmpm: NAMED_PIPE_MODE) -> Self {
+                if nmpm.0 & PIPE_SERVER_END.0 != 0 {
+                        Self::Server
+                } else {
+                        Self::Client
+                }
+        }
+}
+
+/// Maximum number of instances of a pipe.
+/// <br>`CreateNamedPipe::nMaxInstances`, `GetNamedPipeInfo::lpMaxInstances`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MaxInstances {
+        /// at most this many instances, 1 to 254
+        Limited(u32),
+        /// `PIPE_UNLIMITED_INSTANCES`, only limited by system resources
+        Unlimited,
+}
+impl MaxInstances {
+        pub fn into(self) -> u32 {
+                match self {
+                        MaxInstances::Limited(max) => max,
+                        MaxInstances::Unlimited => PIPE_UNLIMITED_INSTANCES,
+                }
+        }
+        pub fn from(max: u32) -> Self {
+                if max == PIPE_UNLIMITED_INSTANCES {
+                        MaxInstances::Unlimited
+                } else {
+                        MaxInstances::Limited(max)
+                }
+        }
+}
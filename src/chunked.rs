@@ -0,0 +1,221 @@
+//! Messages larger than a single pipe message should be.
+//! A message pipe only guarantees [`MAX_TRANSACTION`] bytes per transaction and a byte pipe blocks writes larger than its
+//! buffer until the reader catches up, so [`Chunked`] splits every message into pieces no larger than the pipe's buffer.
+//! The first piece starts with the total length as a u64 big endian, the receiver checks it against its limit before
+//! taking any more and reassembles the pieces into one message.
+//! <br>In message read mode every piece is its own pipe message, in byte read mode they are plain writes.
+
+use super::*;
+use ::std::fmt;
+
+/// total length at the start of the first piece
+const HEADER_LEN: usize = 8;
+/// room for the header and some data, whatever the pipe's buffer
+const MIN_CHUNK_SIZE: usize = 2 * HEADER_LEN;
+
+/// Piece size and message limit of a [`Chunked`] pipe.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ChunkedConfig {
+        /// `None` for the buffer size the pipe was created with
+        pub(crate) chunk_size: Option<usize>,
+        pub(crate) max_message_size: usize,
+}
+impl ChunkedConfig {
+        /// Largest piece written at once, by default the pipe's outgoing buffer size. Kept between 16 bytes and [`MAX_TRANSACTION`].
+        pub fn chunk_size(&mut self, chunk_size: usize) -> &mut Self {
+                self.chunk_size = Some(clamp_chunk_size(chunk_size));
+                self
+        }
+        /// Largest whole message sent or received, [`DEFAULT_MAX_FRAME_SIZE`] by default.
+        pub fn max_message_size(&mut self, max_message_size: usize) -> &mut Self {
+                self.max_message_size = max_message_size;
+                self
+        }
+        pub fn new() -> Self { Self::default() }
+}
+impl Default for ChunkedConfig {
+        fn default() -> Self {
+                ChunkedConfig {
+                        chunk_size: None,
+                        max_message_size: DEFAULT_MAX_FRAME_SIZE,
+                }
+        }
+}
+
+/// A connected server or client sending messages of any size up to a limit, split into pieces that fit the pipe's buffer.
+/// <br>Both ends must wrap their pipe, they may use different piece sizes but a message over the receiver's limit fails
+/// with [`InvalidData`](io::ErrorKind::InvalidData). After any error the pipe is best dropped, the rest of the message is
+/// still in it.
+/// ```no_run
+/// # use windows_named_pipes::*;
+/// # fn main() -> std::io::Result<()> {
+/// let client = DuplexClient::open(std::path::Path::new(r"\\.\pipe\chunked"))?;
+/// let mut config = ChunkedConfig::new();
+/// config.max_message_size(64 * 1024 * 1024);
+/// let mut pipe = Chunked::with_config(client, config)?;
+/// pipe.send(&vec![0u8; 16 * 1024 * 1024])?;
+/// let reply = pipe.recv()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Chunked<P> {
+        pipe: P,
+        config: ChunkedConfig,
+        /// whether the pipe was in message read mode when wrapped
+        message: bool,
+        /// the configured piece size or the pipe's buffer size
+        chunk_size: usize,
+        /// data of the last message not yet taken by `read`
+        buf: Vec<u8>,
+        pos: usize,
+}
+impl<P: Read + Write + AsHandle> Chunked<P> {
+        pub fn new(pipe: P) -> io::Result<Self> { Self::with_config(pipe, ChunkedConfig::default()) }
+        pub fn with_config(pipe: P, config: ChunkedConfig) -> io::Result<Self> {
+                let handle = borrow_handle(&pipe);
+                let message = handle.get_state(false).map_err(io_error)?.read_mode == ReadMode::Message;
+                let chunk_size = match config.chunk_size {
+                        Some(chunk_size) => chunk_size,
+                        None => {
+                                let info = handle.get_info().map_err(io_error)?;
+                                let buffer = match info.end {
+                                        PipeEnd::Server => info.out_size,
+                                        PipeEnd::Client => info.in_size,
+                                };
+                                // a zero sized buffer is left to the system to size
+                                match buffer {
+                                        0 => MAX_TRANSACTION as usize,
+                                        buffer => clamp_chunk_size(buffer as usize),
+                                }
+                        }
+                };
+                Ok(Self {
+                        pipe,
+                        config,
+                        message,
+                        chunk_size,
+                        buf: Vec::new(),
+                        pos: 0,
+                })
+        }
+}
+impl<P> Chunked<P> {
+        /// Largest piece written at once.
+        pub fn chunk_size(&self) -> usize { self.chunk_size }
+        pub fn config(&self) -> &ChunkedConfig { &self.config }
+        pub fn get_ref(&self) -> &P { &self.pipe }
+        pub fn get_mut(&mut self) -> &mut P { &mut self.pipe }
+        pub fn into_inner(self) -> P { self.pipe }
+}
+impl<P: Read + Write> Chunked<P> {
+        /// Send `data` as one message, in as many pieces as it takes.
+        pub fn send(&mut self, data: &[u8]) -> io::Result<()> {
+                if data.len() > self.config.max_message_size {
+                        return Err(io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                format!(
+                                        "message of {} bytes is larger than the limit of {} bytes",
+                                        data.len(),
+                                        self.config.max_message_size
+                                ),
+                        ));
+                }
+                let (first, rest) = data.split_at(data.len().min(self.chunk_size - HEADER_LEN));
+                let mut chunk = Vec::with_capacity(self.chunk_size);
+                chunk.extend_from_slice(&(data.len() as u64).to_be_bytes());
+                chunk.extend_from_slice(first);
+                self.send_chunk(&chunk)?;
+                for chunk in rest.chunks(self.chunk_size) {
+                        self.send_chunk(chunk)?;
+                }
+                Ok(())
+        }
+        /// Receive the next message, once all of its pieces are in.
+        pub fn recv(&mut self) -> io::Result<Vec<u8>> {
+                let (len, mut data) = match self.message {
+                        true => {
+                                let mut first = read_message(&mut self.pipe, MAX_TRANSACTION as usize)?;
+                                if first.len() < HEADER_LEN {
+                                        return Err(io::Error::new(io::ErrorKind::InvalidData, "chunked message without its length"));
+                                }
+                                let data = first.split_off(HEADER_LEN);
+                                (u64::from_be_bytes(first.try_into().unwrap()), data)
+                        }
+                        false => {
+                                let mut header = [0u8; HEADER_LEN];
+                                self.pipe.read_exact(&mut header)?;
+                                (u64::from_be_bytes(header), Vec::new())
+                        }
+                };
+                if len > self.config.max_message_size as u64 {
+                        return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!(
+                                        "message of {} bytes is larger than the limit of {} bytes",
+                                        len, self.config.max_message_size
+                                ),
+                        ));
+                }
+                let len = len as usize;
+                if data.len() > len {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "chunk runs past the end of its message"));
+                }
+                data.reserve_exact(len - data.len());
+                while data.len() < len {
+                        match self.message {
+                                // a piece longer than what is left of the message fails here
+                                true => data.extend_from_slice(&read_message(&mut self.pipe, len - data.len())?),
+                                false => {
+                                        let filled = data.len();
+                                        data.resize(len.min(filled + self.chunk_size), 0);
+                                        self.pipe.read_exact(&mut data[filled..])?;
+                                }
+                        }
+                }
+                if data.len() != len {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "chunk runs past the end of its message"));
+                }
+                Ok(data)
+        }
+        fn send_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+                match self.message {
+                        true => write_message(&mut self.pipe, chunk),
+                        false => self.pipe.write_all(chunk),
+                }
+        }
+}
+impl<P: Read + Write> Read for Chunked<P> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                while self.pos == self.buf.len() {
+                        self.buf = self.recv()?;
+                        self.pos = 0;
+                }
+                let n = buf.len().min(self.buf.len() - self.pos);
+                buf[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+                self.pos += n;
+                Ok(n)
+        }
+}
+impl<P: Read + Write> Write for Chunked<P> {
+        /// Sends at most the max message size of `buf` as one message.
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if buf.is_empty() {
+                        return Ok(0);
+                }
+                let n = buf.len().min(self.config.max_message_size);
+                self.send(&buf[..n])?;
+                Ok(n)
+        }
+        fn flush(&mut self) -> io::Result<()> { self.pipe.flush() }
+}
+impl<P> fmt::Debug for Chunked<P> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("Chunked")
+                        .field("config", &self.config)
+                        .field("message", &self.message)
+                        .field("chunk_size", &self.chunk_size)
+                        .finish_non_exhaustive()
+        }
+}
+
+fn clamp_chunk_size(chunk_size: usize) -> usize { chunk_size.clamp(MIN_CHUNK_SIZE, MAX_TRANSACTION as usize) }
@@ -90,6 +90,7 @@ pub(super) fn _flush<Client: ClientImpl>(cs: &mut Client) -> Result<(), NamedPip
         }
 }
 
+#[cfg(feature = "stdio")]
 #[inline]
 pub(super) fn _set_stdin<Client: ClientImpl>(cs: &Client) -> Result<OldStdinHandle, NamedPipeWinError> {
         unsafe {
@@ -108,6 +109,7 @@ pub(super) fn _set_stdin<Client: ClientImpl>(cs: &Client) -> Result<OldStdinHand
         }
 }
 
+#[cfg(feature = "stdio")]
 #[inline]
 pub(super) fn _set_stdout<Client: ClientImpl>(cs: &Client) -> Result<OldStdoutHandle, NamedPipeWinError> {
         unsafe {
@@ -23,14 +23,33 @@ def_client_struct! {
                 }
                 #[doc = "read from pipe into buffer, returning number of bytes read"]
                 pub fn read(&mut self, buf: &mut [u8]) -> Result<u32, NamedPipeWinError> { _read(self,buf) }
-                #[doc = "Set the named pipe as current process's stdin pipe.<br>Returns old stdin handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur."]
+                #[cfg(feature = "stdio")]
+                #[doc = "Set the named pipe as current process's stdin pipe.<br>Returns old stdin handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur.<br>To give a child process the pipe as stdio, use `try_stdio()` instead."]
                 pub unsafe fn set_stdin(&self) -> Result<OldStdinHandle, NamedPipeWinError> { _set_stdin(self) }
                 #[doc = "write into pipe from buffer, returning number of bytes written"]
                 pub fn write(&mut self, buf: &mut [u8]) -> Result<u32, NamedPipeWinError> { _write(self,buf) }
                 #[doc = "flush the I/O buffers and if wait mode, wait until all messages are received"]
                 pub fn flush(&mut self) -> Result<(), NamedPipeWinError> { _flush(self) }
-                #[doc = "Set the named pipe as current process's stdout pipe.<br>Returns old stdout handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur."]
+                #[cfg(feature = "stdio")]
+                #[doc = "Set the named pipe as current process's stdout pipe.<br>Returns old stdout handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur.<br>To give a child process the pipe as stdio, use `try_stdio()` instead."]
                 pub unsafe fn set_stdout(&self) -> Result<OldStdoutHandle, NamedPipeWinError> { _set_stdout(self) }
+                #[doc = "Borrow the pipe as a read half and a write half that can be used from two threads at once.<br>The halves start out with the pipe's read and write timeouts.<br>Fails with `ERROR_NOT_SUPPORTED` unless the pipe was opened with `overlapped(true)`."]
+                pub fn split(&mut self) -> Result<(ReadHalf<'_>, WriteHalf<'_>), NamedPipeWinError> {
+                        if !self.0.overlapped {
+                                return Err(pipe_err::<Self>(&self.0.path, "split()")(split::not_overlapped()));
+                        }
+                        Ok((ReadHalf::new(&self.0.handle, self.0.read_timeout), WriteHalf::new(&self.0.handle, self.0.write_timeout)))
+                }
+                #[doc = "Split the pipe into an owned read half and write half, see [`split`](Self::split).<br>[`OwnedReadHalf::reunite`] gives the pipe back."]
+                #[allow(clippy::result_large_err)]
+                pub fn into_split(self) -> Result<(OwnedReadHalf<Self>, OwnedWriteHalf<Self>), (Self, NamedPipeWinError)> {
+                        if !self.0.overlapped {
+                                let err = pipe_err::<Self>(&self.0.path, "into_split()")(split::not_overlapped());
+                                return Err((self, err));
+                        }
+                        let (read_timeout, write_timeout) = (self.0.read_timeout, self.0.write_timeout);
+                        Ok(split::into_split(self, read_timeout, write_timeout))
+                }
         }
 }
 
@@ -52,13 +71,15 @@ def_client_struct! {
                 }
                 #[doc = "read from pipe into buffer, returning number of bytes read"]
                 pub fn read(&mut self, buf: &mut [u8]) -> Result<u32, NamedPipeWinError> { _read(self,buf) }
-                #[doc = "Set the named pipe as current process's stdin pipe.<br>Returns old stdin handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur."]
+                #[cfg(feature = "stdio")]
+                #[doc = "Set the named pipe as current process's stdin pipe.<br>Returns old stdin handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur.<br>To give a child process the pipe as stdio, use `try_stdio()` instead."]
                 pub unsafe fn set_stdin(&self) -> Result<OldStdinHandle, NamedPipeWinError> { _set_stdin(self) }
                 #[doc = "write into pipe from buffer, returning number of bytes written"]
                 pub fn write(&mut self, buf: &mut [u8]) -> Result<u32, NamedPipeWinError> { _write(self,buf) }
                 #[doc = "flush the I/O buffers and if wait mode, wait until all messages are received"]
                 pub fn flush(&mut self) -> Result<(), NamedPipeWinError> { _flush(self) }
-                #[doc = "Set the named pipe as current process's stdout pipe.<br>Returns old stdout handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur."]
+                #[cfg(feature = "stdio")]
+                #[doc = "Set the named pipe as current process's stdout pipe.<br>Returns old stdout handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur.<br>To give a child process the pipe as stdio, use `try_stdio()` instead."]
                 pub unsafe fn set_stdout(&self) -> Result<OldStdoutHandle, NamedPipeWinError> { _set_stdout(self) }
         }
 }
@@ -81,13 +102,15 @@ def_client_struct! {
                 }
                 #[doc = "read from pipe into buffer, returning number of bytes read"]
                 pub fn read(&mut self, buf: &mut [u8]) -> Result<u32, NamedPipeWinError> { _read(self,buf) }
-                #[doc = "Set the named pipe as current process's stdin pipe.<br>Returns old stdin handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur."]
+                #[cfg(feature = "stdio")]
+                #[doc = "Set the named pipe as current process's stdin pipe.<br>Returns old stdin handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur.<br>To give a child process the pipe as stdio, use `try_stdio()` instead."]
                 pub unsafe fn set_stdin(&self) -> Result<OldStdinHandle, NamedPipeWinError> { _set_stdin(self) }
                 #[doc = "write into pipe from buffer, returning number of bytes written"]
                 pub fn write(&mut self, buf: &mut [u8]) -> Result<u32, NamedPipeWinError> { _write(self,buf) }
                 #[doc = "flush the I/O buffers and if wait mode, wait until all messages are received"]
                 pub fn flush(&mut self) -> Result<(), NamedPipeWinError> { _flush(self) }
-                #[doc = "Set the named pipe as current process's stdout pipe.<br>Returns old stdout handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur."]
+                #[cfg(feature = "stdio")]
+                #[doc = "Set the named pipe as current process's stdout pipe.<br>Returns old stdout handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur.<br>To give a child process the pipe as stdio, use `try_stdio()` instead."]
                 pub unsafe fn set_stdout(&self) -> Result<OldStdoutHandle, NamedPipeWinError> { _set_stdout(self) }
         }
 }
@@ -5,12 +5,18 @@ pub struct UnsafeClient {
         pub(crate) handle: UnsafeHandle,
         pub(crate) path: PathBuf,
         pub(crate) info: PipeInfo,
+        /// how long a read may block, `None` blocks forever
+        pub(crate) read_timeout: Option<Duration>,
+        /// how long a write or flush may block, `None` blocks forever
+        pub(crate) write_timeout: Option<Duration>,
+        /// opened with `FILE_FLAG_OVERLAPPED`, so its I/O must go through an `OVERLAPPED`
+        pub(crate) overlapped: bool,
 }
 impl AsRawHandle for UnsafeClient {
         fn as_raw_handle(&self) -> RawHandle { self.handle.as_raw_handle() }
 }
 impl IntoRawHandle for UnsafeClient {
-        fn into_raw_handle(self) -> RawHandle { UnsafeHandle::from(self).0 as RawHandle }
+        fn into_raw_handle(self) -> RawHandle { UnsafeHandle::from(self).into_raw_handle() }
 }
 impl AsHandle for UnsafeClient {
         fn as_handle(&self) -> BorrowedHandle { unsafe { BorrowedHandle::borrow_raw(self.handle.as_raw_handle()) } }
@@ -21,7 +27,7 @@ impl From<UnsafeClient> for UnsafeHandle {
                         let mut that = ManuallyDrop::new(that);
                         ::std::ptr::drop_in_place(&mut that.path);
                         ::std::ptr::drop_in_place(&mut that.info);
-                        that.handle
+                        ::std::ptr::read(&that.handle)
                 }
         }
 }
@@ -32,12 +38,12 @@ impl From<UnsafeClient> for fs::File {
         fn from(that: UnsafeClient) -> Self { unsafe { FromRawHandle::from_raw_handle(that.into_raw_handle()) } }
 }
 impl Read for UnsafeClient {
-        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { Read::read(&mut &self.handle, buf) }
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { UnsafeClient::read(self, buf).map(|r| r as _).map_err(io_error) }
 }
 impl Write for UnsafeClient {
         #[inline]
-        fn write(&mut self, buf: &[u8]) -> io::Result<usize> { Write::write(&mut &self.handle, buf) }
-        fn flush(&mut self) -> io::Result<()> { Write::flush(&mut &self.handle) }
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> { UnsafeClient::write(self, buf).map(|w| w as _).map_err(io_error) }
+        fn flush(&mut self) -> io::Result<()> { UnsafeClient::flush(self).map_err(io_error) }
 }
 impl Drop for UnsafeClient {
         fn drop(&mut self) {
@@ -54,15 +60,57 @@ impl UnsafeClient {
         #[inline]
         pub fn as_win_handle(&self) -> HANDLE { self.handle.as_win_handle() }
         #[inline]
-        pub(crate) unsafe fn from_inner(handle: UnsafeHandle, path: PathBuf, info: PipeInfo) -> Self { Self { handle, path, info } }
+        pub(crate) unsafe fn from_inner(handle: UnsafeHandle, path: PathBuf, info: PipeInfo, overlapped: bool) -> Self {
+                Self {
+                        handle,
+                        path,
+                        info,
+                        read_timeout: None,
+                        write_timeout: None,
+                        overlapped,
+                }
+        }
+        #[cfg(feature = "stdio")]
         #[inline]
         pub unsafe fn set_stdout(&self) -> win::core::Result<OldStdoutHandle> { self.handle.set_stdout() }
+        #[cfg(feature = "stdio")]
         #[inline]
         pub unsafe fn set_stdin(&self) -> win::core::Result<OldStdinHandle> { self.handle.set_stdin() }
 
         /// open a client connection and handle to the given named pipe path
         pub unsafe fn open(path: &Path, access: ClientAccessMode, read_mode: ReadMode, token: Option<ImpersonationLevel>) -> win::core::Result<Self> {
-                let handle = UnsafeHandle::open(path, access, token)?;
+                Self::open_timeout(path, access, read_mode, token, None, false)
+        }
+        /// open a client connection, waiting with `WaitNamedPipe` for up to `timeout` while every instance of the pipe is busy.
+        /// <br>`None` fails right away with `ERROR_PIPE_BUSY`, once `timeout` has passed it fails with `ERROR_SEM_TIMEOUT`. With `overlapped` the handle is opened with `FILE_FLAG_OVERLAPPED`.
+        pub unsafe fn open_timeout(
+                path: &Path,
+                access: ClientAccessMode,
+                read_mode: ReadMode,
+                token: Option<ImpersonationLevel>,
+                timeout: Option<Timeout>,
+                overlapped: bool,
+        ) -> win::core::Result<Self> {
+                let deadline = match timeout {
+                        Some(Timeout::Duration(timeout)) => Some(::std::time::Instant::now() + timeout),
+                        _ => None,
+                };
+                let handle = loop {
+                        match (UnsafeHandle::open(path, access, token, overlapped), timeout) {
+                                (Err(err), Some(timeout)) if WIN32_ERROR::from_error(&err) == Some(ERROR_PIPE_BUSY) => {
+                                        // another client may grab the instance between the wait and the open, so try again with what is left
+                                        let timeout = match deadline {
+                                                Some(deadline) => match deadline.checked_duration_since(::std::time::Instant::now()) {
+                                                        Some(left) if !left.is_zero() => Timeout::Duration(left),
+                                                        _ => return Err(win::Win32::Foundation::ERROR_SEM_TIMEOUT.into()),
+                                                },
+                                                None => timeout,
+                                        };
+                                        UnsafeHandle::wait(path, timeout.into())?
+                                }
+                                (res, _) => break res?,
+                        }
+                };
                 if read_mode == ReadMode::Message {
                         if !SetNamedPipeHandleState(handle.as_win_handle(), &read_mode.into(), ::std::ptr::null(), ::std::ptr::null()).as_bool() {
                                 let _ = CloseHandle(handle.as_win_handle());
@@ -80,15 +128,33 @@ impl UnsafeClient {
                         handle,
                         path: path.to_owned(),
                         info,
+                        read_timeout: None,
+                        write_timeout: None,
+                        overlapped,
                 })
         }
         /// close the client connection and handle, safely dropping handle while capturing error
         pub fn close(self) -> Result<(), (Self, WinError)> {
-                if let Err(err) = self.handle.close() {
-                        return Err((self, err));
-                }
-                let _ = UnsafeHandle::from(self); // already closed
-                Ok(())
+                let (path, info, read_timeout, write_timeout, overlapped) = (
+                        self.path.clone(),
+                        self.info.clone(),
+                        self.read_timeout,
+                        self.write_timeout,
+                        self.overlapped,
+                );
+                UnsafeHandle::from(self).close().map_err(|(handle, err)| {
+                        (
+                                Self {
+                                        handle,
+                                        path,
+                                        info,
+                                        read_timeout,
+                                        write_timeout,
+                                        overlapped,
+                                },
+                                err,
+                        )
+                })
         }
 
         /// connect to the named pipe as client, then write to and read from pipe
@@ -125,9 +191,65 @@ impl UnsafeClient {
                 }
         }
         #[inline]
-        pub fn read(&mut self, buf: &mut [u8]) -> win::core::Result<u32> { self.handle.read(buf) }
+        pub fn read(&mut self, buf: &mut [u8]) -> win::core::Result<u32> { self.handle.read_as(buf, self.read_timeout, self.overlapped) }
+        #[inline]
+        pub fn write(&mut self, buf: &[u8]) -> win::core::Result<u32> { self.handle.write_as(buf, self.write_timeout, self.overlapped) }
+        #[inline]
+        pub fn flush(&mut self) -> win::core::Result<()> { with_deadline(self.write_timeout, || self.handle.flush()) }
+        #[inline]
+        pub fn info(&self) -> &PipeInfo { &self.info }
+        /// query `GetNamedPipeInfo` again and update the cached info.
+        pub fn refresh_info(&mut self) -> win::core::Result<&PipeInfo> {
+                self.info = self.handle.get_info()?;
+                Ok(&self.info)
+        }
         #[inline]
-        pub fn write(&mut self, buf: &[u8]) -> win::core::Result<u32> { self.handle.write(buf) }
+        pub fn set_read_mode(&self, read_mode: ReadMode) -> win::core::Result<()> { self.handle.set_read_mode(read_mode) }
+        /// `GetNamedPipeHandleState`, the client user name is always `None` on the client end.
+        pub fn handle_state(&self) -> win::core::Result<HandleState> { self.handle.get_state(false) }
+        /// set how many bytes are buffered before sending to a remote server, fails unless the server is remote.
         #[inline]
-        pub fn flush(&mut self) -> win::core::Result<()> { self.handle.flush() }
+        pub fn set_max_collection_count(&self, max_collection_count: u32) -> win::core::Result<()> {
+                self.handle.set_collection(Some(max_collection_count), None)
+        }
+        /// set how long data is buffered before sending to a remote server, fails unless the server is remote.
+        #[inline]
+        pub fn set_collect_data_timeout(&self, timeout: Duration) -> win::core::Result<()> {
+                self.handle.set_collection(None, Some(Timeout::Duration(timeout).into()))
+        }
+        /// switch the handle between blocking and non-blocking mode.
+        #[inline]
+        pub fn set_nonblocking(&self, nonblocking: bool) -> win::core::Result<()> { self.handle.set_nonblocking(nonblocking) }
+        #[inline]
+        pub fn set_inheritable(&self, inheritable: bool) -> win::core::Result<()> { self.handle.set_inheritable(inheritable) }
+        /// whether the client was opened with `FILE_FLAG_OVERLAPPED`.
+        #[inline]
+        pub fn is_overlapped(&self) -> bool { self.overlapped }
+        /// another client on the same connection, with its own handle and the same timeouts.
+        pub fn try_clone(&self) -> win::core::Result<Self> {
+                Ok(Self {
+                        handle: self.handle.duplicate(false)?,
+                        path: self.path.clone(),
+                        info: self.info.clone(),
+                        read_timeout: self.read_timeout,
+                        write_timeout: self.write_timeout,
+                        overlapped: self.overlapped,
+                })
+        }
+        #[inline]
+        pub fn read_timeout(&self) -> Option<Duration> { self.read_timeout }
+        #[inline]
+        pub fn write_timeout(&self) -> Option<Duration> { self.write_timeout }
+        /// set how long a read may block, `None` blocks forever.
+        pub fn set_read_timeout(&mut self, dur: Option<Duration>) -> io::Result<()> {
+                check_timeout(dur)?;
+                self.read_timeout = dur;
+                Ok(())
+        }
+        /// set how long a write or flush may block, `None` blocks forever.
+        pub fn set_write_timeout(&mut self, dur: Option<Duration>) -> io::Result<()> {
+                check_timeout(dur)?;
+                self.write_timeout = dur;
+                Ok(())
+        }
 }
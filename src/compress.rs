@@ -0,0 +1,188 @@
+//! Per-frame compression for [`FrameCodec`].
+//! With a compression set, every frame starts with a flag byte: 0 for a frame sent as is, otherwise the id of the
+//! algorithm it was compressed with. Frames below the threshold, or that don't get smaller, are sent as is.
+
+use super::*;
+use ::std::borrow::Cow;
+
+/// Frames smaller than this are sent uncompressed unless told otherwise, 256 bytes.
+pub const DEFAULT_COMPRESS_THRESHOLD: usize = 256;
+
+const STORED: u8 = 0;
+
+/// Compression algorithm of a [`FrameCodec`], each behind the cargo feature of its name.
+/// <br>Both ends must agree on using compression, [`Handshake::compressions`] offers every algorithm compiled in and
+/// [`Compression::negotiated`] picks the one both sides have.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Compression {
+        /// `zstd` at its default level, feature `zstd`
+        #[cfg(feature = "zstd")]
+        Zstd,
+        /// LZ4 block format of `lz4_flex`, feature `lz4`
+        #[cfg(feature = "lz4")]
+        Lz4,
+        /// raw deflate of `flate2`, feature `deflate`
+        #[cfg(feature = "deflate")]
+        Deflate,
+}
+impl Compression {
+        /// Every algorithm compiled in, best ratio first.
+        pub const ALL: &'static [Compression] = &[
+                #[cfg(feature = "zstd")]
+                Compression::Zstd,
+                #[cfg(feature = "lz4")]
+                Compression::Lz4,
+                #[cfg(feature = "deflate")]
+                Compression::Deflate,
+        ];
+        /// Capability name in a [`Handshake`].
+        pub fn name(self) -> &'static str {
+                match self {
+                        #[cfg(feature = "zstd")]
+                        Compression::Zstd => "zstd",
+                        #[cfg(feature = "lz4")]
+                        Compression::Lz4 => "lz4",
+                        #[cfg(feature = "deflate")]
+                        Compression::Deflate => "deflate",
+                }
+        }
+        /// The first negotiated capability naming an algorithm, `None` if there is none and frames go uncompressed.
+        pub fn negotiated(negotiated: &Negotiated) -> Option<Self> {
+                negotiated
+                        .capabilities
+                        .iter()
+                        .find_map(|name| Self::ALL.iter().copied().find(|compression| compression.name() == name))
+        }
+        /// flag byte of frames compressed with it
+        fn id(self) -> u8 {
+                match self {
+                        #[cfg(feature = "zstd")]
+                        Compression::Zstd => 1,
+                        #[cfg(feature = "lz4")]
+                        Compression::Lz4 => 2,
+                        #[cfg(feature = "deflate")]
+                        Compression::Deflate => 3,
+                }
+        }
+        fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+                match self {
+                        #[cfg(feature = "zstd")]
+                        Compression::Zstd => zstd::bulk::compress(data, zstd::DEFAULT_COMPRESSION_LEVEL),
+                        #[cfg(feature = "lz4")]
+                        Compression::Lz4 => Ok(lz4_flex::block::compress_prepend_size(data)),
+                        #[cfg(feature = "deflate")]
+                        Compression::Deflate => {
+                                let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                                encoder.write_all(data)?;
+                                encoder.finish()
+                        }
+                }
+        }
+        /// Decompress at most `max` bytes, more fail with [`InvalidData`](io::ErrorKind::InvalidData) before they are allocated.
+        fn decompress(self, data: &[u8], max: usize) -> io::Result<Vec<u8>> {
+                let too_large = || {
+                        io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("decompressed frame is larger than the limit of {} bytes", max),
+                        )
+                };
+                match self {
+                        #[cfg(feature = "zstd")]
+                        Compression::Zstd => match zstd::zstd_safe::get_frame_content_size(data) {
+                                Ok(Some(len)) if len > max as u64 => Err(too_large()),
+                                _ => zstd::bulk::decompress(data, max).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+                        },
+                        #[cfg(feature = "lz4")]
+                        Compression::Lz4 => {
+                                let len = data.get(..4).map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize);
+                                if len.is_some_and(|len| len > max) {
+                                        return Err(too_large());
+                                }
+                                lz4_flex::block::decompress_size_prepended(data).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+                        }
+                        #[cfg(feature = "deflate")]
+                        Compression::Deflate => {
+                                let mut frame = Vec::new();
+                                flate2::read::DeflateDecoder::new(data).take(max as u64 + 1).read_to_end(&mut frame)?;
+                                match frame.len() > max {
+                                        true => Err(too_large()),
+                                        false => Ok(frame),
+                                }
+                        }
+                }
+        }
+}
+
+impl FrameCodec {
+        /// Compress frames with `compression`, `None` sends them as they are and without the flag byte.
+        pub fn compression(&mut self, compression: Option<Compression>) -> &mut Self {
+                self.compression = compression;
+                self
+        }
+        /// Smallest frame worth compressing, defaults to [`DEFAULT_COMPRESS_THRESHOLD`].
+        pub fn compress_threshold(&mut self, compress_threshold: usize) -> &mut Self {
+                self.compress_threshold = compress_threshold;
+                self
+        }
+        /// the flag byte in front of every frame once a compression is set
+        pub(crate) fn flag_len(&self) -> usize { self.compression.is_some() as usize }
+        /// `frame` with its flag byte, compressed if it is at least the threshold long and gets smaller.
+        pub(crate) fn compress_frame<'a>(&self, frame: &'a [u8]) -> io::Result<Cow<'a, [u8]>> {
+                self.check_frame_size(frame.len())?;
+                let compression = match self.compression {
+                        Some(compression) => compression,
+                        None => return Ok(Cow::Borrowed(frame)),
+                };
+                if frame.len() >= self.compress_threshold {
+                        let compressed = compression.compress(frame)?;
+                        if compressed.len() < frame.len() {
+                                let mut flagged = Vec::with_capacity(1 + compressed.len());
+                                flagged.push(compression.id());
+                                flagged.extend_from_slice(&compressed);
+                                return Ok(Cow::Owned(flagged));
+                        }
+                }
+                let mut flagged = Vec::with_capacity(1 + frame.len());
+                flagged.push(STORED);
+                flagged.extend_from_slice(frame);
+                Ok(Cow::Owned(flagged))
+        }
+        /// the frame without its flag byte, decompressed with whichever algorithm the flag names
+        pub(crate) fn decompress_frame(&self, mut frame: Vec<u8>) -> io::Result<Vec<u8>> {
+                if self.compression.is_none() {
+                        return Ok(frame);
+                }
+                match frame.first().copied() {
+                        Some(STORED) if frame.len() - 1 > self.max_frame_size => Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!(
+                                        "received frame of {} bytes is larger than the limit of {} bytes",
+                                        frame.len() - 1,
+                                        self.max_frame_size
+                                ),
+                        )),
+                        Some(STORED) => {
+                                frame.remove(0);
+                                Ok(frame)
+                        }
+                        Some(id) => match Compression::ALL.iter().find(|compression| compression.id() == id) {
+                                Some(compression) => compression.decompress(&frame[1..], self.max_frame_size),
+                                None => Err(io::Error::new(
+                                        io::ErrorKind::InvalidData,
+                                        format!("frame compressed with unknown algorithm {}", id),
+                                )),
+                        },
+                        None => Err(io::Error::new(io::ErrorKind::InvalidData, "compressed frame without its flag")),
+                }
+        }
+}
+
+impl Handshake {
+        /// Offer every compression algorithm compiled in, see [`Compression::negotiated`].
+        pub fn compressions(&mut self) -> &mut Self {
+                for compression in Compression::ALL {
+                        self.capability(compression.name());
+                }
+                self
+        }
+}
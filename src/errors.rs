@@ -5,14 +5,26 @@ use windows::core::Error as WinError;
 pub struct NamedPipeWinError {
         inner: WinError,
         msg: String,
+        /// set when a client refused to talk to the server it opened
+        untrusted: Option<Box<crate::UntrustedServer>>,
 }
 impl std::fmt::Display for NamedPipeWinError {
         #[inline]
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { core::write!(f, "{}: {}", self.msg, self.inner.message()) }
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match &self.untrusted {
+                        Some(untrusted) => core::write!(f, "{}: {}", self.msg, untrusted),
+                        None => core::write!(f, "{}: {}", self.msg, self.inner.message()),
+                }
+        }
 }
 impl std::error::Error for NamedPipeWinError {
         #[inline]
-        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { Some(&self.inner) }
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                match &self.untrusted {
+                        Some(untrusted) => Some(&**untrusted),
+                        None => Some(&self.inner),
+                }
+        }
         #[inline]
         fn cause(&self) -> Option<&dyn std::error::Error> { self.source() }
 }
@@ -22,17 +34,43 @@ impl NamedPipeWinError {
                 NamedPipeWinError {
                         inner: WinError::from_win32(),
                         msg,
+                        untrusted: None,
                 }
         }
         #[inline]
-        pub fn from_inner(inner: WinError, msg: String) -> Self { NamedPipeWinError { inner, msg } }
+        pub fn from_inner(inner: WinError, msg: String) -> Self { NamedPipeWinError { inner, msg, untrusted: None } }
+        /// a client found `server` serving the pipe, the inner error is `ERROR_ACCESS_DENIED`.
+        #[inline]
+        pub fn from_untrusted(server: crate::UntrustedServer, msg: String) -> Self {
+                NamedPipeWinError {
+                        inner: windows::Win32::Foundation::ERROR_ACCESS_DENIED.into(),
+                        msg,
+                        untrusted: Some(Box::new(server)),
+                }
+        }
         #[inline]
         pub fn from_msg(msg: String) -> Self {
                 NamedPipeWinError {
                         inner: unsafe { ::std::mem::zeroed() },
                         msg,
+                        untrusted: None,
                 }
         }
+        /// the server a client refused to talk to, see [`ClientBuilder::expect_server`](crate::builder::ClientBuilder::expect_server).
+        #[inline]
+        pub fn untrusted_server(&self) -> Option<&crate::UntrustedServer> { self.untrusted.as_deref() }
+        /// the lower level windows.rs error
+        #[inline]
+        pub fn inner(&self) -> &WinError { &self.inner }
+        /// the io error kind of the underlying win32 error, e.g. [`TimedOut`](io::ErrorKind::TimedOut) for an elapsed timeout.
+        #[inline]
+        pub fn kind(&self) -> io::ErrorKind { crate::handle::io_error(self.inner.clone()).kind() }
+}
+impl From<NamedPipeWinError> for io::Error {
+        fn from(that: NamedPipeWinError) -> Self {
+                let kind = that.kind();
+                io::Error::new(kind, that)
+        }
 }
 /// simply appends information about the high level lib error to the lower level io error.
 #[derive(Debug)]
@@ -0,0 +1,279 @@
+//! Length-prefixed frames over byte streams.
+//! Byte pipes have no message boundaries and message pipes can't be trusted past [`MAX_TRANSACTION`],
+//! so every frame goes out as its length followed by its bytes.
+
+use super::*;
+
+/// Largest frame a [`FrameCodec`] accepts unless told otherwise, 8 MiB.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
+
+/// Number of bytes the length prefix of a frame takes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PrefixWidth {
+        U8 = 1,
+        U16 = 2,
+        U32 = 4,
+        U64 = 8,
+}
+impl PrefixWidth {
+        #[allow(clippy::len_without_is_empty)]
+        pub fn len(self) -> usize { self as usize }
+        /// largest length the prefix can hold
+        pub fn max(self) -> u64 {
+                match self {
+                        PrefixWidth::U8 => u8::MAX as u64,
+                        PrefixWidth::U16 => u16::MAX as u64,
+                        PrefixWidth::U32 => u32::MAX as u64,
+                        PrefixWidth::U64 => u64::MAX,
+                }
+        }
+}
+
+/// Byte order of the length prefix.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Endian {
+        Big,
+        Little,
+}
+
+/// Frame format: prefix width, prefix byte order and the largest frame either side accepts.
+/// <br>With the `zstd`, `lz4` or `deflate` feature frames may also be compressed, see [`Compression`].
+/// <br>With the `tokio` feature it is also a `tokio_util` [`Decoder`](tokio_util::codec::Decoder) and
+/// [`Encoder`](tokio_util::codec::Encoder).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FrameCodec {
+        pub(crate) prefix_width: PrefixWidth,
+        pub(crate) endian: Endian,
+        pub(crate) max_frame_size: usize,
+        #[cfg(any(feature = "zstd", feature = "lz4", feature = "deflate"))]
+        pub(crate) compression: Option<Compression>,
+        /// frames shorter than this are not compressed
+        #[cfg(any(feature = "zstd", feature = "lz4", feature = "deflate"))]
+        pub(crate) compress_threshold: usize,
+}
+impl FrameCodec {
+        pub fn prefix_width(&mut self, prefix_width: PrefixWidth) -> &mut Self {
+                self.prefix_width = prefix_width;
+                self
+        }
+        pub fn endian(&mut self, endian: Endian) -> &mut Self {
+                self.endian = endian;
+                self
+        }
+        /// Frames longer than this fail to send with [`InvalidInput`](io::ErrorKind::InvalidInput)
+        /// and fail to receive with [`InvalidData`](io::ErrorKind::InvalidData).
+        /// <br>The limit is on the frame as sent and received by the caller, the flag byte of a compressed codec comes on top.
+        pub fn max_frame_size(&mut self, max_frame_size: usize) -> &mut Self {
+                self.max_frame_size = max_frame_size;
+                self
+        }
+        pub fn new() -> Self { Self::default() }
+        /// the largest frame on the wire the prefix can hold and the limit allow, with the flag byte if there is one
+        fn limit(&self) -> usize {
+                (self.prefix_width.max().min(usize::MAX as u64) as usize).min(self.max_frame_size.saturating_add(self.flag_len()))
+        }
+        /// fail with [`InvalidInput`](io::ErrorKind::InvalidInput) on a frame over `max_frame_size`, before it is compressed
+        pub(crate) fn check_frame_size(&self, len: usize) -> io::Result<()> {
+                match len > self.max_frame_size {
+                        true => Err(io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                format!("frame of {} bytes is larger than the limit of {} bytes", len, self.max_frame_size),
+                        )),
+                        false => Ok(()),
+                }
+        }
+        /// the prefix for a frame of `len` bytes, in the first `prefix_width` bytes
+        pub(crate) fn encode_prefix(&self, len: usize) -> io::Result<[u8; 8]> {
+                if len > self.limit() {
+                        return Err(io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                format!("frame of {} bytes is larger than the limit of {} bytes", len, self.limit()),
+                        ));
+                }
+                let mut prefix = [0u8; 8];
+                let width = self.prefix_width.len();
+                match self.endian {
+                        Endian::Big => prefix[..width].copy_from_slice(&(len as u64).to_be_bytes()[8 - width..]),
+                        Endian::Little => prefix[..width].copy_from_slice(&(len as u64).to_le_bytes()[..width]),
+                }
+                Ok(prefix)
+        }
+        /// the frame length in a prefix of `prefix_width` bytes
+        pub(crate) fn decode_prefix(&self, prefix: &[u8]) -> io::Result<usize> {
+                let mut bytes = [0u8; 8];
+                let width = self.prefix_width.len();
+                let len = match self.endian {
+                        Endian::Big => {
+                                bytes[8 - width..].copy_from_slice(&prefix[..width]);
+                                u64::from_be_bytes(bytes)
+                        }
+                        Endian::Little => {
+                                bytes[..width].copy_from_slice(&prefix[..width]);
+                                u64::from_le_bytes(bytes)
+                        }
+                };
+                if len > self.limit() as u64 {
+                        return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("received frame of {} bytes is larger than the limit of {} bytes", len, self.limit()),
+                        ));
+                }
+                Ok(len as usize)
+        }
+}
+impl Default for FrameCodec {
+        fn default() -> Self {
+                FrameCodec {
+                        prefix_width: PrefixWidth::U32,
+                        endian: Endian::Big,
+                        max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+                        #[cfg(any(feature = "zstd", feature = "lz4", feature = "deflate"))]
+                        compression: None,
+                        #[cfg(any(feature = "zstd", feature = "lz4", feature = "deflate"))]
+                        compress_threshold: DEFAULT_COMPRESS_THRESHOLD,
+                }
+        }
+}
+/// without a compression feature frames always go out as they are
+#[cfg(not(any(feature = "zstd", feature = "lz4", feature = "deflate")))]
+impl FrameCodec {
+        pub(crate) fn flag_len(&self) -> usize { 0 }
+        pub(crate) fn compress_frame<'a>(&self, frame: &'a [u8]) -> io::Result<::std::borrow::Cow<'a, [u8]>> {
+                self.check_frame_size(frame.len())?;
+                Ok(frame.into())
+        }
+        pub(crate) fn decompress_frame(&self, frame: Vec<u8>) -> io::Result<Vec<u8>> { Ok(frame) }
+}
+
+/// Sends and receives length-prefixed frames over a pipe, or any other reader or writer.
+/// <br>Use byte read mode on message pipes, a prefix read from a longer message fails with `ERROR_MORE_DATA`.
+/// After an error the stream may be in the middle of a frame, so it is best dropped.
+#[derive(Debug)]
+pub struct Framed<T> {
+        inner: T,
+        codec: FrameCodec,
+        /// prefix and frame of the last send, so both go out in one write
+        buf: Vec<u8>,
+}
+impl<T> Framed<T> {
+        /// Frames with the [`FrameCodec`] defaults: a 4 byte big endian prefix and at most [`DEFAULT_MAX_FRAME_SIZE`] bytes.
+        pub fn new(inner: T) -> Self { Self::with_codec(inner, FrameCodec::default()) }
+        pub fn with_codec(inner: T, codec: FrameCodec) -> Self {
+                Self {
+                        inner,
+                        codec,
+                        buf: Vec::new(),
+                }
+        }
+        pub fn codec(&self) -> &FrameCodec { &self.codec }
+        pub fn codec_mut(&mut self) -> &mut FrameCodec { &mut self.codec }
+        pub fn get_ref(&self) -> &T { &self.inner }
+        pub fn get_mut(&mut self) -> &mut T { &mut self.inner }
+        pub fn into_inner(self) -> T { self.inner }
+}
+impl<T: Write> Framed<T> {
+        /// Write `frame` with its length prefix. Nothing is written if the frame is too large.
+        pub fn send_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+                let frame = self.codec.compress_frame(frame)?;
+                let prefix = self.codec.encode_prefix(frame.len())?;
+                self.buf.clear();
+                self.buf.extend_from_slice(&prefix[..self.codec.prefix_width.len()]);
+                self.buf.extend_from_slice(&frame);
+                self.inner.write_all(&self.buf)
+        }
+}
+impl<T: Read> Framed<T> {
+        /// Read the next frame, blocking until all of it has arrived.
+        /// <br>A stream ending between frames fails with [`UnexpectedEof`](io::ErrorKind::UnexpectedEof), like one ending inside a frame.
+        pub fn recv_frame(&mut self) -> io::Result<Vec<u8>> {
+                let mut prefix = [0u8; 8];
+                let width = self.codec.prefix_width.len();
+                self.inner.read_exact(&mut prefix[..width])?;
+                // checked against the limit before allocating
+                let mut frame = vec![0u8; self.codec.decode_prefix(&prefix[..width])?];
+                self.inner.read_exact(&mut frame)?;
+                self.codec.decompress_frame(frame)
+        }
+}
+
+/// Write `message` as one pipe message, a message pipe never splits a single write.
+pub(crate) fn write_message<W: Write>(writer: &mut W, message: &[u8]) -> io::Result<()> {
+        match writer.write(message)? {
+                written if written == message.len() => Ok(()),
+                _ => Err(io::ErrorKind::WriteZero.into()),
+        }
+}
+/// Read one whole message from a pipe in message read mode, growing the buffer while the read fails with `ERROR_MORE_DATA`.
+/// <br>A message longer than `max` fails with [`InvalidData`](io::ErrorKind::InvalidData), its rest stays in the pipe.
+pub(crate) fn read_message<R: Read>(reader: &mut R, max: usize) -> io::Result<Vec<u8>> {
+        let mut message = Vec::new();
+        let mut len = 0;
+        let too_large = || {
+                io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("received message is larger than the limit of {} bytes", max),
+                )
+        };
+        loop {
+                if len > max {
+                        return Err(too_large());
+                }
+                // one byte past the limit tells a message of exactly `max` bytes from a longer one
+                message.resize((len * 2).max(DEFAULT_BUFSIZE as usize).min(max.saturating_add(1)), 0);
+                match reader.read(&mut message[len..]) {
+                        Ok(read) if len + read > max => return Err(too_large()),
+                        Ok(read) => {
+                                message.truncate(len + read);
+                                return Ok(message);
+                        }
+                        // the read filled the buffer and more of the message is left
+                        Err(err) if err.raw_os_error() == Some(win::Win32::Foundation::ERROR_MORE_DATA.0 as i32) => len = message.len(),
+                        Err(err) => return Err(err),
+                }
+        }
+}
+
+#[cfg(feature = "tokio")]
+mod codec {
+        use super::*;
+        use bytes::{Buf, BufMut, Bytes, BytesMut};
+        use tokio_util::codec::{Decoder, Encoder};
+
+        impl Decoder for FrameCodec {
+                type Item = BytesMut;
+                type Error = io::Error;
+                fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+                        let width = self.prefix_width.len();
+                        if src.len() < width {
+                                return Ok(None);
+                        }
+                        let len = self.decode_prefix(&src[..width])?;
+                        if src.len() < width + len {
+                                src.reserve(width + len - src.len());
+                                return Ok(None);
+                        }
+                        src.advance(width);
+                        let frame = src.split_to(len);
+                        #[cfg(any(feature = "zstd", feature = "lz4", feature = "deflate"))]
+                        if self.compression.is_some() {
+                                return Ok(Some(BytesMut::from(&self.decompress_frame(frame.to_vec())?[..])));
+                        }
+                        Ok(Some(frame))
+                }
+        }
+        impl Encoder<&[u8]> for FrameCodec {
+                type Error = io::Error;
+                fn encode(&mut self, frame: &[u8], dst: &mut BytesMut) -> io::Result<()> {
+                        let frame = self.compress_frame(frame)?;
+                        let prefix = self.encode_prefix(frame.len())?;
+                        dst.reserve(self.prefix_width.len() + frame.len());
+                        dst.put_slice(&prefix[..self.prefix_width.len()]);
+                        dst.put_slice(&frame);
+                        Ok(())
+                }
+        }
+        impl Encoder<Bytes> for FrameCodec {
+                type Error = io::Error;
+                fn encode(&mut self, frame: Bytes, dst: &mut BytesMut) -> io::Result<()> { self.encode(&frame[..], dst) }
+        }
+}
@@ -21,3 +21,53 @@ pub(crate) fn cvt_path(path: &Path) -> Result<PCSTR, WinError> {
         path.push('\0');
         Ok(PCSTR(path.as_ptr()))
 }
+
+/// wrap a windows.rs error with the pipe type, path and failed call, like `DuplexClient {path: "\\.\pipe\name",..} read() failed with`.
+pub(crate) fn pipe_err<'a, T>(path: &'a Path, call: &'static str) -> impl FnOnce(WinError) -> NamedPipeWinError + 'a {
+        move |error| {
+                NamedPipeWinError::from_inner(
+                        error,
+                        format!(
+                                r#"{} {{path: "{}",..}} {} failed with"#,
+                                ::std::any::type_name::<T>(),
+                                path.display(),
+                                call
+                        ),
+                )
+        }
+}
+
+/// a fresh path under [`LOCAL_PREFIX`] for pipes the crate creates for itself, open it with `create_new(true)` so nobody can squat it.
+pub(crate) fn unique_path() -> PathBuf {
+        use ::std::{
+                collections::hash_map::RandomState,
+                hash::{BuildHasher, Hasher},
+                sync::atomic::{AtomicU64, Ordering},
+        };
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(count);
+        PathBuf::from(format!(
+                r"{}windows_named_pipes_{}_{}_{:016x}",
+                LOCAL_PREFIX,
+                ::std::process::id(),
+                count,
+                hasher.finish()
+        ))
+}
+
+/// The pipe's handle without taking ownership, it stays open as long as `pipe`.
+pub(crate) fn borrow_handle<T: AsHandle>(pipe: &T) -> ManuallyDrop<UnsafeHandle> {
+        ManuallyDrop::new(unsafe { UnsafeHandle::from_raw_handle(pipe.as_handle().as_raw_handle()) })
+}
+
+/// Cancel all I/O on the pipe until `done`, for threads blocked in a read or write that may also be between calls.
+pub(crate) fn cancel_io_until(pipe: isize, mut done: impl FnMut() -> bool) {
+        while !done() {
+                unsafe {
+                        let _ = CancelIoEx(HANDLE(pipe), ::core::ptr::null());
+                }
+                thread::sleep(Duration::from_millis(1));
+        }
+}
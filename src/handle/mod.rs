@@ -2,9 +2,11 @@ use super::*;
 mod tests;
 
 /// Raw Named Pipe handle for Windows.
-#[derive(Debug, Clone, Copy)]
+/// <br>Not `Copy` or `Clone`, so only one value owns the handle, but closing is still left to its owner.
+/// Safe code only gets one by taking ownership of a handle, like an [`OwnedHandle`] or [`fs::File`], so it can't close a handle twice.
+#[derive(Debug)]
 #[repr(transparent)]
-pub struct UnsafeHandle(pub isize);
+pub struct UnsafeHandle(isize);
 impl AsRawHandle for UnsafeHandle {
         fn as_raw_handle(&self) -> RawHandle { self.0 as RawHandle }
 }
@@ -26,9 +28,6 @@ impl From<fs::File> for UnsafeHandle {
 impl From<UnsafeHandle> for fs::File {
         fn from(that: UnsafeHandle) -> Self { unsafe { FromRawHandle::from_raw_handle(that.0 as RawHandle) } }
 }
-impl From<HANDLE> for UnsafeHandle {
-        fn from(h: HANDLE) -> Self { Self(h.0) }
-}
 impl From<UnsafeHandle> for HANDLE {
         fn from(h: UnsafeHandle) -> Self { HANDLE(h.0) }
 }
@@ -43,53 +42,104 @@ impl UnsafeHandle {
                 let fi = ::std::mem::ManuallyDrop::new(unsafe { fs::File::from_raw_handle(self.as_raw_handle()) });
                 f(&fi)
         }
-        pub fn read(&self, buf: &mut [u8]) -> ::windows::core::Result<u32> {
-                let lpnumberofbytesread = &mut 0u32;
+        pub fn read(&self, buf: &mut [u8]) -> ::windows::core::Result<u32> { self.read_timeout(buf, None) }
+        /// read from a synchronous handle, cancelling the call once `timeout` elapses. A cancelled call fails with `ERROR_TIMEOUT`.
+        pub fn read_timeout(&self, buf: &mut [u8], timeout: Option<Duration>) -> ::windows::core::Result<u32> { self.read_as(buf, timeout, false) }
+        /// [`read_timeout`](Self::read_timeout) for handles opened with `FILE_FLAG_OVERLAPPED`.
+        pub fn read_overlapped(&self, buf: &mut [u8], timeout: Option<Duration>) -> ::windows::core::Result<u32> { self.read_as(buf, timeout, true) }
+        /// read the way the handle was opened, `overlapped` must match `FILE_FLAG_OVERLAPPED`.
+        pub(crate) fn read_as(&self, buf: &mut [u8], timeout: Option<Duration>, overlapped: bool) -> ::windows::core::Result<u32> {
                 let nnumberofbytestoread = ::core::cmp::min(buf.len(), u32::MAX as usize) as u32;
-                if unsafe {
-                        ReadFile(
-                                self.as_win_handle(),
-                                buf.as_mut_ptr().cast(),
-                                nnumberofbytestoread,
-                                lpnumberofbytesread,
-                                ::core::ptr::null_mut(),
-                        )
-                }
-                .as_bool()
-                {
-                        Ok((*lpnumberofbytesread) as _)
-                } else {
-                        Err(WinError::from_win32())
+                match self.io(overlapped, timeout, |read, ov| unsafe {
+                        ReadFile(self.as_win_handle(), buf.as_mut_ptr().cast(), nnumberofbytestoread, read, ov)
+                }) {
+                        // non-blocking pipes fail reads with ERROR_NO_DATA while empty
+                        Err(err) if WIN32_ERROR::from_error(&err) == Some(ERROR_NO_DATA) && self.is_nonblocking() => Err(WSAEWOULDBLOCK.into()),
+                        res => res,
                 }
         }
         #[inline]
         pub fn is_read_vectored(&self) -> bool { false }
-        pub fn write(&self, buf: &[u8]) -> ::windows::core::Result<u32> {
+        pub fn write(&self, buf: &[u8]) -> ::windows::core::Result<u32> { self.write_timeout(buf, None) }
+        /// write to a synchronous handle, cancelling the call once `timeout` elapses. A cancelled call fails with `ERROR_TIMEOUT`.
+        pub fn write_timeout(&self, buf: &[u8], timeout: Option<Duration>) -> ::windows::core::Result<u32> { self.write_as(buf, timeout, false) }
+        /// [`write_timeout`](Self::write_timeout) for handles opened with `FILE_FLAG_OVERLAPPED`.
+        pub fn write_overlapped(&self, buf: &[u8], timeout: Option<Duration>) -> ::windows::core::Result<u32> { self.write_as(buf, timeout, true) }
+        /// write the way the handle was opened, `overlapped` must match `FILE_FLAG_OVERLAPPED`.
+        pub(crate) fn write_as(&self, buf: &[u8], timeout: Option<Duration>, overlapped: bool) -> ::windows::core::Result<u32> {
                 if buf.len() > u32::MAX as usize {
                         return Err(WinError::new(
                                 ::windows::core::HRESULT(-1),
                                 ::windows::w!("write buffer length larger than u32").to_owned(),
                         ));
                 }
-                let lpnumberofbyteswritten = &mut 0u32;
-                // let nnumberofbytestowrite = ::core::cmp::min(buf.len(), u32::MAX as usize) as u32;
-                if unsafe {
-                        WriteFile(
-                                self.as_win_handle(),
-                                buf.as_ptr().cast(),
-                                buf.len() as _,
-                                lpnumberofbyteswritten,
-                                ::core::ptr::null_mut(),
-                        )
+                let written = self.io(overlapped, timeout, |written, ov| unsafe {
+                        WriteFile(self.as_win_handle(), buf.as_ptr().cast(), buf.len() as _, written, ov)
+                })?;
+                // non-blocking pipes accept nothing while the buffer is full
+                if written == 0 && !buf.is_empty() && self.is_nonblocking() {
+                        return Err(WSAEWOULDBLOCK.into());
                 }
-                .as_bool()
-                {
-                        Ok((*lpnumberofbyteswritten) as _)
-                } else {
-                        Err(WinError::from_win32())
+                Ok(written)
+        }
+        /// Run `f` with a byte count and an `OVERLAPPED` for the handle's kind of I/O, returning the bytes transferred.
+        /// <br>Synchronous handles get a null `OVERLAPPED` and are cancelled with [`with_deadline`], overlapped handles wait on an event.
+        fn io<F: FnOnce(*mut u32, *mut OVERLAPPED) -> BOOL>(
+                &self,
+                overlapped: bool,
+                timeout: Option<Duration>,
+                f: F,
+        ) -> ::windows::core::Result<u32> {
+                if overlapped {
+                        return self.overlapped(timeout, |ov| f(::core::ptr::null_mut(), ov));
+                }
+                with_deadline(timeout, || {
+                        let mut transferred = 0u32;
+                        match f(&mut transferred, ::core::ptr::null_mut()).as_bool() {
+                                true => Ok(transferred),
+                                false => Err(WinError::from_win32()),
+                        }
+                })
+        }
+        /// Start an overlapped call and wait for it to finish, cancelling it with `CancelIoEx` once `timeout` elapses.
+        /// <br>Only for handles opened with `FILE_FLAG_OVERLAPPED`, where a read blocked on one thread does not hold up a write on another.
+        fn overlapped<F: FnOnce(*mut OVERLAPPED) -> BOOL>(&self, timeout: Option<Duration>, f: F) -> ::windows::core::Result<u32> {
+                unsafe {
+                        let event = CreateEventW(::core::ptr::null(), true, false, PCWSTR::null())?;
+                        let mut overlapped = OVERLAPPED {
+                                hEvent: event,
+                                ..Default::default()
+                        };
+                        if !f(&mut overlapped).as_bool() {
+                                let err = WinError::from_win32();
+                                if WIN32_ERROR::from_error(&err) != Some(ERROR_IO_PENDING) {
+                                        let _ = CloseHandle(event);
+                                        return Err(err);
+                                }
+                        }
+                        let mut cancelled = false;
+                        if let Some(timeout) = timeout {
+                                let millis = timeout.as_millis().clamp(1, u32::MAX as u128 - 1) as u32;
+                                if WaitForSingleObject(event, millis) == WAIT_TIMEOUT.0 {
+                                        cancelled = CancelIoEx(self.as_win_handle(), &overlapped).as_bool();
+                                }
+                        }
+                        // always wait, the kernel owns `overlapped` until the call is finished
+                        let mut transferred = 0u32;
+                        let res = if GetOverlappedResult(self.as_win_handle(), &overlapped, &mut transferred, true).as_bool() {
+                                Ok(transferred)
+                        } else {
+                                match WinError::from_win32() {
+                                        err if cancelled && WIN32_ERROR::from_error(&err) == Some(ERROR_OPERATION_ABORTED) => {
+                                                Err(ERROR_TIMEOUT.into())
+                                        }
+                                        err => Err(err),
+                                }
+                        };
+                        let _ = CloseHandle(event);
+                        res
                 }
         }
-        // NOT IMPLEMENTED: Overlapped read/write (async)
         #[inline]
         pub fn is_write_vectored(&self) -> bool { false }
         // REVIEW: Does WriteFile guarantee a flush to pipe or should we make it so it does?
@@ -100,6 +150,7 @@ impl UnsafeHandle {
                         Err(WinError::from_win32())
                 }
         }
+        #[cfg(feature = "stdio")]
         /// Set pipe as stdout. Returns previous stdout handle if successful.
         pub fn set_stdout(&self) -> ::windows::core::Result<OldStdoutHandle> {
                 use windows::Win32::System::Console::{SetStdHandle, STD_OUTPUT_HANDLE};
@@ -110,6 +161,7 @@ impl UnsafeHandle {
                         Err(WinError::from_win32())
                 }
         }
+        #[cfg(feature = "stdio")]
         /// Set pipe as stdin. Returns previous stdin handle if successful.
         pub fn set_stdin(&self) -> ::windows::core::Result<OldStdinHandle> {
                 use windows::Win32::System::Console::{SetStdHandle, STD_INPUT_HANDLE};
@@ -132,23 +184,40 @@ impl UnsafeHandle {
         ) -> ::windows::core::Result<UnsafeHandle> {
                 unsafe {
                         let path = cvt_path(path)?;
-                        Ok(UnsafeHandle::from(CreateNamedPipeA(
-                                path,
-                                dwopenmode,
-                                dwpipemode,
-                                nmaxinstances,
-                                noutbuffersize,
-                                ninbuffersize,
-                                ndefaulttimeout,
-                                &lpsecurityattributes,
-                        )?))
-                }
-        }
-        pub fn connect(&self) -> ::windows::core::Result<()> {
-                if unsafe { ConnectNamedPipe(self.as_win_handle(), ::core::ptr::null_mut()).as_bool() } {
-                        Ok(())
-                } else {
-                        Err(WinError::from_win32())
+                        Ok(UnsafeHandle(
+                                CreateNamedPipeA(
+                                        path,
+                                        dwopenmode,
+                                        dwpipemode,
+                                        nmaxinstances,
+                                        noutbuffersize,
+                                        ninbuffersize,
+                                        ndefaulttimeout,
+                                        &lpsecurityattributes,
+                                )?
+                                .0,
+                        ))
+                }
+        }
+        /// wait for a client to connect, a client that connected before the call (`ERROR_PIPE_CONNECTED`) counts as connected.
+        /// <br>In non-blocking mode returns right away, failing with `ERROR_PIPE_LISTENING` until a client has connected.
+        pub fn connect(&self) -> ::windows::core::Result<()> { self.connect_timeout(None) }
+        /// connect a synchronous handle, cancelling the call once `timeout` elapses. A cancelled call fails with `ERROR_TIMEOUT`.
+        pub fn connect_timeout(&self, timeout: Option<Duration>) -> ::windows::core::Result<()> { self.connect_as(timeout, false) }
+        /// connect the way the handle was created, `overlapped` must match `FILE_FLAG_OVERLAPPED`.
+        pub(crate) fn connect_as(&self, timeout: Option<Duration>, overlapped: bool) -> ::windows::core::Result<()> {
+                let mut listening = false;
+                let res = self.io(overlapped, timeout, |_, ov| {
+                        let res = unsafe { ConnectNamedPipe(self.as_win_handle(), ov) };
+                        listening = res.as_bool();
+                        res
+                });
+                match res {
+                        // non-blocking pipes succeed here only to say the instance is ready for a client
+                        Ok(_) if listening && self.is_nonblocking() => Err(ERROR_PIPE_LISTENING.into()),
+                        Ok(_) => Ok(()),
+                        Err(err) if WIN32_ERROR::from_error(&err) == Some(ERROR_PIPE_CONNECTED) => Ok(()),
+                        Err(err) => Err(err),
                 }
         }
         pub fn disconnect(&self) -> ::windows::core::Result<()> {
@@ -158,44 +227,215 @@ impl UnsafeHandle {
                         Err(WinError::from_win32())
                 }
         }
-        pub fn open(path: &Path, access: ClientAccessMode, token: Option<ImpersonationLevel>) -> ::windows::core::Result<UnsafeHandle> {
+        /// open the client end of a pipe, with `FILE_FLAG_OVERLAPPED` if `overlapped`.
+        pub fn open(
+                path: &Path,
+                access: ClientAccessMode,
+                token: Option<ImpersonationLevel>,
+                overlapped: bool,
+        ) -> ::windows::core::Result<UnsafeHandle> {
                 unsafe {
                         let lpfilename = cvt_path(path)?;
-                        Ok(UnsafeHandle::from(CreateFileA(
-                                lpfilename,
-                                match access {
-                                        ClientAccessMode::Duplex => FILE_GENERIC_READ | FILE_GENERIC_WRITE,
-                                        ClientAccessMode::Outbound => FILE_GENERIC_READ,
-                                        ClientAccessMode::Inbound => FILE_GENERIC_WRITE,
-                                },
-                                FILE_SHARE_NONE,
-                                &SECURITY_ATTRIBUTES::default(),
-                                OPEN_EXISTING,
-                                if let Some(t) = token { t.into() } else { FILE_FLAGS_AND_ATTRIBUTES(0) },
-                                HANDLE(0),
-                        )?))
-                }
-        }
-        pub fn close(self) -> ::windows::core::Result<()> {
+                        Ok(UnsafeHandle(
+                                CreateFileA(
+                                        lpfilename,
+                                        match access {
+                                                ClientAccessMode::Duplex => FILE_GENERIC_READ | FILE_GENERIC_WRITE,
+                                                ClientAccessMode::Outbound => FILE_GENERIC_READ,
+                                                ClientAccessMode::Inbound => FILE_GENERIC_WRITE,
+                                        },
+                                        FILE_SHARE_NONE,
+                                        &SECURITY_ATTRIBUTES::default(),
+                                        OPEN_EXISTING,
+                                        if overlapped {
+                                                FILE_FLAG_OVERLAPPED
+                                        } else {
+                                                FILE_FLAGS_AND_ATTRIBUTES(0)
+                                        } | if let Some(t) = token { t.into() } else { FILE_FLAGS_AND_ATTRIBUTES(0) },
+                                        HANDLE(0),
+                                )?
+                                .0,
+                        ))
+                }
+        }
+        /// wait until an instance of the named pipe is available for a client to open, or the timeout elapses.
+        pub fn wait(path: &Path, timeout: u32) -> ::windows::core::Result<()> {
+                unsafe {
+                        let lpnamedpipename = cvt_path(path)?;
+                        if WaitNamedPipeA(lpnamedpipename, timeout).as_bool() {
+                                Ok(())
+                        } else {
+                                Err(WinError::from_win32())
+                        }
+                }
+        }
+        /// close the handle, giving it back on failure.
+        pub fn close(self) -> Result<(), (Self, WinError)> {
                 if unsafe { CloseHandle(self.as_win_handle()).as_bool() } {
                         Ok(())
+                } else {
+                        Err((self, WinError::from_win32()))
+                }
+        }
+        /// duplicate the handle with the same access, `inheritable` decides if child processes get a copy of the new handle.
+        pub fn duplicate(&self, inheritable: bool) -> ::windows::core::Result<UnsafeHandle> {
+                let mut handle = HANDLE::default();
+                if unsafe {
+                        DuplicateHandle(
+                                GetCurrentProcess(),
+                                self.as_win_handle(),
+                                GetCurrentProcess(),
+                                &mut handle,
+                                0,
+                                inheritable,
+                                DUPLICATE_SAME_ACCESS,
+                        )
+                }
+                .as_bool()
+                {
+                        Ok(UnsafeHandle(handle.0))
                 } else {
                         Err(WinError::from_win32())
                 }
         }
-        pub fn get_info(&self) -> ::windows::core::Result<PipeInfo> {
-                unsafe {
-                        let mut info = PipeInfo::default();
-                        if GetNamedPipeInfo(
+        /// whether child processes created with handle inheritance get a copy of the handle, `HANDLE_FLAG_INHERIT`
+        pub fn is_inheritable(&self) -> ::windows::core::Result<bool> {
+                let mut flags = 0u32;
+                if unsafe { GetHandleInformation(self.as_win_handle(), &mut flags) }.as_bool() {
+                        Ok(flags & HANDLE_FLAG_INHERIT.0 != 0)
+                } else {
+                        Err(WinError::from_win32())
+                }
+        }
+        pub fn set_inheritable(&self, inheritable: bool) -> ::windows::core::Result<()> {
+                let flags = if inheritable { HANDLE_FLAG_INHERIT } else { HANDLE_FLAGS(0) };
+                if unsafe { SetHandleInformation(self.as_win_handle(), HANDLE_FLAG_INHERIT.0, flags) }.as_bool() {
+                        Ok(())
+                } else {
+                        Err(WinError::from_win32())
+                }
+        }
+        /// current read mode and wait mode of the handle, `GetNamedPipeHandleState::lpState`
+        pub fn get_mode(&self) -> ::windows::core::Result<NAMED_PIPE_MODE> {
+                let mut mode = NAMED_PIPE_MODE::default();
+                if unsafe {
+                        GetNamedPipeHandleStateA(
+                                self.as_win_handle(),
+                                &mut mode,
+                                ::core::ptr::null_mut(),
+                                ::core::ptr::null_mut(),
+                                ::core::ptr::null_mut(),
+                                &mut [],
+                        )
+                }
+                .as_bool()
+                {
+                        Ok(mode)
+                } else {
+                        Err(WinError::from_win32())
+                }
+        }
+        /// set read mode and wait mode of the handle, `SetNamedPipeHandleState::lpMode`
+        pub fn set_mode(&self, mode: NAMED_PIPE_MODE) -> ::windows::core::Result<()> {
+                if unsafe { SetNamedPipeHandleState(self.as_win_handle(), &mode, ::core::ptr::null(), ::core::ptr::null()) }.as_bool() {
+                        Ok(())
+                } else {
+                        Err(WinError::from_win32())
+                }
+        }
+        /// switch between byte and message read mode, keeping the wait mode.
+        pub fn set_read_mode(&self, read_mode: ReadMode) -> ::windows::core::Result<()> {
+                self.set_mode(read_mode.into() | WaitMode::from(self.get_mode()?).into())
+        }
+        /// set how many bytes (`lpMaxCollectionCount`) or milliseconds (`lpCollectDataTimeout`) a client buffers before sending to a remote server.
+        /// <br>Only the client end of a pipe to another machine buffers, so server ends fail here with `ERROR_INVALID_PARAMETER`
+        /// and local clients fail with the same error from `SetNamedPipeHandleState`.
+        pub fn set_collection(&self, max_collection_count: Option<u32>, collect_data_timeout: Option<u32>) -> ::windows::core::Result<()> {
+                if self.get_info()?.end == PipeEnd::Server {
+                        return Err(WinError::new(
+                                win::Win32::Foundation::ERROR_INVALID_PARAMETER.to_hresult(),
+                                ::windows::w!("collection settings only apply to the client end of a pipe").to_owned(),
+                        ));
+                }
+                let count = max_collection_count.as_ref().map_or(::core::ptr::null(), |count| count as *const u32);
+                let timeout = collect_data_timeout.as_ref().map_or(::core::ptr::null(), |timeout| timeout as *const u32);
+                if unsafe { SetNamedPipeHandleState(self.as_win_handle(), ::core::ptr::null(), count, timeout) }.as_bool() {
+                        Ok(())
+                } else {
+                        Err(WinError::from_win32())
+                }
+        }
+        /// `GetNamedPipeHandleState`, the client user name is only available to the server end of a connected pipe.
+        pub fn get_state(&self, user_name: bool) -> ::windows::core::Result<HandleState> {
+                let mut mode = NAMED_PIPE_MODE::default();
+                let mut instances = 0u32;
+                let mut name = [0u8; 256]; // UNLEN + 1
+                let name_buf: &mut [u8] = if user_name { &mut name } else { &mut [] };
+                if !unsafe {
+                        GetNamedPipeHandleStateA(
+                                self.as_win_handle(),
+                                &mut mode,
+                                &mut instances,
+                                ::core::ptr::null_mut(),
+                                ::core::ptr::null_mut(),
+                                name_buf,
+                        )
+                }
+                .as_bool()
+                {
+                        return Err(WinError::from_win32());
+                }
+                let client_user_name = if user_name {
+                        let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+                        Some(String::from_utf8_lossy(&name[..len]).into_owned())
+                } else {
+                        None
+                };
+                Ok(HandleState {
+                        instances,
+                        read_mode: ReadMode::from(mode),
+                        wait_mode: WaitMode::from(mode),
+                        client_user_name,
+                })
+        }
+        pub fn is_nonblocking(&self) -> bool { self.get_mode().is_ok_and(|mode| WaitMode::from(mode) == WaitMode::NoWait) }
+        /// switch between `PIPE_WAIT` and `PIPE_NOWAIT`, keeping the read mode.
+        pub fn set_nonblocking(&self, nonblocking: bool) -> ::windows::core::Result<()> {
+                let wait_mode = if nonblocking { WaitMode::NoWait } else { WaitMode::Wait };
+                self.set_mode(ReadMode::from(self.get_mode()?).into() | wait_mode.into())
+        }
+        /// number of bytes that can be read without blocking, `PeekNamedPipe::lpTotalBytesAvail`
+        pub fn available(&self) -> ::windows::core::Result<u32> {
+                let mut available = 0u32;
+                if unsafe {
+                        PeekNamedPipe(
                                 self.as_win_handle(),
-                                &mut info.flags.0,
-                                &mut info.out_size,
-                                &mut info.in_size,
-                                &mut info.max_instances,
+                                ::core::ptr::null_mut(),
+                                0,
+                                ::core::ptr::null_mut(),
+                                &mut available,
+                                ::core::ptr::null_mut(),
                         )
-                        .as_bool()
-                        {
-                                Ok(info)
+                }
+                .as_bool()
+                {
+                        Ok(available)
+                } else {
+                        Err(WinError::from_win32())
+                }
+        }
+        pub fn get_info(&self) -> ::windows::core::Result<PipeInfo> {
+                unsafe {
+                        let mut flags = PipeInfoFlags::default();
+                        let (mut out_size, mut in_size, mut max_instances) = (0u32, 0u32, 0u32);
+                        if GetNamedPipeInfo(self.as_win_handle(), &mut flags.0, &mut out_size, &mut in_size, &mut max_instances).as_bool() {
+                                Ok(PipeInfo {
+                                        type_mode: flags.type_mode(),
+                                        end: flags.end(),
+                                        in_size,
+                                        out_size,
+                                        max_instances: MaxInstances::from(max_instances),
+                                })
                         } else {
                                 Err(WinError::from_win32())
                         }
@@ -206,7 +446,7 @@ impl<'a> io::Read for &'a UnsafeHandle {
         fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
                 match UnsafeHandle::read(*self, buf) {
                         Ok(w) => Ok(w as _),
-                        Err(e) => Err(::std::io::Error::from_raw_os_error(e.code().0)),
+                        Err(e) => Err(io_error(e)),
                 }
         }
 }
@@ -214,19 +454,172 @@ impl<'a> io::Write for &'a UnsafeHandle {
         fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
                 match UnsafeHandle::write(*self, buf) {
                         Ok(w) => Ok(w as _),
-                        Err(e) => Err(::std::io::Error::from_raw_os_error(e.code().0)),
+                        Err(e) => Err(io_error(e)),
                 }
         }
         fn flush(&mut self) -> io::Result<()> {
                 match UnsafeHandle::flush(*self) {
                         Ok(_) => Ok(()),
-                        Err(e) => Err(::std::io::Error::from_raw_os_error(e.code().0)),
+                        Err(e) => Err(io_error(e)),
                 }
         }
 }
 
+/// Reads and writes through a handle opened with `FILE_FLAG_OVERLAPPED`, so threads sharing it don't wait on each other.
+pub(crate) struct OverlappedIo<'a>(pub(crate) &'a UnsafeHandle);
+impl io::Read for OverlappedIo<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { self.0.read_overlapped(buf, None).map(|r| r as _).map_err(io_error) }
+}
+impl io::Write for OverlappedIo<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> { self.0.write_overlapped(buf, None).map(|w| w as _).map_err(io_error) }
+        fn flush(&mut self) -> io::Result<()> { self.0.flush().map_err(io_error) }
+}
+
+/// translate a windows.rs error into an io error, keeping the win32 error code so `io::Error::kind` works.
+pub(crate) fn io_error(e: WinError) -> io::Error {
+        match WIN32_ERROR::from_error(&e) {
+                Some(ERROR_PIPE_LISTENING) => io::Error::new(io::ErrorKind::WouldBlock, "no client has connected to the pipe yet"),
+                Some(code) => io::Error::from_raw_os_error(code.0 as i32),
+                None => io::Error::from_raw_os_error(e.code().0),
+        }
+}
+
+/// Reject a zero duration the way [`TcpStream::set_read_timeout`](std::net::TcpStream::set_read_timeout) does.
+pub(crate) fn check_timeout(dur: Option<Duration>) -> io::Result<()> {
+        if dur == Some(Duration::ZERO) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "cannot set a 0 duration timeout"));
+        }
+        Ok(())
+}
+
+/// Run a blocking synchronous call (like `FlushFileBuffers`) on the current thread, cancelling it with `CancelSynchronousIo` once the timeout elapses.
+/// <br>A cancelled call fails with `ERROR_TIMEOUT`, which is [`io::ErrorKind::TimedOut`].
+/// Every timed call shares one [`Watchdog`] thread, and each calling thread duplicates its handle only once.
+pub(crate) fn with_deadline<R, F: FnOnce() -> ::windows::core::Result<R>>(timeout: Option<Duration>, f: F) -> ::windows::core::Result<R> {
+        let timeout = match timeout {
+                Some(timeout) => timeout,
+                None => return f(),
+        };
+        let thread = CURRENT_THREAD.with(|thread| thread.0.clone())?;
+        let watchdog = WATCHDOG.get_or_init(Watchdog::spawn);
+        let id = watchdog.watch(thread, ::std::time::Instant::now() + timeout);
+        let res = f();
+        let cancelled = watchdog.unwatch(id);
+        match res {
+                Err(e) if cancelled && WIN32_ERROR::from_error(&e) == Some(ERROR_OPERATION_ABORTED) => Err(ERROR_TIMEOUT.into()),
+                res => res,
+        }
+}
+
+static WATCHDOG: ::std::sync::OnceLock<Watchdog> = ::std::sync::OnceLock::new();
+thread_local! {
+        /// a real handle to the current thread, `GetCurrentThread` is only a pseudo handle that means the caller
+        static CURRENT_THREAD: ThreadHandle = ThreadHandle::current();
+}
+
+/// Duplicated handle of the thread that owns it, closed when the thread exits.
+struct ThreadHandle(::windows::core::Result<isize>);
+impl ThreadHandle {
+        fn current() -> Self {
+                let mut thread = HANDLE::default();
+                let duplicated = unsafe {
+                        DuplicateHandle(
+                                GetCurrentProcess(),
+                                GetCurrentThread(),
+                                GetCurrentProcess(),
+                                &mut thread,
+                                0,
+                                false,
+                                DUPLICATE_SAME_ACCESS,
+                        )
+                };
+                match duplicated.as_bool() {
+                        true => Self(Ok(thread.0)),
+                        false => Self(Err(WinError::from_win32())),
+                }
+        }
+}
+impl Drop for ThreadHandle {
+        fn drop(&mut self) {
+                if let Ok(thread) = self.0 {
+                        unsafe {
+                                let _ = CloseHandle(HANDLE(thread));
+                        }
+                }
+        }
+}
+
+/// One thread cancelling the timed synchronous calls of every other thread once their deadline passes.
+struct Watchdog {
+        calls: Mutex<WatchedCalls>,
+        changed: Condvar,
+}
+#[derive(Default)]
+struct WatchedCalls {
+        next_id: u64,
+        calls: Vec<WatchedCall>,
+}
+struct WatchedCall {
+        id: u64,
+        thread: isize,
+        deadline: ::std::time::Instant,
+        cancelled: bool,
+}
+impl Watchdog {
+        fn spawn() -> Self {
+                // blocks until the initialization that spawned it is done
+                thread::spawn(|| WATCHDOG.get_or_init(Watchdog::spawn).run());
+                Self {
+                        calls: Mutex::new(WatchedCalls::default()),
+                        changed: Condvar::new(),
+                }
+        }
+        fn watch(&self, thread: isize, deadline: ::std::time::Instant) -> u64 {
+                let mut calls = self.calls.lock().unwrap();
+                let id = calls.next_id;
+                calls.next_id += 1;
+                calls.calls.push(WatchedCall {
+                        id,
+                        thread,
+                        deadline,
+                        cancelled: false,
+                });
+                self.changed.notify_one();
+                id
+        }
+        /// Stop watching the call, whether it was cancelled. Once this returns the call's thread is never cancelled for it.
+        fn unwatch(&self, id: u64) -> bool {
+                let mut calls = self.calls.lock().unwrap();
+                let index = calls.calls.iter().position(|call| call.id == id).unwrap();
+                calls.calls.swap_remove(index).cancelled
+        }
+        fn run(&self) {
+                let mut calls = self.calls.lock().unwrap();
+                loop {
+                        let now = ::std::time::Instant::now();
+                        // cancelling under the lock, so a call that has been unwatched can't be hit
+                        for call in calls.calls.iter_mut().filter(|call| !call.cancelled && call.deadline <= now) {
+                                call.cancelled = unsafe { CancelSynchronousIo(HANDLE(call.thread)) }.as_bool();
+                        }
+                        // a call past its deadline may not have reached the kernel yet, try again shortly
+                        let wait = calls
+                                .calls
+                                .iter()
+                                .filter(|call| !call.cancelled)
+                                .map(|call| call.deadline.saturating_duration_since(now))
+                                .min();
+                        calls = match wait {
+                                None => self.changed.wait(calls).unwrap(),
+                                Some(wait) => self.changed.wait_timeout(calls, wait.max(Duration::from_millis(1))).unwrap().0,
+                        };
+                }
+        }
+}
+
+#[cfg(feature = "stdio")]
 #[repr(transparent)]
 pub struct OldStdinHandle(HANDLE);
+#[cfg(feature = "stdio")]
 impl OldStdinHandle {
         pub fn set_stdin(self) -> Result<(), WinError> {
                 use windows::Win32::System::Console::{SetStdHandle, STD_INPUT_HANDLE};
@@ -238,8 +631,10 @@ impl OldStdinHandle {
         }
 }
 
+#[cfg(feature = "stdio")]
 #[repr(transparent)]
 pub struct OldStdoutHandle(HANDLE);
+#[cfg(feature = "stdio")]
 impl OldStdoutHandle {
         pub fn set_stdout(self) -> Result<(), WinError> {
                 use windows::Win32::System::Console::{SetStdHandle, STD_OUTPUT_HANDLE};
@@ -251,18 +646,37 @@ impl OldStdoutHandle {
         }
 }
 
-#[derive(Default, Clone)]
+/// Current state of a pipe handle, from `GetNamedPipeHandleState`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HandleState {
+        /// number of current pipe instances
+        pub instances: u32,
+        pub read_mode: ReadMode,
+        pub wait_mode: WaitMode,
+        /// user name of the connected client, only for server ends and when the client allows impersonation
+        pub client_user_name: Option<String>,
+}
+
+/// Raw `GetNamedPipeInfo::lpFlags`.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
 pub struct PipeInfoFlags(pub NAMED_PIPE_MODE);
 impl PipeInfoFlags {
         pub fn server(&self) -> bool { self.0 .0 & PIPE_SERVER_END.0 != 0 }
         pub fn client(&self) -> bool { self.0 .0 & PIPE_SERVER_END.0 == 0 }
         pub fn byte(&self) -> bool { self.0 .0 & PIPE_TYPE_MESSAGE.0 == 0 }
         pub fn message(&self) -> bool { self.0 .0 & PIPE_TYPE_MESSAGE.0 != 0 }
+        pub fn end(&self) -> PipeEnd { PipeEnd::from(self.0) }
+        pub fn type_mode(&self) -> TypeMode { TypeMode::from(self.0) }
 }
-#[derive(Default, Clone)]
+/// Fixed properties of a pipe, from `GetNamedPipeInfo`.
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct PipeInfo {
-        pub flags: PipeInfoFlags,
+        pub type_mode: TypeMode,
+        /// which end of the pipe the handle belongs to
+        pub end: PipeEnd,
+        /// inbound buffer size (client -> server)
         pub in_size: u32,
+        /// outbound buffer size (server -> client)
         pub out_size: u32,
-        pub max_instances: u32,
+        pub max_instances: MaxInstances,
 }
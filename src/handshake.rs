@@ -0,0 +1,359 @@
+//! Optional protocol handshake right after a client connected.
+//! The client sends a hello with the protocol name, the range of versions it speaks and its capabilities, the server
+//! answers with what both sides agree on or with why they don't. Both ends then fail with the same [`Mismatch`],
+//! instead of misreading each other's messages later.
+//! <br>A hello is the magic value, a u16 big endian body length and the body, written at once so it is one message in
+//! message read mode.
+
+use super::*;
+use ::std::{error::Error, fmt, ops::RangeInclusive};
+
+/// first bytes of every hello and reply
+const MAGIC: [u8; 4] = *b"WNPH";
+const ACCEPT: u8 = 0;
+const REJECT: u8 = 1;
+
+/// What a client and server both have to agree on before talking.
+/// ```no_run
+/// # use windows_named_pipes::*;
+/// # fn main() -> Result<(), HandshakeError> {
+/// let mut handshake = Handshake::new("telemetry", 1..=2);
+/// handshake.capability("zstd").require("batches");
+/// let (client, negotiated) = ClientBuilder::<DuplexClient>::new().open_handshake(std::path::Path::new(r"\\.\pipe\telemetry"), &handshake)?;
+/// if negotiated.has("zstd") { /* ... */ }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Handshake {
+        pub(crate) protocol: String,
+        pub(crate) versions: RangeInclusive<u16>,
+        /// largest frame this side accepts, the smaller one of both sides applies
+        pub(crate) max_frame_size: u64,
+        /// capabilities in order of preference, and whether the peer must support them
+        pub(crate) capabilities: Vec<(String, bool)>,
+}
+impl Handshake {
+        /// A handshake for `protocol` speaking the `versions`, without capabilities.
+        pub fn new(protocol: &str, versions: RangeInclusive<u16>) -> Self {
+                Self {
+                        protocol: protocol.to_string(),
+                        versions,
+                        max_frame_size: DEFAULT_MAX_FRAME_SIZE as u64,
+                        capabilities: Vec::new(),
+                }
+        }
+        pub fn max_frame_size(&mut self, max_frame_size: u64) -> &mut Self {
+                self.max_frame_size = max_frame_size;
+                self
+        }
+        /// Offer a capability, used if the peer offers it too. The client's order of preference is kept.
+        pub fn capability(&mut self, name: &str) -> &mut Self { self.add(name, false) }
+        /// Offer a capability the peer must support, the handshake fails with [`Mismatch::Capability`] otherwise.
+        pub fn require(&mut self, name: &str) -> &mut Self { self.add(name, true) }
+        fn add(&mut self, name: &str, required: bool) -> &mut Self {
+                match self.capabilities.iter_mut().find(|(offered, _)| offered == name) {
+                        Some((_, offered)) => *offered |= required,
+                        None => self.capabilities.push((name.to_string(), required)),
+                }
+                self
+        }
+        /// Run the client side on a freshly opened pipe: send the hello and wait for the server's answer.
+        pub fn client<P: Read + Write + AsHandle>(&self, pipe: &mut P) -> Result<Negotiated, HandshakeError> {
+                let message = is_message(pipe)?;
+                let mut hello = Vec::new();
+                encode_string(&mut hello, &self.protocol)?;
+                hello.extend_from_slice(&self.versions.start().to_be_bytes());
+                hello.extend_from_slice(&self.versions.end().to_be_bytes());
+                hello.extend_from_slice(&self.max_frame_size.to_be_bytes());
+                hello.push(u8::try_from(self.capabilities.len()).map_err(|_| invalid_input("more than 255 capabilities"))?);
+                for (name, required) in &self.capabilities {
+                        hello.push(*required as u8);
+                        encode_string(&mut hello, name)?;
+                }
+                send(pipe, message, &hello)?;
+                let reply = recv(pipe, message)?;
+                let mut reply = Decoder(&reply);
+                match reply.u8()? {
+                        ACCEPT => {
+                                let version = u16::from_be_bytes(reply.take()?);
+                                let max_frame_size = u64::from_be_bytes(reply.take()?);
+                                let capabilities = (0..reply.u8()?).map(|_| reply.string()).collect::<io::Result<_>>()?;
+                                Ok(Negotiated {
+                                        version,
+                                        max_frame_size,
+                                        capabilities,
+                                })
+                        }
+                        REJECT => {
+                                // the server reports from its side, turn it around
+                                let mismatch = match reply.u8()? {
+                                        0 => Mismatch::Magic,
+                                        1 => Mismatch::Protocol {
+                                                local: self.protocol.clone(),
+                                                remote: reply.string()?,
+                                        },
+                                        2 => Mismatch::Version {
+                                                local: self.versions.clone(),
+                                                remote: u16::from_be_bytes(reply.take()?)..=u16::from_be_bytes(reply.take()?),
+                                        },
+                                        3 => Mismatch::Capability(reply.string()?),
+                                        _ => return Err(invalid_data().into()),
+                                };
+                                Err(HandshakeError::Mismatch(mismatch))
+                        }
+                        _ => Err(invalid_data().into()),
+                }
+        }
+        /// Run the server side on a freshly connected pipe: wait for the client's hello and answer it.
+        /// <br>A client not starting with a hello fails with [`Mismatch::Magic`] and is answered with a reject, so it doesn't
+        /// wait for a reply that never comes.
+        pub fn server<P: Read + Write + AsHandle>(&self, pipe: &mut P) -> Result<Negotiated, HandshakeError> {
+                let message = is_message(pipe)?;
+                let hello = match recv(pipe, message) {
+                        Err(HandshakeError::Mismatch(Mismatch::Magic)) => {
+                                send(pipe, message, &[REJECT, 0])?;
+                                return Err(HandshakeError::Mismatch(Mismatch::Magic));
+                        }
+                        hello => hello?,
+                };
+                let mut hello = Decoder(&hello);
+                let protocol = hello.string()?;
+                let versions = u16::from_be_bytes(hello.take()?)..=u16::from_be_bytes(hello.take()?);
+                let max_frame_size = u64::from_be_bytes(hello.take()?);
+                let offered = (0..hello.u8()?)
+                        .map(|_| Ok((hello.u8()? != 0, hello.string()?)))
+                        .collect::<io::Result<Vec<_>>>()?;
+
+                let version = *versions.end().min(self.versions.end());
+                let capabilities: Vec<String> = offered
+                        .iter()
+                        .filter(|(_, name)| self.capabilities.iter().any(|(own, _)| own == name))
+                        .map(|(_, name)| name.clone())
+                        .collect();
+                let missing = offered
+                        .iter()
+                        .map(|(required, name)| (name, *required))
+                        .chain(self.capabilities.iter().map(|(name, required)| (name, *required)))
+                        .find(|(name, required)| *required && !capabilities.contains(name));
+                let mismatch = if protocol != self.protocol {
+                        Some(Mismatch::Protocol {
+                                local: self.protocol.clone(),
+                                remote: protocol,
+                        })
+                } else if version < *versions.start() || version < *self.versions.start() {
+                        Some(Mismatch::Version {
+                                local: self.versions.clone(),
+                                remote: versions,
+                        })
+                } else {
+                        missing.map(|(name, _)| Mismatch::Capability(name.clone()))
+                };
+
+                let mut reply = Vec::new();
+                match &mismatch {
+                        None => {
+                                reply.push(ACCEPT);
+                                reply.extend_from_slice(&version.to_be_bytes());
+                                reply.extend_from_slice(&max_frame_size.min(self.max_frame_size).to_be_bytes());
+                                reply.push(capabilities.len() as u8);
+                                for name in &capabilities {
+                                        encode_string(&mut reply, name)?;
+                                }
+                        }
+                        Some(mismatch) => {
+                                reply.push(REJECT);
+                                match mismatch {
+                                        Mismatch::Protocol { local, .. } => {
+                                                reply.push(1);
+                                                encode_string(&mut reply, local)?;
+                                        }
+                                        Mismatch::Version { local, .. } => {
+                                                reply.push(2);
+                                                reply.extend_from_slice(&local.start().to_be_bytes());
+                                                reply.extend_from_slice(&local.end().to_be_bytes());
+                                        }
+                                        Mismatch::Capability(name) => {
+                                                reply.push(3);
+                                                encode_string(&mut reply, name)?;
+                                        }
+                                        Mismatch::Magic => unreachable!(),
+                                }
+                        }
+                }
+                send(pipe, message, &reply)?;
+                match mismatch {
+                        None => Ok(Negotiated {
+                                version,
+                                max_frame_size: max_frame_size.min(self.max_frame_size),
+                                capabilities,
+                        }),
+                        Some(mismatch) => Err(HandshakeError::Mismatch(mismatch)),
+                }
+        }
+}
+
+/// What a successful handshake agreed on, the same on both ends.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Negotiated {
+        /// highest version both sides speak
+        pub version: u16,
+        /// smaller max frame size of both sides
+        pub max_frame_size: u64,
+        /// capabilities offered by both sides, in the client's order of preference
+        pub capabilities: Vec<String>,
+}
+impl Negotiated {
+        pub fn has(&self, capability: &str) -> bool { self.capabilities.iter().any(|name| name == capability) }
+}
+
+/// Why a client and server did not agree, reported by both ends from their own side.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Mismatch {
+        /// the peer did not start with the handshake's magic value, it does not use a handshake
+        Magic,
+        /// the peers speak different protocols
+        Protocol { local: String, remote: String },
+        /// the version ranges don't overlap
+        Version {
+                local: RangeInclusive<u16>,
+                remote: RangeInclusive<u16>,
+        },
+        /// a required capability is not offered by both sides
+        Capability(String),
+}
+impl fmt::Display for Mismatch {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                        Mismatch::Magic => write!(f, "peer did not send a handshake"),
+                        Mismatch::Protocol { local, remote } => write!(f, "protocol {} does not match the peer's protocol {}", local, remote),
+                        Mismatch::Version { local, remote } => write!(
+                                f,
+                                "versions {}..={} don't overlap with the peer's versions {}..={}",
+                                local.start(),
+                                local.end(),
+                                remote.start(),
+                                remote.end()
+                        ),
+                        Mismatch::Capability(name) => write!(f, "required capability {} is not supported by both sides", name),
+                }
+        }
+}
+impl Error for Mismatch {}
+
+/// Failed [`Handshake`].
+#[derive(Debug)]
+pub enum HandshakeError {
+        /// the pipe failed or closed, or the peer sent a malformed hello
+        Io(io::Error),
+        /// the peers don't agree, the connection should be dropped
+        Mismatch(Mismatch),
+}
+impl fmt::Display for HandshakeError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                        HandshakeError::Io(err) => write!(f, "handshake failed: {}", err),
+                        HandshakeError::Mismatch(mismatch) => write!(f, "handshake failed: {}", mismatch),
+                }
+        }
+}
+impl Error for HandshakeError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+                match self {
+                        HandshakeError::Io(err) => Some(err),
+                        HandshakeError::Mismatch(mismatch) => Some(mismatch),
+                }
+        }
+}
+impl From<io::Error> for HandshakeError {
+        fn from(err: io::Error) -> Self { HandshakeError::Io(err) }
+}
+impl From<NamedPipeWinError> for HandshakeError {
+        fn from(err: NamedPipeWinError) -> Self { HandshakeError::Io(err.into()) }
+}
+impl From<HandshakeError> for io::Error {
+        fn from(err: HandshakeError) -> Self {
+                match err {
+                        HandshakeError::Io(err) => err,
+                        HandshakeError::Mismatch(mismatch) => io::Error::new(io::ErrorKind::InvalidData, mismatch),
+                }
+        }
+}
+
+impl ClientBuilder<DuplexClient> {
+        /// Open a client and run the client side of `handshake` on it.
+        pub fn open_handshake(&self, path: &Path, handshake: &Handshake) -> Result<(DuplexClient, Negotiated), HandshakeError> {
+                let mut client = self.open(path)?;
+                let negotiated = handshake.client(&mut client)?;
+                Ok((client, negotiated))
+        }
+}
+impl DuplexServer {
+        /// Connect the next client and run the server side of `handshake` on it, the client is dropped if it fails.
+        pub fn connect_handshake(&mut self, handshake: &Handshake) -> Result<(ConnectedDuplexServer<'_>, Negotiated), HandshakeError> {
+                let mut connected = self.connect()?;
+                let negotiated = handshake.server(&mut connected)?;
+                Ok((connected, negotiated))
+        }
+}
+
+fn is_message<P: AsHandle>(pipe: &P) -> io::Result<bool> {
+        Ok(borrow_handle(pipe).get_state(false).map_err(io_error)?.read_mode == ReadMode::Message)
+}
+fn send<P: Write>(pipe: &mut P, message: bool, body: &[u8]) -> io::Result<()> {
+        let len = u16::try_from(body.len()).map_err(|_| invalid_input("handshake is larger than 65535 bytes"))?;
+        let mut buf = MAGIC.to_vec();
+        buf.extend_from_slice(&len.to_be_bytes());
+        buf.extend_from_slice(body);
+        match message {
+                true => write_message(pipe, &buf),
+                false => pipe.write_all(&buf),
+        }
+}
+fn recv<P: Read>(pipe: &mut P, message: bool) -> Result<Vec<u8>, HandshakeError> {
+        let mut buf = match message {
+                true => read_message(pipe, MAGIC.len() + 2 + u16::MAX as usize)?,
+                false => {
+                        let mut header = vec![0u8; MAGIC.len() + 2];
+                        pipe.read_exact(&mut header)?;
+                        header
+                }
+        };
+        if buf.len() < MAGIC.len() + 2 || buf[..MAGIC.len()] != MAGIC {
+                return Err(HandshakeError::Mismatch(Mismatch::Magic));
+        }
+        let len = u16::from_be_bytes([buf[4], buf[5]]) as usize;
+        match message {
+                true if buf.len() != MAGIC.len() + 2 + len => return Err(invalid_data().into()),
+                true => {}
+                false => {
+                        buf.resize(MAGIC.len() + 2 + len, 0);
+                        pipe.read_exact(&mut buf[MAGIC.len() + 2..])?;
+                }
+        }
+        Ok(buf.split_off(MAGIC.len() + 2))
+}
+
+fn encode_string(buf: &mut Vec<u8>, string: &str) -> io::Result<()> {
+        buf.push(u8::try_from(string.len()).map_err(|_| invalid_input("handshake names are at most 255 bytes long"))?);
+        buf.extend_from_slice(string.as_bytes());
+        Ok(())
+}
+/// reads the fields of a hello or reply
+struct Decoder<'a>(&'a [u8]);
+impl Decoder<'_> {
+        fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+                let bytes = self.0.get(..N).ok_or_else(invalid_data)?.try_into().unwrap();
+                self.0 = &self.0[N..];
+                Ok(bytes)
+        }
+        fn u8(&mut self) -> io::Result<u8> { Ok(self.take::<1>()?[0]) }
+        fn string(&mut self) -> io::Result<String> {
+                let len = self.u8()? as usize;
+                let bytes = self.0.get(..len).ok_or_else(invalid_data)?;
+                self.0 = &self.0[len..];
+                String::from_utf8(bytes.to_vec()).map_err(|_| invalid_data())
+        }
+}
+fn invalid_data() -> io::Error { io::Error::new(io::ErrorKind::InvalidData, "malformed handshake") }
+fn invalid_input(msg: &str) -> io::Error { io::Error::new(io::ErrorKind::InvalidInput, msg) }
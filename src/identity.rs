@@ -0,0 +1,103 @@
+//! Check who serves a pipe before talking to it.
+//! Any process may create an instance of a well-known pipe name first, so a client that cares asks for the server's process
+//! and compares it, or the user it runs as, with what it expects.
+
+use super::*;
+use ::std::{error::Error, ffi::CStr, fmt};
+use windows::Win32::{
+        Foundation::PSID,
+        Security::{Authorization::ConvertSidToStringSidA, EqualSid, GetTokenInformation, TokenUser, TOKEN_QUERY, TOKEN_USER},
+        System::{
+                Memory::LocalFree,
+                Threading::{OpenProcess, OpenProcessToken, PROCESS_QUERY_LIMITED_INFORMATION},
+        },
+};
+
+/// Who a client expects to serve a pipe, see [`ClientBuilder::expect_server`](crate::builder::ClientBuilder::expect_server).
+/// <br>Only meaningful for pipes on the local machine, a remote server's process id belongs to the remote machine.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ServerIdentity {
+        /// the server is the process with this id
+        ProcessId(u32),
+        /// the server process runs as the same user as the current process
+        CurrentUser,
+        /// the server process runs as the user with this string SID, e.g. `S-1-5-18` for LocalSystem
+        Sid(String),
+}
+
+/// The process serving the pipe is not the expected one, the client was closed before sending anything.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct UntrustedServer {
+        pub expected: ServerIdentity,
+        /// id of the process actually serving the pipe
+        pub process_id: u32,
+}
+impl fmt::Display for UntrustedServer {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "untrusted pipe server: process {} is not {:?}", self.process_id, self.expected)
+        }
+}
+impl Error for UntrustedServer {}
+
+/// compare the server of a connected client handle with `expected`, `Ok(Some(_))` if it does not match.
+pub(crate) fn verify_server(handle: &UnsafeHandle, expected: &ServerIdentity) -> win::core::Result<Option<UntrustedServer>> {
+        let mut process_id = 0u32;
+        if !unsafe { GetNamedPipeServerProcessId(handle.as_win_handle(), &mut process_id) }.as_bool() {
+                return Err(WinError::from_win32());
+        }
+        let trusted = match expected {
+                ServerIdentity::ProcessId(expected) => *expected == process_id,
+                ServerIdentity::CurrentUser => {
+                        let server = process_user(process_id)?;
+                        let current = token_user(unsafe { GetCurrentProcess() })?;
+                        unsafe { EqualSid(user_sid(&server), user_sid(&current)) }.as_bool()
+                }
+                ServerIdentity::Sid(expected) => sid_string(user_sid(&process_user(process_id)?))?.eq_ignore_ascii_case(expected),
+        };
+        Ok((!trusted).then(|| UntrustedServer {
+                expected: expected.clone(),
+                process_id,
+        }))
+}
+
+/// `TOKEN_USER` of the process with the given id.
+fn process_user(process_id: u32) -> win::core::Result<Vec<u64>> {
+        unsafe {
+                let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id)?;
+                let user = token_user(process);
+                let _ = CloseHandle(process);
+                user
+        }
+}
+/// `TOKEN_USER` of a process, in a buffer aligned for it.
+fn token_user(process: HANDLE) -> win::core::Result<Vec<u64>> {
+        unsafe {
+                let mut token = HANDLE::default();
+                if !OpenProcessToken(process, TOKEN_QUERY, &mut token).as_bool() {
+                        return Err(WinError::from_win32());
+                }
+                // the first call only asks for the size
+                let mut len = 0u32;
+                let _ = GetTokenInformation(token, TokenUser, ::core::ptr::null_mut(), 0, &mut len);
+                let mut buf = vec![0u64; (len as usize).div_ceil(8)];
+                let res = GetTokenInformation(token, TokenUser, buf.as_mut_ptr().cast(), len, &mut len).as_bool();
+                let err = WinError::from_win32();
+                let _ = CloseHandle(token);
+                match res {
+                        true => Ok(buf),
+                        false => Err(err),
+                }
+        }
+}
+fn user_sid(token_user: &[u64]) -> PSID { unsafe { (*token_user.as_ptr().cast::<TOKEN_USER>()).User.Sid } }
+fn sid_string(sid: PSID) -> win::core::Result<String> {
+        unsafe {
+                let mut string = win::core::PSTR::null();
+                if !ConvertSidToStringSidA(sid, &mut string).as_bool() {
+                        return Err(WinError::from_win32());
+                }
+                let sid = CStr::from_ptr(string.0.cast()).to_string_lossy().into_owned();
+                LocalFree(string.0 as isize);
+                Ok(sid)
+        }
+}
@@ -0,0 +1,488 @@
+//! JSON-RPC 2.0 over named pipes, as spoken by language servers and editor plugins.
+//! In message read mode every JSON value is one pipe message. In byte read mode values are framed with
+//! `Content-Length` headers like the Language Server Protocol does.
+
+use super::*;
+use ::std::{
+        collections::{HashMap, HashSet},
+        error::Error,
+        fmt,
+        io::{BufRead, BufReader},
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// Longest header line accepted in byte read mode.
+const MAX_HEADER_LINE: u64 = 1024;
+
+/// Error object of a JSON-RPC response.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct JsonRpcErrorObject {
+        pub code: i64,
+        pub message: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub data: Option<Value>,
+}
+impl JsonRpcErrorObject {
+        /// the message is not valid JSON
+        pub const PARSE_ERROR: i64 = -32700;
+        /// the JSON is not a valid request object
+        pub const INVALID_REQUEST: i64 = -32600;
+        pub const METHOD_NOT_FOUND: i64 = -32601;
+        pub const INVALID_PARAMS: i64 = -32602;
+        pub const INTERNAL_ERROR: i64 = -32603;
+        pub fn new(code: i64, message: impl Into<String>) -> Self {
+                Self {
+                        code,
+                        message: message.into(),
+                        data: None,
+                }
+        }
+        pub fn with_data(mut self, data: Value) -> Self {
+                self.data = Some(data);
+                self
+        }
+}
+impl fmt::Display for JsonRpcErrorObject {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "json-rpc error {}: {}", self.code, self.message) }
+}
+impl Error for JsonRpcErrorObject {}
+
+/// Failed call of a [`JsonRpcClient`].
+#[derive(Debug)]
+pub enum JsonRpcError {
+        /// the pipe failed or closed, the params or result did not (de)serialize, or the response was not JSON-RPC
+        Io(io::Error),
+        /// the server answered with an error object
+        Remote(JsonRpcErrorObject),
+}
+impl fmt::Display for JsonRpcError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                        JsonRpcError::Io(err) => write!(f, "json-rpc call failed: {}", err),
+                        JsonRpcError::Remote(err) => err.fmt(f),
+                }
+        }
+}
+impl Error for JsonRpcError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+                match self {
+                        JsonRpcError::Io(err) => Some(err),
+                        JsonRpcError::Remote(err) => Some(err),
+                }
+        }
+}
+impl From<io::Error> for JsonRpcError {
+        fn from(err: io::Error) -> Self { JsonRpcError::Io(err) }
+}
+impl From<JsonRpcError> for io::Error {
+        fn from(err: JsonRpcError) -> Self {
+                match err {
+                        JsonRpcError::Io(err) => err,
+                        JsonRpcError::Remote(err) => io::Error::other(err),
+                }
+        }
+}
+
+/// Sends and receives JSON values, one per message or one per `Content-Length` frame.
+struct Transport<P> {
+        /// only buffers in byte read mode, message reads bypass it
+        reader: BufReader<P>,
+        message: bool,
+        max_size: usize,
+}
+impl<P: Read + Write> Transport<P> {
+        fn new(pipe: P, message: bool, max_size: usize) -> Self {
+                Self {
+                        reader: BufReader::new(pipe),
+                        message,
+                        max_size,
+                }
+        }
+        fn send(&mut self, value: &Value) -> io::Result<()> {
+                let body = serde_json::to_vec(value)?;
+                if body.len() > self.max_size {
+                        return Err(io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                format!(
+                                        "json-rpc message of {} bytes is larger than the limit of {} bytes",
+                                        body.len(),
+                                        self.max_size
+                                ),
+                        ));
+                }
+                match self.message {
+                        true => write_message(self.reader.get_mut(), &body),
+                        false => {
+                                let mut frame = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+                                frame.extend_from_slice(&body);
+                                self.reader.get_mut().write_all(&frame)
+                        }
+                }
+        }
+        /// the body of the next message, not parsed yet so parse errors can be answered
+        fn recv(&mut self) -> io::Result<Vec<u8>> {
+                if self.message {
+                        return read_message(self.reader.get_mut(), self.max_size);
+                }
+                let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+                let mut len = None;
+                let mut line = String::new();
+                loop {
+                        line.clear();
+                        if (&mut self.reader).take(MAX_HEADER_LINE).read_line(&mut line)? == 0 {
+                                return Err(io::ErrorKind::UnexpectedEof.into());
+                        }
+                        let header = line
+                                .strip_suffix("\r\n")
+                                .ok_or_else(|| invalid(format!("malformed json-rpc header: {:?}", line)))?;
+                        if header.is_empty() {
+                                break;
+                        }
+                        // other headers, like Content-Type, are ignored
+                        if let Some((name, value)) = header.split_once(':') {
+                                if name.trim().eq_ignore_ascii_case("Content-Length") {
+                                        len = Some(value
+                                                .trim()
+                                                .parse::<usize>()
+                                                .map_err(|err| invalid(format!("invalid Content-Length: {}", err)))?);
+                                }
+                        }
+                }
+                let len = len.ok_or_else(|| invalid("json-rpc message without Content-Length".to_string()))?;
+                if len > self.max_size {
+                        return Err(invalid(format!(
+                                "json-rpc message of {} bytes is larger than the limit of {} bytes",
+                                len, self.max_size
+                        )));
+                }
+                let mut body = vec![0u8; len];
+                self.reader.read_exact(&mut body)?;
+                Ok(body)
+        }
+}
+
+type Method = dyn Fn(Value) -> Result<Value, JsonRpcErrorObject> + Send + Sync;
+type ErrorHook = dyn Fn(io::Error) + Send + Sync;
+
+/// JSON-RPC 2.0 server answering requests, notifications and batches of connected clients.
+/// <br>Like [`Router`], it serves clients of a [`DuplexServer`] one after another, or several at once from the handler of a [`PipeServer`].
+pub struct JsonRpcServer {
+        methods: HashMap<String, Box<Method>>,
+        max_size: usize,
+        /// gets the errors that dropped a client in `serve`
+        on_error: Option<Box<ErrorHook>>,
+}
+impl JsonRpcServer {
+        pub fn new() -> Self {
+                Self {
+                        methods: HashMap::new(),
+                        max_size: DEFAULT_MAX_FRAME_SIZE,
+                        on_error: None,
+                }
+        }
+        /// Called with the error of every client [`serve`](Self::serve) drops, without it these errors are lost.
+        pub fn on_error<F: Fn(io::Error) + Send + Sync + 'static>(&mut self, on_error: F) -> &mut Self {
+                self.on_error = Some(Box::new(on_error));
+                self
+        }
+        /// Largest message the server reads or writes, [`DEFAULT_MAX_FRAME_SIZE`] by default.
+        pub fn max_message_size(&mut self, max_size: usize) -> &mut Self {
+                self.max_size = max_size;
+                self
+        }
+        /// Handle `name` with `handler`, which gets the params, or `null` when the request has none.
+        /// <br>Failing to deserialize the params answers with [`INVALID_PARAMS`](JsonRpcErrorObject::INVALID_PARAMS).
+        pub fn method<P, R, F>(&mut self, name: &str, handler: F) -> &mut Self
+        where
+                P: DeserializeOwned,
+                R: Serialize,
+                F: Fn(P) -> Result<R, JsonRpcErrorObject> + Send + Sync + 'static,
+        {
+                let method: Box<Method> = Box::new(move |params| {
+                        let params = serde_json::from_value(params)
+                                .map_err(|err| JsonRpcErrorObject::new(JsonRpcErrorObject::INVALID_PARAMS, err.to_string()))?;
+                        serde_json::to_value(handler(params)?)
+                                .map_err(|err| JsonRpcErrorObject::new(JsonRpcErrorObject::INTERNAL_ERROR, err.to_string()))
+                });
+                self.methods.insert(name.to_string(), method);
+                self
+        }
+        /// Answer one message, a request or a batch of them. `None` when nothing is to be sent back,
+        /// as for notifications and batches of only notifications.
+        pub fn handle(&self, message: &[u8]) -> Option<Value> {
+                match serde_json::from_slice::<Value>(message) {
+                        Err(err) => Some(error_response(
+                                Value::Null,
+                                JsonRpcErrorObject::new(JsonRpcErrorObject::PARSE_ERROR, err.to_string()),
+                        )),
+                        Ok(Value::Array(batch)) if batch.is_empty() => Some(error_response(
+                                Value::Null,
+                                JsonRpcErrorObject::new(JsonRpcErrorObject::INVALID_REQUEST, "empty batch"),
+                        )),
+                        Ok(Value::Array(batch)) => {
+                                let responses: Vec<_> = batch.into_iter().filter_map(|request| self.handle_request(request)).collect();
+                                (!responses.is_empty()).then_some(Value::Array(responses))
+                        }
+                        Ok(request) => self.handle_request(request),
+                }
+        }
+        fn handle_request(&self, request: Value) -> Option<Value> {
+                let invalid = |id: Value, msg: &str| Some(error_response(id, JsonRpcErrorObject::new(JsonRpcErrorObject::INVALID_REQUEST, msg)));
+                let mut request = match request {
+                        Value::Object(request) => request,
+                        _ => return invalid(Value::Null, "request is not an object"),
+                };
+                // a request without an id is a notification and gets no response, not even an error
+                let id = request.remove("id");
+                let reply_to = match id {
+                        None => None,
+                        Some(id @ (Value::Null | Value::Number(_) | Value::String(_))) => Some(id),
+                        Some(_) => return invalid(Value::Null, "id is not a string, number or null"),
+                };
+                if request.get("jsonrpc") != Some(&json!("2.0")) {
+                        return invalid(reply_to.unwrap_or(Value::Null), "jsonrpc is not \"2.0\"");
+                }
+                let method = match request.remove("method") {
+                        Some(Value::String(method)) => method,
+                        _ => return invalid(reply_to.unwrap_or(Value::Null), "method is not a string"),
+                };
+                let params = match request.remove("params") {
+                        None => Value::Null,
+                        Some(params @ (Value::Array(_) | Value::Object(_))) => params,
+                        Some(_) => return invalid(reply_to.unwrap_or(Value::Null), "params are not an array or object"),
+                };
+                let result = match self.methods.get(&method) {
+                        Some(handler) => handler(params),
+                        None => Err(JsonRpcErrorObject::new(
+                                JsonRpcErrorObject::METHOD_NOT_FOUND,
+                                format!("method not found: {}", method),
+                        )),
+                };
+                let id = reply_to?;
+                Some(match result {
+                        Ok(result) => json!({"jsonrpc": "2.0", "result": result, "id": id}),
+                        Err(err) => error_response(id, err),
+                })
+        }
+        /// Answer messages from one connected client until it disconnects.
+        pub fn serve_connection<P: Read + Write + AsHandle>(&self, pipe: &mut P) -> io::Result<()> {
+                let message = borrow_handle(&pipe).get_state(false).map_err(io_error)?.read_mode == ReadMode::Message;
+                let mut transport = Transport::new(pipe, message, self.max_size);
+                loop {
+                        let message = match transport.recv() {
+                                Ok(message) => message,
+                                Err(err) if err.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+                                Err(err) => return Err(err),
+                        };
+                        if let Some(response) = self.handle(&message) {
+                                transport.send(&response)?;
+                        }
+                }
+        }
+        /// Serve clients of `server` one after another with [`DuplexServer::serve_with`], returns once connecting a client fails.
+        /// <br>A client whose connection fails is dropped and the next one served, the error goes to [`on_error`](Self::on_error).
+        pub fn serve(&self, server: &mut DuplexServer) -> io::Result<()> {
+                server.serve_with(ClientErrorPolicy::Continue, |connected| {
+                        if let (Err(err), Some(on_error)) = (self.serve_connection(connected), &self.on_error) {
+                                on_error(err);
+                        }
+                        Ok(())
+                })
+        }
+}
+impl Default for JsonRpcServer {
+        fn default() -> Self { Self::new() }
+}
+impl fmt::Debug for JsonRpcServer {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("JsonRpcServer")
+                        .field("methods", &self.methods.keys().collect::<Vec<_>>())
+                        .field("max_size", &self.max_size)
+                        .finish_non_exhaustive()
+        }
+}
+
+fn error_response(id: Value, error: JsonRpcErrorObject) -> Value { json!({"jsonrpc": "2.0", "error": error, "id": id}) }
+
+/// Params as a request carries them: an array or object, or left out for `null`.
+fn to_params<P: Serialize>(params: &P) -> io::Result<Option<Value>> {
+        match serde_json::to_value(params)? {
+                Value::Null => Ok(None),
+                params @ (Value::Array(_) | Value::Object(_)) => Ok(Some(params)),
+                _ => Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "json-rpc params must serialize to an array, object or null",
+                )),
+        }
+}
+fn request(method: &str, params: Option<Value>, id: Option<u64>) -> Value {
+        let mut request = Map::new();
+        request.insert("jsonrpc".to_string(), json!("2.0"));
+        request.insert("method".to_string(), json!(method));
+        if let Some(params) = params {
+                request.insert("params".to_string(), params);
+        }
+        if let Some(id) = id {
+                request.insert("id".to_string(), json!(id));
+        }
+        Value::Object(request)
+}
+
+/// Calls and notifications sent together with [`JsonRpcClient::batch`].
+#[derive(Debug, Default, Clone)]
+pub struct JsonRpcBatch {
+        /// method, params and whether a response is expected
+        calls: Vec<(String, Option<Value>, bool)>,
+}
+impl JsonRpcBatch {
+        pub fn new() -> Self { Self::default() }
+        /// Add a call, its result comes back at its position among the calls.
+        pub fn call<P: Serialize>(&mut self, method: &str, params: &P) -> io::Result<&mut Self> {
+                self.calls.push((method.to_string(), to_params(params)?, true));
+                Ok(self)
+        }
+        /// Add a notification, which has no result.
+        pub fn notify<P: Serialize>(&mut self, method: &str, params: &P) -> io::Result<&mut Self> {
+                self.calls.push((method.to_string(), to_params(params)?, false));
+                Ok(self)
+        }
+        pub fn is_empty(&self) -> bool { self.calls.is_empty() }
+}
+
+/// JSON-RPC 2.0 client on a duplex pipe, framing by the pipe's read mode like [`JsonRpcServer`].
+/// <br>Calls wait for their response with the read timeout of the pipe, responses to earlier calls that gave up are skipped.
+/// A timeout in byte read mode may leave half a message in the pipe, so the client is best dropped after one.
+/// <br>An error response with a `null` id, which a server sends when it can't tell which request failed, answers the
+/// waiting call as long as no earlier call is still unanswered.
+pub struct JsonRpcClient {
+        transport: Transport<DuplexClient>,
+        next_id: u64,
+        /// ids of the calls sent but not answered yet, including those that gave up waiting
+        unanswered: HashSet<u64>,
+}
+impl JsonRpcClient {
+        pub fn new(client: DuplexClient) -> io::Result<Self> {
+                let message = client.handle_state()?.read_mode == ReadMode::Message;
+                Ok(Self {
+                        transport: Transport::new(client, message, DEFAULT_MAX_FRAME_SIZE),
+                        next_id: 0,
+                        unanswered: HashSet::new(),
+                })
+        }
+        /// Largest message the client reads or writes, [`DEFAULT_MAX_FRAME_SIZE`] by default.
+        pub fn set_max_message_size(&mut self, max_size: usize) { self.transport.max_size = max_size; }
+        pub fn get_ref(&self) -> &DuplexClient { self.transport.reader.get_ref() }
+        pub fn get_mut(&mut self) -> &mut DuplexClient { self.transport.reader.get_mut() }
+        /// Call `method` and wait for its result. `params` must serialize to an array, an object or `null` for none.
+        pub fn call<P: Serialize, R: DeserializeOwned>(&mut self, method: &str, params: &P) -> Result<R, JsonRpcError> {
+                let id = self.next_id();
+                self.transport.send(&request(method, to_params(params)?, Some(id)))?;
+                self.unanswered.insert(id);
+                loop {
+                        let response = self.recv()?;
+                        let answers = match response.get("id") {
+                                // the server couldn't tell which request failed, this call is the only one it can be
+                                Some(Value::Null) => self.unanswered.len() == 1,
+                                Some(other) => match other.as_u64() {
+                                        Some(other) => {
+                                                // a late answer to a call that gave up is no longer unanswered
+                                                self.unanswered.remove(&other);
+                                                other == id
+                                        }
+                                        None => false,
+                                },
+                                None => false,
+                        };
+                        if answers {
+                                self.unanswered.remove(&id);
+                                return Ok(serde_json::from_value(response_result(response)?).map_err(io::Error::from)?);
+                        }
+                }
+        }
+        /// Send a notification, the server does not answer it.
+        pub fn notify<P: Serialize>(&mut self, method: &str, params: &P) -> io::Result<()> {
+                self.transport.send(&request(method, to_params(params)?, None))
+        }
+        /// Send the calls and notifications of `batch` in one message and wait for the results of the calls, in the order they were added.
+        pub fn batch(&mut self, batch: &JsonRpcBatch) -> Result<Vec<Result<Value, JsonRpcErrorObject>>, JsonRpcError> {
+                if batch.is_empty() {
+                        return Ok(Vec::new());
+                }
+                let mut ids = Vec::new();
+                let mut requests = Vec::with_capacity(batch.calls.len());
+                for (method, params, call) in &batch.calls {
+                        let id = call.then(|| self.next_id());
+                        ids.extend(id);
+                        requests.push(request(method, params.clone(), id));
+                }
+                self.transport.send(&Value::Array(requests))?;
+                if ids.is_empty() {
+                        return Ok(Vec::new());
+                }
+                self.unanswered.extend(&ids);
+                loop {
+                        match self.recv()? {
+                                Value::Array(responses) => {
+                                        let mut results: HashMap<u64, _> = responses
+                                                .into_iter()
+                                                .filter_map(|response| Some((response.get("id")?.as_u64()?, response_result(response))))
+                                                .collect();
+                                        self.unanswered.retain(|id| !results.contains_key(id));
+                                        if !results.contains_key(&ids[0]) {
+                                                // a batch answering an earlier call
+                                                continue;
+                                        }
+                                        return ids
+                                                .iter()
+                                                .map(|id| match results.remove(id) {
+                                                        Some(Ok(result)) => Ok(Ok(result)),
+                                                        Some(Err(JsonRpcError::Remote(err))) => Ok(Err(err)),
+                                                        Some(Err(err)) => Err(err),
+                                                        None => Err(io::Error::new(
+                                                                io::ErrorKind::InvalidData,
+                                                                format!("no response to call {} of the batch", id),
+                                                        )
+                                                        .into()),
+                                                })
+                                                .collect();
+                                }
+                                // the whole batch was rejected, unless an earlier call is still unanswered
+                                response if response.get("id") == Some(&Value::Null) && self.unanswered.len() == ids.len() => {
+                                        self.unanswered.clear();
+                                        response_result(response)?;
+                                }
+                                _ => continue,
+                        }
+                }
+        }
+        fn next_id(&mut self) -> u64 {
+                self.next_id += 1;
+                self.next_id
+        }
+        fn recv(&mut self) -> io::Result<Value> { Ok(serde_json::from_slice(&self.transport.recv()?)?) }
+}
+impl fmt::Debug for JsonRpcClient {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("JsonRpcClient")
+                        .field("message", &self.transport.message)
+                        .field("max_size", &self.transport.max_size)
+                        .finish_non_exhaustive()
+        }
+}
+impl ClientBuilder<DuplexClient> {
+        /// Open a duplex client with these options and speak JSON-RPC over it.
+        pub fn open_jsonrpc(&self, path: &Path) -> io::Result<JsonRpcClient> { JsonRpcClient::new(self.open(path)?) }
+}
+
+/// The result of a response object, or its error object.
+fn response_result(mut response: Value) -> Result<Value, JsonRpcError> {
+        if let Some(error) = response.get_mut("error") {
+                let error = serde_json::from_value(error.take()).map_err(io::Error::from)?;
+                return Err(JsonRpcError::Remote(error));
+        }
+        match response.get_mut("result") {
+                Some(result) => Ok(result.take()),
+                None => Err(io::Error::new(io::ErrorKind::InvalidData, "json-rpc response without result or error").into()),
+        }
+}
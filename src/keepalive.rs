@@ -0,0 +1,282 @@
+//! Heartbeats and an idle timeout for long-lived connections.
+//! Both ends wrap their pipe in a [`Keepalive`], which frames application data and sends a heartbeat whenever it has not
+//! sent anything for a while. A peer that sends neither is considered gone once the idle timeout elapses.
+//! <br>Everything runs on the caller's thread, there is no background thread: heartbeats only go out while waiting in
+//! [`recv`](Keepalive::recv) or [`tick`](Keepalive::tick), so an end busy elsewhere for longer than the peer's idle timeout
+//! has to call `tick` in between or it is taken for gone. A frame is a kind byte and the payload, in byte read mode with a
+//! u32 big endian payload length in between.
+
+use super::*;
+use ::std::{fmt, time::Instant};
+
+const DATA: u8 = 0;
+const HEARTBEAT: u8 = 1;
+
+/// Timing of a [`Keepalive`], both ends should use the same.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct KeepaliveConfig {
+        /// time without sending anything before a heartbeat is sent
+        pub(crate) interval: Duration,
+        /// time without receiving anything before the peer is considered gone
+        pub(crate) idle_timeout: Duration,
+        pub(crate) max_message_size: usize,
+}
+impl KeepaliveConfig {
+        pub fn interval(&mut self, interval: Duration) -> &mut Self {
+                self.interval = interval.max(Duration::from_millis(1));
+                self
+        }
+        /// Should be a few heartbeat intervals, so a late heartbeat does not drop the connection.
+        pub fn idle_timeout(&mut self, idle_timeout: Duration) -> &mut Self {
+                self.idle_timeout = idle_timeout.max(Duration::from_millis(1));
+                self
+        }
+        pub fn max_message_size(&mut self, max_message_size: usize) -> &mut Self {
+                self.max_message_size = max_message_size.min(u32::MAX as usize);
+                self
+        }
+        pub fn new() -> Self { Self::default() }
+}
+impl Default for KeepaliveConfig {
+        fn default() -> Self {
+                KeepaliveConfig {
+                        interval: Duration::from_secs(5),
+                        idle_timeout: Duration::from_secs(15),
+                        max_message_size: DEFAULT_MAX_FRAME_SIZE,
+                }
+        }
+}
+
+/// A connected server or client that sends heartbeats and detects a silent peer.
+/// <br>Reads never see heartbeats. Once the peer has been silent for the idle timeout, every call fails with
+/// [`TimedOut`](io::ErrorKind::TimedOut). A server end is disconnected right away, a client end should be dropped, which
+/// for a [`PipeServer`] handler means returning the error.
+/// <br>A peer that stops in the middle of a frame is caught by the idle timeout too, on a synchronous pipe. An overlapped
+/// pipe can't be interrupted that way, give it a read timeout of its own.
+/// ```no_run
+/// # use windows_named_pipes::*;
+/// # fn main() -> Result<(), NamedPipeWinError> {
+/// let server = PipeServer::builder().spawn(std::path::Path::new(r"\\.\pipe\keepalive"), |pipe| {
+///         let mut pipe = Keepalive::new(pipe)?;
+///         loop {
+///                 let request = pipe.recv()?;
+///                 pipe.send(&request)?;
+///         }
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Keepalive<P> {
+        pipe: P,
+        config: KeepaliveConfig,
+        /// whether the pipe was in message read mode when wrapped
+        message: bool,
+        last_sent: Instant,
+        last_received: Instant,
+        /// data of the last message not yet taken by `read`
+        buf: Vec<u8>,
+        pos: usize,
+        timed_out: bool,
+}
+impl<P: Read + Write + AsHandle> Keepalive<P> {
+        pub fn new(pipe: P) -> io::Result<Self> { Self::with_config(pipe, KeepaliveConfig::default()) }
+        pub fn with_config(pipe: P, config: KeepaliveConfig) -> io::Result<Self> {
+                let message = borrow_handle(&pipe).get_state(false).map_err(io_error)?.read_mode == ReadMode::Message;
+                let now = Instant::now();
+                Ok(Self {
+                        pipe,
+                        config,
+                        message,
+                        last_sent: now,
+                        last_received: now,
+                        buf: Vec::new(),
+                        pos: 0,
+                        timed_out: false,
+                })
+        }
+        /// Send `data` as one message.
+        pub fn send(&mut self, data: &[u8]) -> io::Result<()> {
+                self.check()?;
+                self.send_frame(DATA, data)
+        }
+        /// Wait for the next message, sending heartbeats meanwhile, until the peer has been silent for the idle timeout.
+        pub fn recv(&mut self) -> io::Result<Vec<u8>> {
+                loop {
+                        self.tick()?;
+                        let next = (self.last_sent + self.config.interval).min(self.last_received + self.config.idle_timeout);
+                        let wait = next.saturating_duration_since(Instant::now()).max(Duration::from_millis(1));
+                        if poll(&mut [PollPipe::new(&self.pipe)], Some(wait))? == 0 {
+                                continue;
+                        }
+                        if let Some(data) = self.recv_frame()? {
+                                return Ok(data);
+                        }
+                }
+        }
+        /// Send a heartbeat if one is due and check the idle timeout, for an end that is busy elsewhere instead of in [`recv`](Self::recv).
+        /// <br>Data waiting to be read counts as the peer being alive.
+        pub fn tick(&mut self) -> io::Result<()> {
+                self.check()?;
+                let now = Instant::now();
+                if now >= self.last_received + self.config.idle_timeout {
+                        match poll(&mut [PollPipe::new(&self.pipe)], Some(Duration::ZERO))? {
+                                0 => return self.time_out(),
+                                _ => self.last_received = now,
+                        }
+                }
+                if now >= self.last_sent + self.config.interval {
+                        self.send_frame(HEARTBEAT, &[])?;
+                }
+                Ok(())
+        }
+        /// the next frame, `None` for a heartbeat
+        fn recv_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+                // the peer has just been heard from, it gets another idle timeout to finish the frame
+                let deadline = Instant::now() + self.config.idle_timeout;
+                let (kind, payload) = match until(deadline, || self.read_frame()) {
+                        Err(err) if err.kind() == io::ErrorKind::TimedOut => return self.time_out().map(|()| None),
+                        res => res?,
+                };
+                self.last_received = Instant::now();
+                match kind {
+                        DATA => Ok(Some(payload)),
+                        HEARTBEAT => Ok(None),
+                        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "malformed keepalive frame")),
+                }
+        }
+        /// the peer went silent, fail from now on and disconnect a server end so the peer finds out too
+        fn time_out(&mut self) -> io::Result<()> {
+                self.timed_out = true;
+                let handle = borrow_handle(&self.pipe);
+                if handle.get_info().is_ok_and(|info| info.end == PipeEnd::Server) {
+                        let _ = handle.disconnect();
+                }
+                self.check()
+        }
+}
+impl<P> Keepalive<P> {
+        pub fn config(&self) -> &KeepaliveConfig { &self.config }
+        pub fn get_ref(&self) -> &P { &self.pipe }
+        pub fn get_mut(&mut self) -> &mut P { &mut self.pipe }
+        pub fn into_inner(self) -> P { self.pipe }
+        fn check(&self) -> io::Result<()> {
+                match self.timed_out {
+                        true => Err(io::Error::new(
+                                io::ErrorKind::TimedOut,
+                                format!("peer sent nothing for {:?}", self.config.idle_timeout),
+                        )),
+                        false => Ok(()),
+                }
+        }
+}
+impl<P: Read + Write> Keepalive<P> {
+        fn send_frame(&mut self, kind: u8, payload: &[u8]) -> io::Result<()> {
+                if payload.len() > self.config.max_message_size {
+                        return Err(io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                format!(
+                                        "message of {} bytes is larger than the limit of {} bytes",
+                                        payload.len(),
+                                        self.config.max_message_size
+                                ),
+                        ));
+                }
+                let mut frame = Vec::with_capacity(5 + payload.len());
+                frame.push(kind);
+                if !self.message {
+                        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+                }
+                frame.extend_from_slice(payload);
+                match self.message {
+                        true => write_message(&mut self.pipe, &frame)?,
+                        false => self.pipe.write_all(&frame)?,
+                }
+                self.last_sent = Instant::now();
+                Ok(())
+        }
+        /// kind and payload of the next frame
+        fn read_frame(&mut self) -> io::Result<(u8, Vec<u8>)> {
+                match self.message {
+                        true => {
+                                let mut frame = read_message(&mut self.pipe, self.config.max_message_size + 1)?;
+                                match frame.is_empty() {
+                                        true => Err(io::Error::new(io::ErrorKind::InvalidData, "malformed keepalive frame")),
+                                        false => Ok((frame[0], frame.split_off(1))),
+                                }
+                        }
+                        false => {
+                                let mut header = [0u8; 5];
+                                self.pipe.read_exact(&mut header)?;
+                                let len = u32::from_be_bytes(header[1..].try_into().unwrap()) as usize;
+                                if len > self.config.max_message_size {
+                                        return Err(io::Error::new(
+                                                io::ErrorKind::InvalidData,
+                                                format!(
+                                                        "message of {} bytes is larger than the limit of {} bytes",
+                                                        len, self.config.max_message_size
+                                                ),
+                                        ));
+                                }
+                                let mut payload = vec![0u8; len];
+                                self.pipe.read_exact(&mut payload)?;
+                                Ok((header[0], payload))
+                        }
+                }
+        }
+}
+/// Run `f` on a synchronous pipe until `deadline`, after which its blocked read is cancelled with
+/// [`TimedOut`](io::ErrorKind::TimedOut) like a read past the pipe's own timeout.
+fn until<R>(deadline: Instant, f: impl FnOnce() -> io::Result<R>) -> io::Result<R> {
+        let timeout = deadline.saturating_duration_since(Instant::now()).max(Duration::from_millis(1));
+        let mut res = None;
+        let cancelled = with_deadline(Some(timeout), || match f() {
+                // handed back so `with_deadline` can tell its own cancellation from someone else's
+                Err(err) if err.raw_os_error() == Some(ERROR_OPERATION_ABORTED.0 as i32) => Err(ERROR_OPERATION_ABORTED.into()),
+                other => {
+                        res = Some(other);
+                        Ok(())
+                }
+        });
+        match cancelled {
+                Ok(()) => res.unwrap(),
+                Err(err) => Err(io_error(err)),
+        }
+}
+
+impl<P: Read + Write + AsHandle> Read for Keepalive<P> {
+        /// Returns `Ok(0)` once the peer has closed the pipe, so `read_to_end` and `io::copy` finish.
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                while self.pos == self.buf.len() {
+                        self.buf = match self.recv() {
+                                Err(err) if err.kind() == io::ErrorKind::BrokenPipe => return Ok(0),
+                                res => res?,
+                        };
+                        self.pos = 0;
+                }
+                let n = buf.len().min(self.buf.len() - self.pos);
+                buf[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+                self.pos += n;
+                Ok(n)
+        }
+}
+impl<P: Read + Write + AsHandle> Write for Keepalive<P> {
+        /// Sends at most the max message size of `buf` as one message.
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if buf.is_empty() {
+                        return Ok(0);
+                }
+                let n = buf.len().min(self.config.max_message_size);
+                self.send(&buf[..n])?;
+                Ok(n)
+        }
+        fn flush(&mut self) -> io::Result<()> { self.pipe.flush() }
+}
+impl<P> fmt::Debug for Keepalive<P> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("Keepalive")
+                        .field("config", &self.config)
+                        .field("message", &self.message)
+                        .field("timed_out", &self.timed_out)
+                        .finish_non_exhaustive()
+        }
+}
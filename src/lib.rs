@@ -18,19 +18,30 @@ use ::std::{
         mem::ManuallyDrop,
         os::windows::io::*,
         path::{Path, PathBuf},
+        sync::{Arc, Condvar, Mutex},
+        thread,
+        time::Duration,
 };
 use ::windows::{
         self as win,
-        core::{Error as WinError, HRESULT, PCSTR},
+        core::{Error as WinError, HRESULT, PCSTR, PCWSTR},
         Win32::{
-                Foundation::{CloseHandle, HANDLE, MAX_PATH},
+                Foundation::{
+                        CloseHandle, DuplicateHandle, GetHandleInformation, SetHandleInformation, BOOL, DUPLICATE_SAME_ACCESS, ERROR_IO_PENDING,
+                        ERROR_NO_DATA, ERROR_OPERATION_ABORTED, ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED, ERROR_PIPE_LISTENING, ERROR_TIMEOUT, HANDLE,
+                        HANDLE_FLAGS, HANDLE_FLAG_INHERIT, MAX_PATH, WAIT_TIMEOUT, WIN32_ERROR,
+                },
                 Security::SECURITY_ATTRIBUTES,
                 Storage::FileSystem::{
                         CreateFileA, FlushFileBuffers, ReadFile, WriteFile, FILE_FLAGS_AND_ATTRIBUTES, FILE_FLAG_FIRST_PIPE_INSTANCE,
-                        FILE_FLAG_WRITE_THROUGH, FILE_GENERIC_READ, FILE_GENERIC_WRITE, FILE_SHARE_NONE, OPEN_EXISTING, PIPE_ACCESS_DUPLEX,
-                        PIPE_ACCESS_INBOUND, PIPE_ACCESS_OUTBOUND,
+                        FILE_FLAG_OVERLAPPED, FILE_FLAG_WRITE_THROUGH, FILE_GENERIC_READ, FILE_GENERIC_WRITE, FILE_SHARE_NONE, OPEN_EXISTING,
+                        PIPE_ACCESS_DUPLEX, PIPE_ACCESS_INBOUND, PIPE_ACCESS_OUTBOUND,
+                },
+                System::{
+                        Pipes::*,
+                        Threading::{CreateEventW, GetCurrentProcess, GetCurrentThread, WaitForSingleObject},
+                        IO::{CancelIoEx, CancelSynchronousIo, GetOverlappedResult, OVERLAPPED},
                 },
-                System::Pipes::*,
         },
 };
 
@@ -43,6 +54,8 @@ pub const LOCAL_PREFIX: &str = r"\\.\pipe\LOCAL\";
 pub const DEFAULT_PREFIX: &str = r"\\.\pipe\";
 
 const ERROR_INVALID_NAME: HRESULT = HRESULT(123);
+/// Winsock's would block code, the win32 error std maps to [`io::ErrorKind::WouldBlock`].
+const WSAEWOULDBLOCK: WIN32_ERROR = WIN32_ERROR(10035);
 
 mod tests;
 
@@ -57,7 +70,9 @@ use generics::*;
 
 mod handle;
 use handle::*;
-pub use handle::{OldStdinHandle, OldStdoutHandle}; // need to expose in order for handle mod to stay non-pub
+pub use handle::{HandleState, PipeInfo, PipeInfoFlags}; // need to expose in order for handle mod to stay non-pub
+#[cfg(feature = "stdio")]
+pub use handle::{OldStdinHandle, OldStdoutHandle};
 
 mod unsafes;
 use unsafes::*;
@@ -70,3 +85,73 @@ pub use clients::*;
 
 pub mod builder;
 use builder::*;
+
+mod poll;
+pub use poll::*;
+
+mod framed;
+pub use framed::*;
+
+mod chunked;
+pub use chunked::{Chunked, ChunkedConfig};
+
+#[cfg(any(feature = "zstd", feature = "lz4", feature = "deflate"))]
+mod compress;
+#[cfg(any(feature = "zstd", feature = "lz4", feature = "deflate"))]
+pub use compress::{Compression, DEFAULT_COMPRESS_THRESHOLD};
+
+#[cfg(any(feature = "json", feature = "bincode", feature = "msgpack"))]
+mod typed;
+#[cfg(any(feature = "json", feature = "bincode", feature = "msgpack"))]
+pub use typed::{Format, TypedChannel};
+
+#[cfg(any(feature = "json", feature = "bincode", feature = "msgpack"))]
+mod rpc;
+#[cfg(any(feature = "json", feature = "bincode", feature = "msgpack"))]
+pub use rpc::{RemoteError, Router, RpcClient, RpcError};
+
+#[cfg(feature = "jsonrpc")]
+mod jsonrpc;
+#[cfg(feature = "jsonrpc")]
+pub use jsonrpc::{JsonRpcBatch, JsonRpcClient, JsonRpcError, JsonRpcErrorObject, JsonRpcServer};
+
+mod mux;
+pub use mux::{Mux, MuxBuilder, MuxPipe, MuxStream};
+
+mod handshake;
+pub use handshake::{Handshake, HandshakeError, Mismatch, Negotiated};
+
+#[cfg(feature = "encryption")]
+mod secure;
+#[cfg(feature = "encryption")]
+pub use secure::{SecureChannel, SecureError};
+
+mod keepalive;
+pub use keepalive::{Keepalive, KeepaliveConfig};
+
+mod pubsub;
+pub use pubsub::{Overflow, PubSubClient, PubSubServer, PubSubServerBuilder};
+
+mod auth;
+#[cfg(feature = "hmac")]
+pub use auth::HmacChallenge;
+pub use auth::{Authenticator, Credential, SharedSecret, DEFAULT_AUTH_TIMEOUT};
+
+mod identity;
+use identity::verify_server;
+pub use identity::{ServerIdentity, UntrustedServer};
+
+mod pair;
+use pair::pair;
+pub use pair::{byte_pair, message_pair, pipe_pair};
+
+mod process;
+pub use process::*;
+
+#[cfg(feature = "stdio")]
+mod stdio;
+#[cfg(feature = "stdio")]
+pub use stdio::StdioRedirect;
+
+mod split;
+pub use split::{OwnedReadHalf, OwnedWriteHalf, ReadHalf, ReuniteError, WriteHalf};
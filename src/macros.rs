@@ -27,6 +27,9 @@ macro_rules! def_server_structs {
                 impl AsHandle for $server {
                         fn as_handle(&self) -> BorrowedHandle<'_> { self.0.as_handle() }
                 }
+                impl From<$server> for OwnedHandle {
+                        fn from(that: $server) -> Self { that.0.into() }
+                }
                 impl $server {
                         #[doc = "Get the full path of the pipe."]
                         pub fn path(&self) -> &Path { &self.0.path }
@@ -36,9 +39,10 @@ macro_rules! def_server_structs {
                         pub fn host(&self) -> &str { unsafe { self.0.host() } }
                         #[doc = "The server handle as a windows.rs [`HANDLE`](::windows::Win32::Foundation::HANDLE)"]
                         pub fn as_win_handle(&self) -> HANDLE { self.0.as_win_handle() }
-                        /// create a new named pipe server with the given path or open an exisiting pipe
+                        /// create a new named pipe server with the given path, failing if a pipe with that path already exists
                         /// # Behavior
-                        /// opens a pipe with Message type, Message read mode, Wait mode, Wait forever, Reject remote clients, DEFAULT_BUFSIZE, default security attributes, and max instances of 1.
+                        /// creates the first instance of the pipe with Message type, Message read mode, Wait mode, Wait forever, Reject remote clients, DEFAULT_BUFSIZE, default security attributes, and max instances of 1.
+                        /// Another process can't have created it first, use [`new_instance`](Self::new_instance) to open further instances of the pipe.
                         pub fn open(path: &Path) -> Result<Self, NamedPipeWinError> { _open(path, Self::ACCESS_MODE) }
                         #[doc = r#"Open server with the given name with local path, equivalent to `open("\\.\pipe\name")`."#]
                         pub fn with_name(name: &str) -> Result<Self, NamedPipeWinError> {
@@ -54,9 +58,51 @@ macro_rules! def_server_structs {
                                 path.push_str(name);
                                 Self::open(&PathBuf::from(path))
                         }
+                        #[doc = "How long `connect()` waits for a client, `None` waits forever."]
+                        pub fn connect_timeout(&self) -> Option<Duration> { self.0.connect_timeout() }
+                        #[doc = "Set how long `connect()` waits for a client before failing with [`TimedOut`](io::ErrorKind::TimedOut), `None` waits forever.<br><br>Errors on a zero duration."]
+                        pub fn set_connect_timeout(&mut self, dur: Option<Duration>) -> io::Result<()> { self.0.set_connect_timeout(dur) }
+                        #[doc = "Type, end, buffer sizes and max instances of the pipe, as cached when the handle was opened."]
+                        pub fn info(&self) -> &PipeInfo { self.0.info() }
+                        #[doc = "Query the pipe's info from the OS again, updating the cached info."]
+                        pub fn refresh_info(&mut self) -> Result<&PipeInfo, NamedPipeWinError> {
+                                let path = self.0.path.clone();
+                                self.0.refresh_info().map_err(pipe_err::<Self>(&path, "refresh_info()"))
+                        }
+                        #[doc = "Switch between byte and message read mode, message read mode fails on byte type pipes."]
+                        pub fn set_read_mode(&self, read_mode: ReadMode) -> Result<(), NamedPipeWinError> {
+                                self.0.set_read_mode(read_mode).map_err(pipe_err::<Self>(&self.0.path, "set_read_mode()"))
+                        }
+                        #[doc = "Current instances, read mode, wait mode and client user name of the pipe handle."]
+                        pub fn handle_state(&self) -> Result<HandleState, NamedPipeWinError> {
+                                self.0.handle_state().map_err(pipe_err::<Self>(&self.0.path, "handle_state()"))
+                        }
+                        #[doc = "Switch between blocking and non-blocking mode.<br><br>In non-blocking mode `connect()` returns right away, failing with `ERROR_PIPE_LISTENING` ([`WouldBlock`](io::ErrorKind::WouldBlock)) until a client has connected. The connected server starts in the same mode."]
+                        pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), NamedPipeWinError> {
+                                self.0.set_nonblocking(nonblocking).map_err(pipe_err::<Self>(&self.0.path, "set_nonblocking()"))
+                        }
+                        #[doc = "Let child processes created with handle inheritance get a copy of the pipe handle, or stop them from getting one."]
+                        pub fn set_inheritable(&self, inheritable: bool) -> Result<(), NamedPipeWinError> {
+                                self.0.set_inheritable(inheritable).map_err(pipe_err::<Self>(&self.0.path, "set_inheritable()"))
+                        }
+                        #[doc = "Whether the pipe was opened with `overlapped(true)`, such a handle must not be used for synchronous I/O like a [`fs::File`]."]
+                        pub fn is_overlapped(&self) -> bool { self.0.is_overlapped() }
+                        #[doc = "Turn the server into an inheritable [`OwnedHandle`] to pass to a spawned child, the server is handed back on failure."]
+                        #[allow(clippy::result_large_err)]
+                        pub fn into_inheritable(self) -> Result<OwnedHandle, (Self, NamedPipeWinError)> {
+                                match self.set_inheritable(true) {
+                                        Ok(()) => Ok(self.into()),
+                                        Err(err) => Err((self, err)),
+                                }
+                        }
+                        #[doc = "Another instance of this server's pipe, created with the same settings and connect timeout, for one more client to connect to.<br>Unlike [`ServerBuilder::open`] it doesn't ask to be the first instance, the pipe is already ours.<br><br>Fails with `ERROR_PIPE_BUSY` once the pipe has its max instances."]
+                        pub fn new_instance(&self) -> Result<Self, NamedPipeWinError> {
+                                self.0.new_instance().map(Self).map_err(pipe_err::<Self>(&self.0.path, "new_instance()"))
+                        }
                         #[doc = "wait for client to connect to the pipe and get IO enabled server."]
-                        pub fn connect(&mut self) -> Result<$c_server, NamedPipeWinError> { _connect(self) }
+                        pub fn connect(&mut self) -> Result<$c_server<'_>, NamedPipeWinError> { _connect(self) }
                         #[doc = "Close the server's pipe handle manually before drop, for handling any drop error."]
+                        #[allow(clippy::result_large_err)]
                         pub fn close(self) -> Result<(), (Self, NamedPipeWinError)> { _close(self) }
                         #[doc = "Create a builder to specify additional/custom properties of the server."]
                         pub fn builder() -> ServerBuilder::<$server> { ServerBuilder::new() }
@@ -64,23 +110,23 @@ macro_rules! def_server_structs {
                 }
                 $(#[$c_server_metas])*
                 #[repr(transparent)]
-                pub struct $c_server(pub(crate) UnsafeConnectedServer);
-                impl ConnectedServerImpl for $c_server {}
-                impl UnsafeWrapper for $c_server {
-                        type Inner = UnsafeConnectedServer;
+                pub struct $c_server<'a>(pub(crate) UnsafeConnectedServer<'a>);
+                impl<'a> ConnectedServerImpl<'a> for $c_server<'a> {}
+                impl<'a> UnsafeWrapper for $c_server<'a> {
+                        type Inner = UnsafeConnectedServer<'a>;
                         unsafe fn as_inner(&self) -> &Self::Inner { &self.0 }
                         unsafe fn as_mut_inner(&mut self) -> &mut Self::Inner { &mut self.0 }
                         unsafe fn from_inner(inner: Self::Inner) -> Self { Self(inner) }
                         unsafe fn into_inner(self) -> Self::Inner { self.0 }
                 }
-                impl AsRawHandle for $c_server {
+                impl AsRawHandle for $c_server<'_> {
                         fn as_raw_handle(&self) -> RawHandle { self.0.as_raw_handle() }
                 }
-                impl AsHandle for $c_server {
+                impl AsHandle for $c_server<'_> {
                         fn as_handle(&self) -> BorrowedHandle<'_> { self.0.as_handle() }
                 }
-                $(impl $c_server_trait for $c_server {$($c_server_trait_inner)+})+
-                impl $c_server {
+                $(impl $c_server_trait for $c_server<'_> {$($c_server_trait_inner)+})+
+                impl $c_server<'_> {
                         #[doc = "Get the full path of the pipe."]
                         pub fn path(&self) -> &Path { &self.0.path }
                         #[doc = "Get the name of the pipe"]
@@ -90,7 +136,60 @@ macro_rules! def_server_structs {
                         #[doc = "The server handle as a windows.rs [`HANDLE`](::windows::Win32::Foundation::HANDLE)"]
                         pub fn as_win_handle(&self) -> HANDLE { self.0.as_win_handle() }
                         #[doc = "Disconnect the server's pipe handle manually before drop, for handling any drop error.<br><br>Does not flush."]
+                        #[allow(clippy::result_large_err)]
                         pub fn disconnect(self) -> Result<(), (Self, NamedPipeWinError)> { _disconnect(self) }
+                        #[doc = "Type, end, buffer sizes and max instances of the pipe, as cached when the handle was opened."]
+                        pub fn info(&self) -> &PipeInfo { self.0.info() }
+                        #[doc = "Query the pipe's info from the OS again, updating the cached info."]
+                        pub fn refresh_info(&mut self) -> Result<&PipeInfo, NamedPipeWinError> {
+                                let path = self.0.path.clone();
+                                self.0.refresh_info().map_err(pipe_err::<Self>(&path, "refresh_info()"))
+                        }
+                        #[doc = "Switch between byte and message read mode, message read mode fails on byte type pipes."]
+                        pub fn set_read_mode(&self, read_mode: ReadMode) -> Result<(), NamedPipeWinError> {
+                                self.0.set_read_mode(read_mode).map_err(pipe_err::<Self>(&self.0.path, "set_read_mode()"))
+                        }
+                        #[doc = "Current instances, read mode, wait mode and client user name of the pipe handle."]
+                        pub fn handle_state(&self) -> Result<HandleState, NamedPipeWinError> {
+                                self.0.handle_state().map_err(pipe_err::<Self>(&self.0.path, "handle_state()"))
+                        }
+                        #[doc = "Switch between blocking and non-blocking mode.<br><br>In non-blocking mode reads on an empty pipe and writes to a full pipe fail with [`WouldBlock`](io::ErrorKind::WouldBlock)."]
+                        pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), NamedPipeWinError> {
+                                self.0.set_nonblocking(nonblocking).map_err(pipe_err::<Self>(&self.0.path, "set_nonblocking()"))
+                        }
+                        #[doc = "Let child processes created with handle inheritance get a copy of the pipe handle, or stop them from getting one."]
+                        pub fn set_inheritable(&self, inheritable: bool) -> Result<(), NamedPipeWinError> {
+                                self.0.set_inheritable(inheritable).map_err(pipe_err::<Self>(&self.0.path, "set_inheritable()"))
+                        }
+                        #[doc = "Whether the pipe was opened with `overlapped(true)`, such a handle must not be used for synchronous I/O like a [`fs::File`]."]
+                        pub fn is_overlapped(&self) -> bool { self.0.is_overlapped() }
+                        #[doc = "Hand the connection to a child process as its stdin, stdout or stderr, through a copy of the instance's handle.<br>The client stays connected instead of being disconnected here, until the child and the server have both closed their handles: drop the server afterwards rather than connecting it again.<br><br>Fails with `ERROR_NOT_SUPPORTED` if the server was opened with `overlapped(true)`, std only does synchronous I/O."]
+                        #[allow(clippy::result_large_err)]
+                        pub fn into_stdio(self) -> Result<::std::process::Stdio, (Self, NamedPipeWinError)> {
+                                if self.0.overlapped {
+                                        let err = pipe_err::<Self>(&self.0.path, "into_stdio()")(::windows::Win32::Foundation::ERROR_NOT_SUPPORTED.into());
+                                        return Err((self, err));
+                                }
+                                match self.0.handle.duplicate(false) {
+                                        Ok(handle) => {
+                                                // the copy owns the connection now, dropping `self` would disconnect it
+                                                ::std::mem::forget(self);
+                                                Ok(OwnedHandle::from(handle).into())
+                                        }
+                                        Err(err) => {
+                                                let err = pipe_err::<Self>(&self.0.path, "into_stdio()")(err);
+                                                Err((self, err))
+                                        }
+                                }
+                        }
+                        #[doc = "How long a read may block, `None` blocks forever."]
+                        pub fn read_timeout(&self) -> Option<Duration> { self.0.read_timeout() }
+                        #[doc = "How long a write or flush may block, `None` blocks forever."]
+                        pub fn write_timeout(&self) -> Option<Duration> { self.0.write_timeout() }
+                        #[doc = "Set how long a read may block before failing with [`TimedOut`](io::ErrorKind::TimedOut), `None` blocks forever.<br><br>Errors on a zero duration, like [`TcpStream::set_read_timeout`](std::net::TcpStream::set_read_timeout)."]
+                        pub fn set_read_timeout(&mut self, dur: Option<Duration>) -> io::Result<()> { self.0.set_read_timeout(dur) }
+                        #[doc = "Set how long a write or flush may block before failing with [`TimedOut`](io::ErrorKind::TimedOut), `None` blocks forever.<br><br>Errors on a zero duration, like [`TcpStream::set_write_timeout`](std::net::TcpStream::set_write_timeout)."]
+                        pub fn set_write_timeout(&mut self, dur: Option<Duration>) -> io::Result<()> { self.0.set_write_timeout(dur) }
                         $($c_server_impl_inner)+
                 }
         };
@@ -118,6 +217,8 @@ macro_rules! def_client_struct {
                 }
                 impl AsRawHandle for $client { fn as_raw_handle(&self) -> RawHandle { self.0.as_raw_handle() } }
                 impl AsHandle for $client { fn as_handle(&self) -> BorrowedHandle<'_> { self.0.as_handle() } }
+                impl From<$client> for OwnedHandle { fn from(that: $client) -> Self { that.0.into() } }
+                impl From<$client> for ::std::process::Stdio { fn from(that: $client) -> Self { OwnedHandle::from(that).into() } }
                 $(impl $trait for $client {$($trait_inner)+})+
                 impl $client {
                         #[doc = "Get the full path of the pipe."]
@@ -142,7 +243,68 @@ macro_rules! def_client_struct {
                                 path.push_str(name);
                                 Self::open(&PathBuf::from(path))
                         }
+                        #[doc = "Type, end, buffer sizes and max instances of the pipe, as cached when the handle was opened."]
+                        pub fn info(&self) -> &PipeInfo { self.0.info() }
+                        #[doc = "Query the pipe's info from the OS again, updating the cached info."]
+                        pub fn refresh_info(&mut self) -> Result<&PipeInfo, NamedPipeWinError> {
+                                let path = self.0.path.clone();
+                                self.0.refresh_info().map_err(pipe_err::<Self>(&path, "refresh_info()"))
+                        }
+                        #[doc = "Switch between byte and message read mode, message read mode fails on byte type pipes."]
+                        pub fn set_read_mode(&self, read_mode: ReadMode) -> Result<(), NamedPipeWinError> {
+                                self.0.set_read_mode(read_mode).map_err(pipe_err::<Self>(&self.0.path, "set_read_mode()"))
+                        }
+                        #[doc = "Current instances, read mode, wait mode and client user name of the pipe handle."]
+                        pub fn handle_state(&self) -> Result<HandleState, NamedPipeWinError> {
+                                self.0.handle_state().map_err(pipe_err::<Self>(&self.0.path, "handle_state()"))
+                        }
+                        #[doc = "Switch between blocking and non-blocking mode.<br><br>In non-blocking mode reads on an empty pipe and writes to a full pipe fail with [`WouldBlock`](io::ErrorKind::WouldBlock)."]
+                        pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), NamedPipeWinError> {
+                                self.0.set_nonblocking(nonblocking).map_err(pipe_err::<Self>(&self.0.path, "set_nonblocking()"))
+                        }
+                        #[doc = "How long a read may block, `None` blocks forever."]
+                        pub fn read_timeout(&self) -> Option<Duration> { self.0.read_timeout() }
+                        #[doc = "How long a write or flush may block, `None` blocks forever."]
+                        pub fn write_timeout(&self) -> Option<Duration> { self.0.write_timeout() }
+                        #[doc = "Set how long a read may block before failing with [`TimedOut`](io::ErrorKind::TimedOut), `None` blocks forever.<br><br>Errors on a zero duration, like [`TcpStream::set_read_timeout`](std::net::TcpStream::set_read_timeout)."]
+                        pub fn set_read_timeout(&mut self, dur: Option<Duration>) -> io::Result<()> { self.0.set_read_timeout(dur) }
+                        #[doc = "Set how long a write or flush may block before failing with [`TimedOut`](io::ErrorKind::TimedOut), `None` blocks forever.<br><br>Errors on a zero duration, like [`TcpStream::set_write_timeout`](std::net::TcpStream::set_write_timeout)."]
+                        pub fn set_write_timeout(&mut self, dur: Option<Duration>) -> io::Result<()> { self.0.set_write_timeout(dur) }
+                        #[doc = "Set how many bytes are buffered before sending to a remote server.<br><br>Fails with `ERROR_INVALID_PARAMETER` unless the server is on another machine, only the client end of a remote pipe buffers."]
+                        pub fn set_max_collection_count(&self, max_collection_count: u32) -> Result<(), NamedPipeWinError> {
+                                self.0.set_max_collection_count(max_collection_count).map_err(pipe_err::<Self>(&self.0.path, "set_max_collection_count()"))
+                        }
+                        #[doc = "Set how long data is buffered before sending to a remote server.<br><br>Fails with `ERROR_INVALID_PARAMETER` unless the server is on another machine, only the client end of a remote pipe buffers."]
+                        pub fn set_collect_data_timeout(&self, timeout: Duration) -> Result<(), NamedPipeWinError> {
+                                self.0.set_collect_data_timeout(timeout).map_err(pipe_err::<Self>(&self.0.path, "set_collect_data_timeout()"))
+                        }
+                        #[doc = "Let child processes created with handle inheritance get a copy of the pipe handle, or stop them from getting one."]
+                        pub fn set_inheritable(&self, inheritable: bool) -> Result<(), NamedPipeWinError> {
+                                self.0.set_inheritable(inheritable).map_err(pipe_err::<Self>(&self.0.path, "set_inheritable()"))
+                        }
+                        #[doc = "Whether the pipe was opened with `overlapped(true)`, such a handle must not be used for synchronous I/O like a [`fs::File`]."]
+                        pub fn is_overlapped(&self) -> bool { self.0.is_overlapped() }
+                        #[doc = "Turn the client into an inheritable [`OwnedHandle`] to pass to a spawned child, the client is handed back on failure."]
+                        #[allow(clippy::result_large_err)]
+                        pub fn into_inheritable(self) -> Result<OwnedHandle, (Self, NamedPipeWinError)> {
+                                match self.set_inheritable(true) {
+                                        Ok(()) => Ok(self.into()),
+                                        Err(err) => Err((self, err)),
+                                }
+                        }
+                        #[doc = "Another client on the same connection with its own handle, the same path, info and timeouts.<br><br>Both clients read from and write to the same pipe, so messages go to whichever reads first."]
+                        pub fn try_clone(&self) -> Result<Self, NamedPipeWinError> {
+                                self.0.try_clone().map(Self).map_err(pipe_err::<Self>(&self.0.path, "try_clone()"))
+                        }
+                        #[doc = "A copy of the pipe handle as a child process's stdin, stdout or stderr, this client stays usable.<br><br>Fails with `ERROR_NOT_SUPPORTED` if the client was opened with `overlapped(true)`, std only does synchronous I/O."]
+                        pub fn try_stdio(&self) -> Result<::std::process::Stdio, NamedPipeWinError> {
+                                if self.0.overlapped {
+                                        return Err(pipe_err::<Self>(&self.0.path, "try_stdio()")(::windows::Win32::Foundation::ERROR_NOT_SUPPORTED.into()));
+                                }
+                                self.0.handle.duplicate(false).map(|handle| OwnedHandle::from(handle).into()).map_err(pipe_err::<Self>(&self.0.path, "try_stdio()"))
+                        }
                         #[doc = "Close the client's pipe handle manually before drop, for handling any drop error."]
+                        #[allow(clippy::result_large_err)]
                         pub fn close(self) -> Result<(), (Self, NamedPipeWinError)> { _close(self) }
                         #[doc = "Create a builder to specify additional/custom properties of the client."]
                         pub fn builder() -> ClientBuilder::<$client> { ClientBuilder::new() }
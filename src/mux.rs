@@ -0,0 +1,572 @@
+//! Many logical streams over one connected duplex pipe.
+//! Every frame is a 9 byte header, kind, stream id and payload length, followed by the payload. A reader thread sorts
+//! incoming frames into the streams, a writer thread sends control frames first and then data, taking turns between streams.
+//! <br>Each side grants the other a window per stream and the sender never sends more than is left of it,
+//! so one stream nobody reads from can't hold up the others.
+
+use super::*;
+use ::std::{
+        collections::{HashMap, VecDeque},
+        fmt,
+        sync::MutexGuard,
+        thread::JoinHandle,
+};
+
+const HEADER_LEN: usize = 9;
+/// a new stream, opened by the side whose parity its id has
+const OPEN: u8 = 0;
+const DATA: u8 = 1;
+/// the receiver read this many more bytes, payload is the increment as big endian u32
+const WINDOW: u8 = 2;
+/// the sender won't send more, reads on the other side end after the data before it
+const CLOSE: u8 = 3;
+/// the stream is aborted in both directions
+const RESET: u8 = 4;
+
+/// The pipes a [`Mux`] runs on: a [`DuplexClient`], or a [`DuplexServer`] a client has connected to.
+/// <br>A [`ConnectedDuplexServer`] only borrows its instance, so pass the server itself, or let [`MuxBuilder::accept`]
+/// connect it.
+pub trait MuxPipe: AsHandle + Send + Sync + 'static + sealed::Sealed {}
+impl MuxPipe for DuplexClient {}
+impl MuxPipe for DuplexServer {}
+
+mod sealed {
+        /// only the crate's pipe types, whose handles know whether they are overlapped
+        pub trait Sealed {
+                fn overlapped(&self) -> bool;
+        }
+        impl Sealed for super::DuplexClient {
+                fn overlapped(&self) -> bool { self.0.overlapped }
+        }
+        impl Sealed for super::DuplexServer {
+                fn overlapped(&self) -> bool { self.0.overlapped }
+        }
+}
+
+/// Builder for a [`Mux`].
+#[derive(Debug, Clone)]
+pub struct MuxBuilder {
+        /// bytes a stream buffers in each direction
+        pub(crate) window: u32,
+        /// largest data frame, smaller frames let streams take turns more often
+        pub(crate) max_frame_size: u32,
+}
+impl MuxBuilder {
+        /// Bytes a stream may receive before it is read, and may have written but not sent.
+        pub fn window(&mut self, window: u32) -> &mut Self {
+                self.window = window.max(1);
+                self
+        }
+        /// Largest chunk of one stream sent before the next stream gets its turn.
+        pub fn max_frame_size(&mut self, max_frame_size: u32) -> &mut Self {
+                self.max_frame_size = max_frame_size.max(1);
+                self
+        }
+        pub fn new() -> Self { Self::default() }
+        /// Multiplex a connected pipe, like a [`DuplexClient`]. Both ends of the pipe have to be multiplexed.
+        /// <br>The pipe is read and written from two threads, so it must have been opened with `overlapped(true)`, it fails
+        /// with [`Unsupported`](io::ErrorKind::Unsupported) otherwise.
+        pub fn spawn<P: MuxPipe>(&self, pipe: P) -> io::Result<Mux> {
+                if !sealed::Sealed::overlapped(&pipe) {
+                        return Err(io::Error::new(io::ErrorKind::Unsupported, split::not_overlapped()));
+                }
+                let handle = borrow_handle(&pipe);
+                let message = handle.get_state(false).map_err(io_error)?.read_mode == ReadMode::Message;
+                let server = handle.get_info().map_err(io_error)?.end == PipeEnd::Server;
+                let raw = handle.as_inner();
+                let shared = Arc::new(Shared {
+                        state: Mutex::new(State {
+                                // like HTTP/2, clients open odd streams and servers even ones
+                                next_id: if server { 2 } else { 1 },
+                                ..State::default()
+                        }),
+                        changed: Condvar::new(),
+                        window: self.window as usize,
+                });
+                let pipe = Arc::new(pipe);
+                let reader = thread::spawn({
+                        let (shared, pipe) = (shared.clone(), pipe.clone());
+                        move || read_loop(&shared, OverlappedIo(&borrow_handle(&*pipe)), message, server)
+                });
+                let writer = thread::spawn({
+                        let (shared, pipe, max_frame_size) = (shared.clone(), pipe.clone(), self.max_frame_size as usize);
+                        move || write_loop(&shared, OverlappedIo(&borrow_handle(&*pipe)), max_frame_size)
+                });
+                Ok(Mux {
+                        shared,
+                        threads: vec![reader, writer],
+                        pipe: raw,
+                        _pipe: pipe,
+                })
+        }
+        /// Wait for a client to connect to `server` and multiplex the connection.
+        pub fn accept(&self, server: DuplexServer) -> Result<Mux, NamedPipeWinError> {
+                if !server.0.overlapped {
+                        return Err(pipe_err::<DuplexServer>(&server.0.path, "Mux::accept()")(split::not_overlapped()));
+                }
+                server.0.handle
+                        .connect_as(server.0.connect_timeout, true)
+                        .map_err(pipe_err::<DuplexServer>(&server.0.path, "connect()"))?;
+                let path = server.0.path.clone();
+                self.spawn(server)
+                        .map_err(|err| NamedPipeWinError::from_msg(format!(r#"Mux {{path: "{}",..}} failed with: {}"#, path.display(), err)))
+        }
+}
+impl Default for MuxBuilder {
+        fn default() -> Self {
+                MuxBuilder {
+                        window: 256 * 1024,
+                        max_frame_size: 16 * 1024,
+                }
+        }
+}
+
+/// Independent bidirectional [`MuxStream`]s over one connected duplex pipe, so a client needs only one pipe instance.
+/// <br>Either side opens streams with [`open`](Self::open), the other side gets them from [`accept`](Self::accept).
+/// Dropping the mux ends the connection and every stream at once, frames still queued are lost,
+/// so flush and drop the streams first for a clean close.
+/// ```no_run
+/// # use windows_named_pipes::*;
+/// # use std::io::Write;
+/// # fn main() -> std::io::Result<()> {
+/// let mux = Mux::new(DuplexClient::builder().overlapped(true).open(std::path::Path::new(r"\\.\pipe\mux"))?)?;
+/// let mut control = mux.open()?;
+/// let mut upload = mux.open()?;
+/// control.write_all(b"upload starting")?;
+/// upload.write_all(&[0u8; 1 << 20])?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Mux {
+        shared: Arc<Shared>,
+        threads: Vec<JoinHandle<()>>,
+        /// raw handle of the pipe, only used to cancel the threads' pending I/O
+        pipe: isize,
+        /// keeps the handle open until the threads are gone
+        _pipe: Arc<dyn AsHandle + Send + Sync>,
+}
+impl Mux {
+        /// Multiplex a connected pipe with the [`MuxBuilder`] defaults.
+        pub fn new<P: MuxPipe>(pipe: P) -> io::Result<Self> { MuxBuilder::new().spawn(pipe) }
+        pub fn builder() -> MuxBuilder { MuxBuilder::new() }
+        /// Open a new stream, the other side gets it from [`accept`](Self::accept).
+        pub fn open(&self) -> io::Result<MuxStream> {
+                let mut state = self.shared.state.lock().unwrap();
+                state.check()?;
+                let id = state.next_id;
+                state.next_id += 2;
+                state.streams.insert(id, Stream::default());
+                state.order.push_back(id);
+                state.control.push_back((OPEN, id, 0));
+                state.control.push_back((WINDOW, id, self.shared.window as u32));
+                self.shared.changed.notify_all();
+                Ok(MuxStream::new(id, self.shared.clone()))
+        }
+        /// Wait for the next stream the other side opens.
+        pub fn accept(&self) -> io::Result<MuxStream> {
+                let mut state = self
+                        .shared
+                        .wait_while(None, |state| state.incoming.is_empty() && state.closed.is_none())?;
+                match state.incoming.pop_front() {
+                        Some(id) => Ok(MuxStream::new(id, self.shared.clone())),
+                        None => Err(state.check().unwrap_err()),
+                }
+        }
+        /// Number of streams still open on either side.
+        pub fn streams(&self) -> usize { self.shared.state.lock().unwrap().streams.len() }
+}
+impl fmt::Debug for Mux {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("Mux")
+                        .field("window", &self.shared.window)
+                        .field("streams", &self.streams())
+                        .finish_non_exhaustive()
+        }
+}
+impl Drop for Mux {
+        fn drop(&mut self) {
+                self.shared.fail(io::ErrorKind::ConnectionAborted);
+                // the reader blocks in a read and the writer may block in a write, cancel until both have noticed
+                for thread in self.threads.drain(..) {
+                        cancel_io_until(self.pipe, || thread.is_finished());
+                        let _ = thread.join();
+                }
+        }
+}
+
+/// One logical stream of a [`Mux`], read and written like a pipe.
+/// <br>Reads return 0 once the other side has closed the stream and everything before has been read.
+/// Dropping the stream closes it after sending what was written, reads on the other side then end.
+pub struct MuxStream {
+        id: u32,
+        shared: Arc<Shared>,
+        read_timeout: Option<Duration>,
+        write_timeout: Option<Duration>,
+}
+impl MuxStream {
+        fn new(id: u32, shared: Arc<Shared>) -> Self {
+                Self {
+                        id,
+                        shared,
+                        read_timeout: None,
+                        write_timeout: None,
+                }
+        }
+        /// Odd for streams the client opened, even for the server's.
+        pub fn id(&self) -> u32 { self.id }
+        /// Stop writing, the other side reads to the end of what was written and then gets 0.
+        /// <br>Reading goes on until the other side closes too.
+        pub fn close_write(&self) {
+                let mut state = self.shared.state.lock().unwrap();
+                state.streams.get_mut(&self.id).unwrap().closing = true;
+                self.shared.changed.notify_all();
+        }
+        /// Abort the stream in both directions, dropping unread and unsent data.
+        /// Reads and writes on both sides fail with [`ConnectionReset`](io::ErrorKind::ConnectionReset).
+        pub fn reset(&self) {
+                let mut guard = self.shared.state.lock().unwrap();
+                let state = &mut *guard;
+                let stream = state.streams.get_mut(&self.id).unwrap();
+                if !stream.reset {
+                        stream.reset();
+                        state.control.push_back((RESET, self.id, 0));
+                        self.shared.changed.notify_all();
+                }
+        }
+        pub fn read_timeout(&self) -> Option<Duration> { self.read_timeout }
+        pub fn write_timeout(&self) -> Option<Duration> { self.write_timeout }
+        /// set how long a read may block, `None` blocks forever.
+        pub fn set_read_timeout(&mut self, dur: Option<Duration>) -> io::Result<()> {
+                check_timeout(dur)?;
+                self.read_timeout = dur;
+                Ok(())
+        }
+        /// set how long a write or flush may block, `None` blocks forever.
+        pub fn set_write_timeout(&mut self, dur: Option<Duration>) -> io::Result<()> {
+                check_timeout(dur)?;
+                self.write_timeout = dur;
+                Ok(())
+        }
+}
+impl Read for MuxStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if buf.is_empty() {
+                        return Ok(0);
+                }
+                let id = self.id;
+                let mut state = self.shared.wait_while(self.read_timeout, |state| {
+                        let stream = &state.streams[&id];
+                        stream.received.is_empty() && !stream.eof && !stream.reset && state.closed.is_none()
+                })?;
+                let (window, state) = (self.shared.window, &mut *state);
+                let stream = state.streams.get_mut(&id).unwrap();
+                if stream.reset {
+                        return Err(io::Error::new(io::ErrorKind::ConnectionReset, "mux stream was reset"));
+                }
+                if stream.received.is_empty() {
+                        return match stream.eof {
+                                true => Ok(0),
+                                false => Err(state.check().unwrap_err()),
+                        };
+                }
+                let read = buf.len().min(stream.received.len());
+                for (dst, src) in buf.iter_mut().zip(stream.received.drain(..read)) {
+                        *dst = src;
+                }
+                stream.consumed += read;
+                // grant the window back in chunks rather than after every read
+                if stream.consumed >= window / 2 && !stream.eof {
+                        let consumed = ::std::mem::take(&mut stream.consumed);
+                        state.control.push_back((WINDOW, id, consumed as u32));
+                        self.shared.changed.notify_all();
+                }
+                Ok(read)
+        }
+}
+impl Write for MuxStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if buf.is_empty() {
+                        return Ok(0);
+                }
+                let (id, window) = (self.id, self.shared.window);
+                let mut state = self.shared.wait_while(self.write_timeout, |state| {
+                        let stream = &state.streams[&id];
+                        stream.unsent.len() >= window && !stream.reset && !stream.closing && state.closed.is_none()
+                })?;
+                state.check()?;
+                let stream = state.streams.get_mut(&id).unwrap();
+                if stream.reset {
+                        return Err(io::Error::new(io::ErrorKind::ConnectionReset, "mux stream was reset"));
+                }
+                if stream.closing {
+                        return Err(io::Error::new(io::ErrorKind::BrokenPipe, "mux stream is closed for writing"));
+                }
+                let written = buf.len().min(window - stream.unsent.len());
+                stream.unsent.extend(&buf[..written]);
+                self.shared.changed.notify_all();
+                Ok(written)
+        }
+        /// Wait until everything written has been handed to the pipe.
+        fn flush(&mut self) -> io::Result<()> {
+                let id = self.id;
+                let state = self.shared.wait_while(self.write_timeout, |state| {
+                        let stream = &state.streams[&id];
+                        !stream.unsent.is_empty() && !stream.reset && state.closed.is_none()
+                })?;
+                match state.streams[&id].reset {
+                        true => Err(io::Error::new(io::ErrorKind::ConnectionReset, "mux stream was reset")),
+                        false if !state.streams[&id].unsent.is_empty() => state.check(),
+                        false => Ok(()),
+                }
+        }
+}
+impl fmt::Debug for MuxStream {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("MuxStream")
+                        .field("id", &self.id)
+                        .field("read_timeout", &self.read_timeout)
+                        .field("write_timeout", &self.write_timeout)
+                        .finish_non_exhaustive()
+        }
+}
+impl Drop for MuxStream {
+        fn drop(&mut self) {
+                let mut guard = self.shared.state.lock().unwrap();
+                let state = &mut *guard;
+                let stream = state.streams.get_mut(&self.id).unwrap();
+                stream.detached = true;
+                stream.closing = true;
+                // nobody reads anymore, give the unread data back so the other side can go on writing until it closes
+                let unread = stream.received.len() + ::std::mem::take(&mut stream.consumed);
+                stream.received.clear();
+                if unread > 0 && !stream.eof && !stream.reset {
+                        state.control.push_back((WINDOW, self.id, unread as u32));
+                }
+                state.prune(self.id);
+                self.shared.changed.notify_all();
+        }
+}
+
+struct Shared {
+        state: Mutex<State>,
+        /// notified on every change of the state
+        changed: Condvar,
+        window: usize,
+}
+impl Shared {
+        fn wait_while(&self, timeout: Option<Duration>, condition: impl FnMut(&mut State) -> bool) -> io::Result<MutexGuard<'_, State>> {
+                let state = self.state.lock().unwrap();
+                match timeout {
+                        None => Ok(self.changed.wait_while(state, condition).unwrap()),
+                        Some(timeout) => match self.changed.wait_timeout_while(state, timeout, condition).unwrap() {
+                                (_, res) if res.timed_out() => Err(io::Error::new(io::ErrorKind::TimedOut, "mux stream timed out")),
+                                (state, _) => Ok(state),
+                        },
+                }
+        }
+        /// end the connection, the first reason is kept
+        fn fail(&self, kind: io::ErrorKind) {
+                let mut state = self.state.lock().unwrap();
+                state.closed.get_or_insert(kind);
+                self.changed.notify_all();
+        }
+}
+
+#[derive(Default)]
+struct State {
+        streams: HashMap<u32, Stream>,
+        /// round-robin order in which streams get to send
+        order: VecDeque<u32>,
+        /// kind, stream id and window increment of frames without data, sent before any data
+        control: VecDeque<(u8, u32, u32)>,
+        /// streams opened by the other side and not accepted yet
+        incoming: VecDeque<u32>,
+        next_id: u32,
+        /// why the connection ended
+        closed: Option<io::ErrorKind>,
+}
+impl State {
+        fn check(&self) -> io::Result<()> {
+                match self.closed {
+                        Some(kind) => Err(io::Error::new(kind, "mux connection is closed")),
+                        None => Ok(()),
+                }
+        }
+        /// forget a stream once neither side can use it anymore
+        fn prune(&mut self, id: u32) {
+                if let Some(stream) = self.streams.get(&id) {
+                        if stream.detached && (stream.reset || (stream.close_sent && stream.eof)) {
+                                self.streams.remove(&id);
+                                self.order.retain(|other| *other != id);
+                        }
+                }
+        }
+        /// the next frame to send, control frames first and then data from the streams in turn
+        fn next_frame(&mut self, frame: &mut Vec<u8>, max_frame_size: usize) -> bool {
+                frame.clear();
+                if let Some((kind, id, increment)) = self.control.pop_front() {
+                        match kind {
+                                WINDOW => {
+                                        put_header(frame, kind, id, 4);
+                                        frame.extend_from_slice(&increment.to_be_bytes());
+                                }
+                                _ => put_header(frame, kind, id, 0),
+                        }
+                        return true;
+                }
+                for _ in 0..self.order.len() {
+                        let id = self.order.pop_front().unwrap();
+                        self.order.push_back(id);
+                        let stream = self.streams.get_mut(&id).unwrap();
+                        if stream.reset {
+                                continue;
+                        }
+                        if !stream.unsent.is_empty() && stream.send_window > 0 {
+                                let len = stream.unsent.len().min(stream.send_window).min(max_frame_size);
+                                put_header(frame, DATA, id, len);
+                                frame.extend(stream.unsent.drain(..len));
+                                stream.send_window -= len;
+                                return true;
+                        }
+                        if stream.closing && stream.unsent.is_empty() && !stream.close_sent {
+                                stream.close_sent = true;
+                                put_header(frame, CLOSE, id, 0);
+                                self.prune(id);
+                                return true;
+                        }
+                }
+                false
+        }
+        /// sort a received frame into its stream
+        fn receive(&mut self, kind: u8, id: u32, payload: &[u8], window: usize, server: bool) -> io::Result<()> {
+                let invalid = |msg: &str| {
+                        Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("mux protocol error on stream {}: {}", id, msg),
+                        ))
+                };
+                if kind == OPEN {
+                        // the other side opens streams of the other parity
+                        if id.is_multiple_of(2) == server || self.streams.contains_key(&id) {
+                                return invalid("invalid id for a new stream");
+                        }
+                        self.streams.insert(id, Stream::default());
+                        self.order.push_back(id);
+                        self.incoming.push_back(id);
+                        self.control.push_back((WINDOW, id, window as u32));
+                        return Ok(());
+                }
+                // frames for forgotten streams were sent before the other side learned about it
+                let stream = match self.streams.get_mut(&id) {
+                        Some(stream) if !stream.reset => stream,
+                        _ => return Ok(()),
+                };
+                match kind {
+                        DATA if stream.eof => return invalid("data after close"),
+                        DATA if stream.received.len() + stream.consumed + payload.len() > window => return invalid("data beyond the window"),
+                        // nobody will read it, so grant the window right back
+                        DATA if stream.detached => self.control.push_back((WINDOW, id, payload.len() as u32)),
+                        DATA => stream.received.extend(payload),
+                        WINDOW => match payload.try_into() {
+                                Ok(increment) => stream.send_window += u32::from_be_bytes(increment) as usize,
+                                Err(_) => return invalid("malformed window update"),
+                        },
+                        CLOSE => stream.eof = true,
+                        RESET => stream.reset(),
+                        _ => return invalid("unknown frame kind"),
+                }
+                self.prune(id);
+                Ok(())
+        }
+}
+
+#[derive(Default)]
+struct Stream {
+        /// received and not read yet
+        received: VecDeque<u8>,
+        /// read since the last window update
+        consumed: usize,
+        /// the other side closed its sending side
+        eof: bool,
+        /// written and not sent yet
+        unsent: VecDeque<u8>,
+        /// bytes the other side is ready to receive
+        send_window: usize,
+        /// no more writes, CLOSE goes out once `unsent` is empty
+        closing: bool,
+        close_sent: bool,
+        reset: bool,
+        /// the `MuxStream` is gone, received data is dropped
+        detached: bool,
+}
+impl Stream {
+        fn reset(&mut self) {
+                self.reset = true;
+                self.received.clear();
+                self.unsent.clear();
+        }
+}
+
+fn put_header(frame: &mut Vec<u8>, kind: u8, id: u32, len: usize) {
+        frame.push(kind);
+        frame.extend_from_slice(&id.to_be_bytes());
+        frame.extend_from_slice(&(len as u32).to_be_bytes());
+}
+
+fn read_loop(shared: &Shared, mut reader: OverlappedIo<'_>, message: bool, server: bool) {
+        let err = loop {
+                let frame = match message {
+                        true => read_message(&mut reader, HEADER_LEN + shared.window),
+                        false => {
+                                let mut header = [0u8; HEADER_LEN];
+                                reader.read_exact(&mut header).and_then(|()| {
+                                        let len = u32::from_be_bytes(header[5..].try_into().unwrap()) as usize;
+                                        if len > shared.window {
+                                                return Err(io::Error::new(io::ErrorKind::InvalidData, "mux frame is larger than the window"));
+                                        }
+                                        let mut frame = header.to_vec();
+                                        frame.resize(HEADER_LEN + len, 0);
+                                        reader.read_exact(&mut frame[HEADER_LEN..]).map(|()| frame)
+                                })
+                        }
+                };
+                let frame = match frame {
+                        // a message holds exactly one frame
+                        Ok(frame)
+                                if frame.len() >= HEADER_LEN
+                                        && u32::from_be_bytes(frame[5..9].try_into().unwrap()) as usize == frame.len() - HEADER_LEN =>
+                        {
+                                frame
+                        }
+                        Ok(_) => break io::ErrorKind::InvalidData,
+                        Err(err) => break err.kind(),
+                };
+                let (kind, id) = (frame[0], u32::from_be_bytes(frame[1..5].try_into().unwrap()));
+                let mut state = shared.state.lock().unwrap();
+                if let Err(err) = state.receive(kind, id, &frame[HEADER_LEN..], shared.window, server) {
+                        break err.kind();
+                }
+                shared.changed.notify_all();
+        };
+        shared.fail(err);
+}
+
+fn write_loop(shared: &Shared, mut writer: OverlappedIo<'_>, max_frame_size: usize) {
+        let mut frame = Vec::new();
+        loop {
+                {
+                        let mut state = shared.state.lock().unwrap();
+                        while state.closed.is_none() && !state.next_frame(&mut frame, max_frame_size) {
+                                state = shared.changed.wait(state).unwrap();
+                        }
+                        if state.closed.is_some() {
+                                return;
+                        }
+                }
+                // room in `unsent` for blocked writes
+                shared.changed.notify_all();
+                if let Err(err) = write_message(&mut writer, &frame) {
+                        return shared.fail(err.kind());
+                }
+        }
+}
@@ -0,0 +1,31 @@
+//! Connected server and client ends of a fresh pipe, for tests and plumbing inside one process.
+
+use super::*;
+
+/// A duplex message pipe under a new random name in [`LOCAL_PREFIX`], see [`message_pair`].
+pub fn pipe_pair() -> Result<(DuplexServer, DuplexClient), NamedPipeWinError> { message_pair() }
+
+/// A duplex pipe of message type with both ends in message read mode.
+/// <br>The name is random and the server is created with `create_new(true)`, so no other process can have taken it first.
+/// The client has already opened the pipe, so the server instance is connected to it and `connect()` returns right away.
+/// <br>Both ends are opened with `overlapped(true)` so they can be split or multiplexed, don't hand them to std as a file.
+pub fn message_pair() -> Result<(DuplexServer, DuplexClient), NamedPipeWinError> { pair(TypeMode::Message, true) }
+
+/// A duplex pipe of byte type with both ends in byte read mode, otherwise like [`message_pair`].
+pub fn byte_pair() -> Result<(DuplexServer, DuplexClient), NamedPipeWinError> { pair(TypeMode::Byte, true) }
+
+pub(crate) fn pair<Server: ServerImpl, Client: ClientImpl>(type_mode: TypeMode, overlapped: bool) -> Result<(Server, Client), NamedPipeWinError> {
+        let read_mode = match type_mode {
+                TypeMode::Byte => ReadMode::Byte,
+                TypeMode::Message => ReadMode::Message,
+        };
+        let path = unique_path();
+        let server = ServerBuilder::<Server>::new()
+                .type_mode(type_mode)
+                .read_mode(read_mode)
+                .create_new(true)
+                .overlapped(overlapped)
+                .open(&path)?;
+        let client = ClientBuilder::<Client>::new().read_mode(read_mode).overlapped(overlapped).open(&path)?;
+        Ok((server, client))
+}
@@ -0,0 +1,51 @@
+//! Wait for several pipes at once.
+//! Synchronous pipes have no readiness notification, so this peeks each pipe in turn and sleeps in between.
+
+use super::*;
+
+/// A pipe end to wait on with [`poll`].
+pub struct PollPipe<'a> {
+        handle: BorrowedHandle<'a>,
+        readable: bool,
+}
+impl<'a> PollPipe<'a> {
+        pub fn new<P: AsHandle>(pipe: &'a P) -> Self {
+                Self {
+                        handle: pipe.as_handle(),
+                        readable: false,
+                }
+        }
+        /// Data is waiting, or the other end has closed the pipe so a read fails right away.
+        pub fn readable(&self) -> bool { self.readable }
+}
+
+/// Wait until at least one of the pipes is readable or the timeout elapses, `None` waits forever.
+/// <br>Returns the number of readable pipes, 0 on timeout. A server still waiting for a client is never readable.
+pub fn poll(pipes: &mut [PollPipe<'_>], timeout: Option<Duration>) -> io::Result<usize> {
+        let deadline = timeout.map(|timeout| ::std::time::Instant::now() + timeout);
+        let mut backoff = Duration::from_millis(1);
+        loop {
+                let mut ready = 0;
+                for pipe in pipes.iter_mut() {
+                        let handle = ManuallyDrop::new(unsafe { UnsafeHandle::from_raw_handle(pipe.handle.as_raw_handle()) });
+                        pipe.readable = match handle.available() {
+                                Ok(available) => available > 0,
+                                Err(err) => WIN32_ERROR::from_error(&err) != Some(ERROR_PIPE_LISTENING),
+                        };
+                        ready += pipe.readable as usize;
+                }
+                if ready > 0 {
+                        return Ok(ready);
+                }
+                let mut sleep = backoff;
+                if let Some(deadline) = deadline {
+                        let now = ::std::time::Instant::now();
+                        if now >= deadline {
+                                return Ok(0);
+                        }
+                        sleep = sleep.min(deadline - now);
+                }
+                thread::sleep(sleep);
+                backoff = (backoff * 2).min(Duration::from_millis(16));
+        }
+}
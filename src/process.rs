@@ -0,0 +1,22 @@
+//! Named pipes as stdio of child processes.
+//! [`Stdio`] owns a handle and [`Command::spawn`](std::process::Command::spawn) hands the child an inheritable copy,
+//! so unlike `set_stdin`/`set_stdout` the current process's std handles are never touched.
+//! <br>Both ends are synchronous: the child does plain blocking I/O on its end, which an overlapped handle doesn't support.
+//! <br>An existing connection goes to a child with `try_stdio()` on a client or `into_stdio()` on a connected server.
+
+use super::*;
+use ::std::process::Stdio;
+
+/// A byte pipe for a child's stdin: the [`Stdio`] is the reading server end, the parent writes into the client.
+/// <br>Dropping the client once the child has been spawned gives the child end of file.
+pub fn stdin_pipe() -> Result<(InboundClient, Stdio), NamedPipeWinError> {
+        let (server, client) = pair::<InboundServer, InboundClient>(TypeMode::Byte, false)?;
+        Ok((client, Stdio::from(OwnedHandle::from(server))))
+}
+
+/// A byte pipe for a child's stdout or stderr: the [`Stdio`] is the writing server end, the parent reads from the client.
+/// <br>Reads fail with [`BrokenPipe`](io::ErrorKind::BrokenPipe) once the child and the spawning `Command` are gone.
+pub fn stdout_pipe() -> Result<(OutboundClient, Stdio), NamedPipeWinError> {
+        let (server, client) = pair::<OutboundServer, OutboundClient>(TypeMode::Byte, false)?;
+        Ok((client, Stdio::from(OwnedHandle::from(server))))
+}
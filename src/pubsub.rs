@@ -0,0 +1,306 @@
+//! Topic based publish/subscribe over byte pipes.
+//! Every client gets a queue of the messages published to its topics, a writer drains it into the pipe while a reader
+//! takes subscribe, unsubscribe and publish commands. A subscriber too slow to keep up fills its queue, what happens
+//! then is the server's [`Overflow`] policy.
+//! <br>Frames are [`Framed`] with the default codec, holding a command byte, the topic as u16 big endian length and UTF-8,
+//! and the payload.
+
+use super::*;
+use ::std::{
+        collections::{HashMap, HashSet, VecDeque},
+        fmt,
+        sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+const SUBSCRIBE: u8 = 1;
+const UNSUBSCRIBE: u8 = 2;
+const PUBLISH: u8 = 3;
+/// a published message on its way to a subscriber
+const MESSAGE: u8 = 4;
+
+/// What a [`PubSubServer`] does with a message for a subscriber whose queue is full.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Overflow {
+        /// drop the oldest queued message to make room
+        DropOldest,
+        /// drop the new message
+        DropNewest,
+        /// disconnect the subscriber, it gets no more messages
+        Disconnect,
+}
+
+/// Builder for a [`PubSubServer`].
+#[derive(Debug)]
+pub struct PubSubServerBuilder {
+        pub(crate) max_clients: usize,
+        /// messages queued per subscriber
+        pub(crate) queue_size: usize,
+        pub(crate) overflow: Overflow,
+}
+impl PubSubServerBuilder {
+        /// Clients connected at once, each one gets its own pipe instance and worker thread.
+        pub fn max_clients(&mut self, max_clients: usize) -> &mut Self {
+                self.max_clients = max_clients.max(1);
+                self
+        }
+        /// Messages queued for a subscriber before the [`Overflow`] policy applies.
+        pub fn queue_size(&mut self, queue_size: usize) -> &mut Self {
+                self.queue_size = queue_size.max(1);
+                self
+        }
+        pub fn overflow(&mut self, overflow: Overflow) -> &mut Self {
+                self.overflow = overflow;
+                self
+        }
+        pub fn new() -> Self { Self::default() }
+        /// Create the pipe instances and start serving subscribers.
+        pub fn spawn(&self, path: &Path) -> Result<PubSubServer, NamedPipeWinError> {
+                let hub = Arc::new(Hub {
+                        subscribers: Mutex::new(HashMap::new()),
+                        next_id: AtomicU64::new(0),
+                        queue_size: self.queue_size,
+                        overflow: self.overflow,
+                        shutdown: AtomicBool::new(false),
+                });
+                let mut pipe = ServerBuilder::<DuplexServer>::new();
+                // every subscriber is read and written from two threads
+                pipe.type_mode(TypeMode::Byte).read_mode(ReadMode::Byte).overlapped(true);
+                let server = PipeServer::builder()
+                        .pipe(pipe)
+                        .instances(self.max_clients)
+                        .workers(self.max_clients)
+                        .max_clients(self.max_clients)
+                        .spawn(path, {
+                                let hub = hub.clone();
+                                move |pipe| serve(&hub, pipe)
+                        })?;
+                Ok(PubSubServer { hub, server: Some(server) })
+        }
+}
+impl Default for PubSubServerBuilder {
+        fn default() -> Self {
+                PubSubServerBuilder {
+                        max_clients: 4,
+                        queue_size: 64,
+                        overflow: Overflow::DropOldest,
+                }
+        }
+}
+
+/// Server fanning out messages published to topics, by itself or by clients, to the [`PubSubClient`]s subscribed to them.
+/// <br>Dropping it disconnects every subscriber and waits for their handlers.
+pub struct PubSubServer {
+        hub: Arc<Hub>,
+        server: Option<PipeServer>,
+}
+impl PubSubServer {
+        pub fn builder() -> PubSubServerBuilder { PubSubServerBuilder::new() }
+        /// Queue `message` for every subscriber of `topic`, returns how many got it queued.
+        pub fn publish(&self, topic: &str, message: &[u8]) -> io::Result<usize> { self.hub.publish(topic, message) }
+        /// Number of connected clients subscribed to `topic`.
+        pub fn subscribers(&self, topic: &str) -> usize {
+                let subscribers = self.hub.subscribers.lock().unwrap();
+                subscribers
+                        .values()
+                        .filter(|subscriber| subscriber.queue.lock().unwrap().topics.contains(topic))
+                        .count()
+        }
+        /// Number of messages queued for all subscribers, not yet taken by their writers.
+        pub fn queued(&self) -> usize {
+                let subscribers = self.hub.subscribers.lock().unwrap();
+                subscribers
+                        .values()
+                        .map(|subscriber| subscriber.queue.lock().unwrap().messages.len())
+                        .sum()
+        }
+        /// Disconnect every subscriber and wait for their handlers.
+        pub fn shutdown(self) {}
+}
+impl fmt::Debug for PubSubServer {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("PubSubServer")
+                        .field("queue_size", &self.hub.queue_size)
+                        .field("overflow", &self.hub.overflow)
+                        .field("clients", &self.hub.subscribers.lock().unwrap().len())
+                        .finish()
+        }
+}
+impl Drop for PubSubServer {
+        fn drop(&mut self) {
+                // handlers started after this see the flag and close right away
+                self.hub.shutdown.store(true, Ordering::SeqCst);
+                for subscriber in self.hub.subscribers.lock().unwrap().values() {
+                        subscriber.close();
+                }
+                drop(self.server.take());
+        }
+}
+
+struct Hub {
+        subscribers: Mutex<HashMap<u64, Arc<Subscriber>>>,
+        next_id: AtomicU64,
+        queue_size: usize,
+        overflow: Overflow,
+        shutdown: AtomicBool,
+}
+impl Hub {
+        fn publish(&self, topic: &str, message: &[u8]) -> io::Result<usize> {
+                // framed once, every subscriber's writer sends the same bytes
+                let body = encode(MESSAGE, topic, message)?;
+                let codec = FrameCodec::default();
+                let mut frame = codec.encode_prefix(body.len())?[..codec.prefix_width.len()].to_vec();
+                frame.extend_from_slice(&body);
+                let frame: Arc<[u8]> = frame.into();
+                let mut queued = 0;
+                for subscriber in self.subscribers.lock().unwrap().values() {
+                        let mut queue = subscriber.queue.lock().unwrap();
+                        if queue.closed || !queue.topics.contains(topic) {
+                                continue;
+                        }
+                        if queue.messages.len() >= self.queue_size {
+                                match self.overflow {
+                                        Overflow::DropOldest => drop(queue.messages.pop_front()),
+                                        Overflow::DropNewest => continue,
+                                        Overflow::Disconnect => {
+                                                queue.closed = true;
+                                                queue.messages.clear();
+                                                subscriber.ready.notify_all();
+                                                continue;
+                                        }
+                                }
+                        }
+                        queue.messages.push_back(frame.clone());
+                        subscriber.ready.notify_all();
+                        queued += 1;
+                }
+                Ok(queued)
+        }
+}
+
+#[derive(Default)]
+struct Subscriber {
+        queue: Mutex<Queue>,
+        ready: Condvar,
+}
+impl Subscriber {
+        fn close(&self) {
+                self.queue.lock().unwrap().closed = true;
+                self.ready.notify_all();
+        }
+}
+#[derive(Default)]
+struct Queue {
+        topics: HashSet<String>,
+        /// framed messages waiting for the writer
+        messages: VecDeque<Arc<[u8]>>,
+        /// the client is being disconnected
+        closed: bool,
+}
+
+fn serve(hub: &Hub, pipe: &mut ConnectedDuplexServer<'_>) -> io::Result<()> {
+        let id = hub.next_id.fetch_add(1, Ordering::Relaxed);
+        let subscriber = Arc::new(Subscriber::default());
+        hub.subscribers.lock().unwrap().insert(id, subscriber.clone());
+        if hub.shutdown.load(Ordering::SeqCst) {
+                subscriber.close();
+        }
+        let raw = pipe.as_raw_handle() as isize;
+        let (read, write) = pipe.split()?;
+        let res = thread::scope(|scope| {
+                let reader = scope.spawn(|| {
+                        let _ = read_commands(hub, &subscriber, read);
+                        subscriber.close();
+                });
+                let res = write_messages(&subscriber, write);
+                // the writer stopped first on overflow, shutdown or a write error, the reader is still blocked in a read
+                subscriber.close();
+                cancel_io_until(raw, || reader.is_finished());
+                res
+        });
+        hub.subscribers.lock().unwrap().remove(&id);
+        res
+}
+fn read_commands(hub: &Hub, subscriber: &Subscriber, read: ReadHalf<'_>) -> io::Result<()> {
+        let mut framed = Framed::new(read);
+        loop {
+                let frame = framed.recv_frame()?;
+                let (command, topic, payload) = decode(&frame)?;
+                match command {
+                        SUBSCRIBE => drop(subscriber.queue.lock().unwrap().topics.insert(topic.to_string())),
+                        UNSUBSCRIBE => drop(subscriber.queue.lock().unwrap().topics.remove(topic)),
+                        PUBLISH => drop(hub.publish(topic, payload)?),
+                        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown pubsub command")),
+                }
+        }
+}
+fn write_messages(subscriber: &Subscriber, mut write: WriteHalf<'_>) -> io::Result<()> {
+        loop {
+                let frame = {
+                        let queue = subscriber.queue.lock().unwrap();
+                        let mut queue = subscriber
+                                .ready
+                                .wait_while(queue, |queue| queue.messages.is_empty() && !queue.closed)
+                                .unwrap();
+                        match queue.closed {
+                                true => return Ok(()),
+                                false => queue.messages.pop_front().unwrap(),
+                        }
+                };
+                write.write_all(&frame)?;
+        }
+}
+
+fn encode(command: u8, topic: &str, payload: &[u8]) -> io::Result<Vec<u8>> {
+        let len = u16::try_from(topic.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "topic is longer than 65535 bytes"))?;
+        let mut frame = Vec::with_capacity(3 + topic.len() + payload.len());
+        frame.push(command);
+        frame.extend_from_slice(&len.to_be_bytes());
+        frame.extend_from_slice(topic.as_bytes());
+        frame.extend_from_slice(payload);
+        Ok(frame)
+}
+fn decode(frame: &[u8]) -> io::Result<(u8, &str, &[u8])> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed pubsub frame");
+        let (&command, rest) = frame.split_first().ok_or_else(invalid)?;
+        let len = u16::from_be_bytes(rest.get(..2).ok_or_else(invalid)?.try_into().unwrap()) as usize;
+        let topic = rest.get(2..2 + len).ok_or_else(invalid)?;
+        let topic = ::std::str::from_utf8(topic).map_err(|_| invalid())?;
+        Ok((command, topic, &rest[2 + len..]))
+}
+
+/// Client of a [`PubSubServer`], subscribing to topics and publishing to them.
+/// <br>A timeout set on the pipe may interrupt a frame, so the client is best dropped after a read or write times out.
+pub struct PubSubClient {
+        framed: Framed<DuplexClient>,
+}
+impl PubSubClient {
+        /// Open the server's pipe, failing right away if every instance is busy.
+        pub fn open(path: &Path) -> Result<Self, NamedPipeWinError> {
+                let client = ClientBuilder::<DuplexClient>::new().read_mode(ReadMode::Byte).open(path)?;
+                Ok(Self { framed: Framed::new(client) })
+        }
+        /// Talk to the server over an already opened client, which is switched to byte read mode.
+        pub fn new(client: DuplexClient) -> io::Result<Self> {
+                client.set_read_mode(ReadMode::Byte)?;
+                Ok(Self { framed: Framed::new(client) })
+        }
+        pub fn subscribe(&mut self, topic: &str) -> io::Result<()> { self.framed.send_frame(&encode(SUBSCRIBE, topic, &[])?) }
+        pub fn unsubscribe(&mut self, topic: &str) -> io::Result<()> { self.framed.send_frame(&encode(UNSUBSCRIBE, topic, &[])?) }
+        /// Publish `message` to the subscribers of `topic`, including this client if it is one.
+        pub fn publish(&mut self, topic: &str, message: &[u8]) -> io::Result<()> { self.framed.send_frame(&encode(PUBLISH, topic, message)?) }
+        /// Wait for the next message of a subscribed topic, returns its topic and payload.
+        /// <br>Fails once the server disconnected the client, after the messages it had sent before.
+        pub fn recv(&mut self) -> io::Result<(String, Vec<u8>)> {
+                let frame = self.framed.recv_frame()?;
+                match decode(&frame)? {
+                        (MESSAGE, topic, payload) => Ok((topic.to_string(), payload.to_vec())),
+                        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected pubsub command from the server")),
+                }
+        }
+        pub fn get_ref(&self) -> &DuplexClient { self.framed.get_ref() }
+        pub fn get_mut(&mut self) -> &mut DuplexClient { self.framed.get_mut() }
+        pub fn into_inner(self) -> DuplexClient { self.framed.into_inner() }
+}
+impl fmt::Debug for PubSubClient {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.debug_struct("PubSubClient").field("path", &self.get_ref().path()).finish() }
+}
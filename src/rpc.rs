@@ -0,0 +1,349 @@
+//! Request/response calls by method name over duplex pipes.
+//! Every request carries an id that its response echoes, so a client can have several calls outstanding and still
+//! match each reply to its caller. Params and results are encoded with the [`Format`] of the connection and embedded in
+//! the request or response as they are: as raw JSON in [`Json`](Format::Json), as a byte string in the binary formats.
+
+use super::*;
+use ::std::{
+        collections::HashMap,
+        error::Error,
+        fmt,
+        sync::{
+                atomic::{AtomicU64, Ordering},
+                mpsc,
+        },
+        thread::JoinHandle,
+};
+use serde::{
+        de::{DeserializeOwned, Deserializer},
+        ser::Serializer,
+        Deserialize, Serialize,
+};
+
+#[derive(Serialize, Deserialize)]
+struct Request {
+        id: u64,
+        method: String,
+        params: Payload,
+}
+#[derive(Serialize, Deserialize)]
+struct Response {
+        id: u64,
+        result: Result<Payload, RemoteError>,
+}
+
+/// Params or result already encoded in the connection's format, so they are not encoded a second time as a byte array.
+/// <br>JSON is the only human readable format, there they are the raw value itself.
+struct Payload(Vec<u8>);
+impl Serialize for Payload {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                #[cfg(feature = "json")]
+                if serializer.is_human_readable() {
+                        let raw: &serde_json::value::RawValue = serde_json::from_slice(&self.0).map_err(serde::ser::Error::custom)?;
+                        return raw.serialize(serializer);
+                }
+                serde_bytes::Bytes::new(&self.0).serialize(serializer)
+        }
+}
+impl<'de> Deserialize<'de> for Payload {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                #[cfg(feature = "json")]
+                if deserializer.is_human_readable() {
+                        let raw = Box::<serde_json::value::RawValue>::deserialize(deserializer)?;
+                        return Ok(Payload(raw.get().as_bytes().to_vec()));
+                }
+                serde_bytes::ByteBuf::deserialize(deserializer).map(|bytes| Payload(bytes.into_vec()))
+        }
+}
+
+/// Error returned by a remote handler, or by the [`Router`] when it could not run one.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct RemoteError {
+        pub code: i32,
+        pub message: String,
+}
+impl RemoteError {
+        /// no handler is routed for the method
+        pub const METHOD_NOT_FOUND: i32 = -32601;
+        /// the params did not deserialize into what the handler takes
+        pub const INVALID_PARAMS: i32 = -32602;
+        /// the result of the handler did not serialize
+        pub const INTERNAL_ERROR: i32 = -32603;
+        pub fn new(code: i32, message: impl Into<String>) -> Self {
+                Self {
+                        code,
+                        message: message.into(),
+                }
+        }
+}
+impl fmt::Display for RemoteError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "remote error {}: {}", self.code, self.message) }
+}
+impl Error for RemoteError {}
+
+/// Failed call of an [`RpcClient`].
+#[derive(Debug)]
+pub enum RpcError {
+        /// the call did not get a response: the pipe failed or closed, the params or result did not (de)serialize,
+        /// or the timeout elapsed with [`TimedOut`](io::ErrorKind::TimedOut)
+        Io(io::Error),
+        /// the server answered with an error
+        Remote(RemoteError),
+}
+impl fmt::Display for RpcError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                        RpcError::Io(err) => write!(f, "rpc call failed: {}", err),
+                        RpcError::Remote(err) => err.fmt(f),
+                }
+        }
+}
+impl Error for RpcError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+                match self {
+                        RpcError::Io(err) => Some(err),
+                        RpcError::Remote(err) => Some(err),
+                }
+        }
+}
+impl From<io::Error> for RpcError {
+        fn from(err: io::Error) -> Self { RpcError::Io(err) }
+}
+impl From<RpcError> for io::Error {
+        fn from(err: RpcError) -> Self {
+                match err {
+                        RpcError::Io(err) => err,
+                        RpcError::Remote(err) => io::Error::other(err),
+                }
+        }
+}
+
+type Method = dyn Fn(&[u8]) -> Result<Vec<u8>, RemoteError> + Send + Sync;
+type ErrorHook = dyn Fn(io::Error) + Send + Sync;
+
+/// Maps method names to handlers and answers the requests of connected clients.
+/// <br>To serve several clients at once, share it with the handler of a [`PipeServer`]:
+/// ```no_run
+/// # use windows_named_pipes::*;
+/// # use std::sync::Arc;
+/// # fn main() -> Result<(), NamedPipeWinError> {
+/// let mut router = Router::new(Format::Json);
+/// router.route("add", |(a, b): (i64, i64)| Ok(a + b));
+/// let router = Arc::new(router);
+/// let server = PipeServer::builder().spawn(std::path::Path::new(r"\\.\pipe\rpc"), move |pipe| router.serve_connection(pipe))?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Router {
+        format: Format,
+        codec: FrameCodec,
+        methods: HashMap<String, Box<Method>>,
+        /// gets the errors that dropped a client in `serve`
+        on_error: Option<Box<ErrorHook>>,
+}
+impl Router {
+        pub fn new(format: Format) -> Self {
+                Self {
+                        format,
+                        codec: FrameCodec::default(),
+                        methods: HashMap::new(),
+                        on_error: None,
+                }
+        }
+        /// Frame format on byte read mode pipes and max request size, see [`TypedChannel::with_codec`].
+        pub fn codec(&mut self, codec: FrameCodec) -> &mut Self {
+                self.codec = codec;
+                self
+        }
+        /// Called with the error of every client [`serve`](Self::serve) drops, without it these errors are lost.
+        pub fn on_error<F: Fn(io::Error) + Send + Sync + 'static>(&mut self, on_error: F) -> &mut Self {
+                self.on_error = Some(Box::new(on_error));
+                self
+        }
+        /// Route `method` to `handler`, replacing the handler routed before.
+        pub fn route<P, R, F>(&mut self, method: &str, handler: F) -> &mut Self
+        where
+                P: DeserializeOwned,
+                R: Serialize,
+                F: Fn(P) -> Result<R, RemoteError> + Send + Sync + 'static,
+        {
+                let format = self.format;
+                let name = method.to_string();
+                let handler: Box<Method> = Box::new(move |params: &[u8]| {
+                        let params = format
+                                .from_slice(params)
+                                .map_err(|err| RemoteError::new(RemoteError::INVALID_PARAMS, format!("invalid params for {}: {}", name, err)))?;
+                        format.to_vec(&handler(params)?)
+                                .map_err(|err| RemoteError::new(RemoteError::INTERNAL_ERROR, err.to_string()))
+                });
+                self.methods.insert(method.to_string(), handler);
+                self
+        }
+        /// Answer requests from one connected client until it disconnects.
+        pub fn serve_connection<P: Read + Write + AsHandle>(&self, pipe: &mut P) -> io::Result<()> {
+                let mut channel = TypedChannel::<Response, Request, _>::with_codec(pipe, self.format, self.codec.clone())?;
+                loop {
+                        let request = match channel.recv() {
+                                Ok(request) => request,
+                                Err(err) if err.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+                                Err(err) => return Err(err),
+                        };
+                        let result = match self.methods.get(&request.method) {
+                                Some(method) => method(&request.params.0).map(Payload),
+                                None => Err(RemoteError::new(
+                                        RemoteError::METHOD_NOT_FOUND,
+                                        format!("method not found: {}", request.method),
+                                )),
+                        };
+                        channel.send(&Response { id: request.id, result })?;
+                }
+        }
+        /// Serve clients of `server` one after another with [`DuplexServer::serve_with`], returns once connecting a client fails.
+        /// <br>A client whose connection fails is dropped and the next one served, the error goes to [`on_error`](Self::on_error).
+        pub fn serve(&self, server: &mut DuplexServer) -> io::Result<()> {
+                server.serve_with(ClientErrorPolicy::Continue, |connected| {
+                        if let (Err(err), Some(on_error)) = (self.serve_connection(connected), &self.on_error) {
+                                on_error(err);
+                        }
+                        Ok(())
+                })
+        }
+}
+impl fmt::Debug for Router {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("Router")
+                        .field("format", &self.format)
+                        .field("codec", &self.codec)
+                        .field("methods", &self.methods.keys().collect::<Vec<_>>())
+                        .finish_non_exhaustive()
+        }
+}
+
+type Reply = mpsc::Sender<Result<Vec<u8>, RemoteError>>;
+
+/// Outstanding calls and whether the connection is still usable.
+#[derive(Default)]
+struct Pending {
+        calls: HashMap<u64, Reply>,
+        /// kind of the error that ended the connection
+        closed: Option<io::ErrorKind>,
+}
+
+/// Calls methods of a [`Router`] over a duplex client, from any number of threads at once.
+/// <br>A background thread reads the responses and hands each one to the call with its id, so the client must be opened
+/// with [`overlapped`](crate::builder::ClientBuilder::overlapped).
+/// A call that times out stops waiting, its response is dropped when it arrives.
+pub struct RpcClient {
+        writer: Mutex<TypedChannel<Request, Response, OwnedWriteHalf<DuplexClient>>>,
+        pending: Arc<Mutex<Pending>>,
+        next_id: AtomicU64,
+        format: Format,
+        /// default timeout of calls
+        timeout: Option<Duration>,
+        reader: Option<JoinHandle<()>>,
+        /// raw handle of the client, only used to cancel the reader's pending read
+        pipe: isize,
+}
+impl RpcClient {
+        pub fn new(client: DuplexClient, format: Format) -> io::Result<Self> { Self::with_codec(client, format, FrameCodec::default()) }
+        /// A client framing requests with `codec` in byte read mode, see [`TypedChannel::with_codec`].
+        pub fn with_codec(client: DuplexClient, format: Format, codec: FrameCodec) -> io::Result<Self> {
+                let message = client.handle_state()?.read_mode == ReadMode::Message;
+                let pipe = client.0.handle.as_inner();
+                let (read, write) = client.into_split().map_err(|(_, err)| io::Error::from(err))?;
+                let mut reader = TypedChannel::<(), Response, _>::from_parts(read, format, codec.clone(), message);
+                let pending = Arc::new(Mutex::new(Pending::default()));
+                let reader = thread::spawn({
+                        let pending = pending.clone();
+                        move || loop {
+                                match reader.recv() {
+                                        Ok(response) => {
+                                                if let Some(reply) = pending.lock().unwrap().calls.remove(&response.id) {
+                                                        let _ = reply.send(response.result.map(|result| result.0));
+                                                }
+                                        }
+                                        Err(err) => {
+                                                // dropping the senders wakes every outstanding call
+                                                let mut pending = pending.lock().unwrap();
+                                                pending.closed = Some(err.kind());
+                                                pending.calls.clear();
+                                                return;
+                                        }
+                                }
+                        }
+                });
+                Ok(Self {
+                        writer: Mutex::new(TypedChannel::from_parts(write, format, codec, message)),
+                        pending,
+                        next_id: AtomicU64::new(0),
+                        format,
+                        timeout: None,
+                        reader: Some(reader),
+                        pipe,
+                })
+        }
+        /// Timeout of [`call`](Self::call), `None` waits forever.
+        pub fn timeout(&self) -> Option<Duration> { self.timeout }
+        pub fn set_timeout(&mut self, dur: Option<Duration>) -> io::Result<()> {
+                check_timeout(dur)?;
+                self.timeout = dur;
+                Ok(())
+        }
+        /// Call `method` with `params` and wait for its result, for at most the client's [`timeout`](Self::timeout).
+        pub fn call<P: Serialize, R: DeserializeOwned>(&self, method: &str, params: &P) -> Result<R, RpcError> {
+                self.call_timeout(method, params, self.timeout)
+        }
+        /// Call `method` with `params` and wait for its result for at most `timeout`, `None` waits forever.
+        pub fn call_timeout<P: Serialize, R: DeserializeOwned>(&self, method: &str, params: &P, timeout: Option<Duration>) -> Result<R, RpcError> {
+                check_timeout(timeout)?;
+                let request = Request {
+                        id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                        method: method.to_string(),
+                        params: Payload(self.format.to_vec(params)?),
+                };
+                let (reply, result) = mpsc::channel();
+                {
+                        let mut pending = self.pending.lock().unwrap();
+                        if let Some(kind) = pending.closed {
+                                return Err(io::Error::new(kind, "rpc connection is closed").into());
+                        }
+                        pending.calls.insert(request.id, reply);
+                }
+                if let Err(err) = self.writer.lock().unwrap().send(&request) {
+                        self.pending.lock().unwrap().calls.remove(&request.id);
+                        return Err(err.into());
+                }
+                let result = match timeout {
+                        Some(timeout) => result.recv_timeout(timeout).map_err(|err| err == mpsc::RecvTimeoutError::Timeout),
+                        None => result.recv().map_err(|_| false),
+                };
+                match result {
+                        Ok(Ok(result)) => Ok(self.format.from_slice(&result)?),
+                        Ok(Err(err)) => Err(RpcError::Remote(err)),
+                        Err(true) => {
+                                self.pending.lock().unwrap().calls.remove(&request.id);
+                                Err(io::Error::new(io::ErrorKind::TimedOut, format!("rpc call of {} timed out", method)).into())
+                        }
+                        Err(false) => {
+                                let kind = self.pending.lock().unwrap().closed.unwrap_or(io::ErrorKind::BrokenPipe);
+                                Err(io::Error::new(kind, "rpc connection closed before the response arrived").into())
+                        }
+                }
+        }
+}
+impl fmt::Debug for RpcClient {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("RpcClient")
+                        .field("format", &self.format)
+                        .field("timeout", &self.timeout)
+                        .finish_non_exhaustive()
+        }
+}
+impl Drop for RpcClient {
+        fn drop(&mut self) {
+                if let Some(reader) = self.reader.take() {
+                        cancel_io_until(self.pipe, || reader.is_finished());
+                        let _ = reader.join();
+                }
+        }
+}
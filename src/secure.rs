@@ -0,0 +1,263 @@
+//! Authenticated encryption over connected servers and clients, feature `encryption`.
+//! Both ends send a hello with 32 random bytes, or with an X25519 public key for a key exchange, and derive one
+//! ChaCha20-Poly1305 key per direction with HKDF-SHA256 over the pre-shared or exchanged secret, salted with both hellos.
+//! Every frame carries its sequence number, which is also its nonce: a frame that is not the next one is a replay,
+//! and one that fails to decrypt has been tampered with.
+//! <br>A key exchange alone does not tell who is at the other end, pair it with
+//! [`ClientBuilder::expect_server`](crate::builder::ClientBuilder::expect_server) or an application level check.
+
+use super::*;
+use ::std::{error::Error, fmt};
+use chacha20poly1305::{aead::Aead, ChaCha20Poly1305, KeyInit, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+const MAGIC: [u8; 4] = *b"WNPE";
+const PRE_SHARED: u8 = 0;
+const KEY_EXCHANGE: u8 = 1;
+/// magic, mode and the random bytes or public key
+const HELLO_LEN: usize = 4 + 1 + 32;
+/// poly1305 tag length
+const TAG_LEN: usize = 16;
+
+/// Failed [`SecureChannel`] call.
+#[derive(Debug)]
+pub enum SecureError {
+        /// the pipe failed or closed, the peer's hello was malformed or the message is too large
+        Io(io::Error),
+        /// the frame failed to authenticate: it was changed on the way, or the peer uses another key
+        Tampered,
+        /// the frame is not the next one from the peer, it was replayed, reordered or dropped
+        Replayed { expected: u64, received: u64 },
+}
+impl fmt::Display for SecureError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                        SecureError::Io(err) => write!(f, "secure channel failed: {}", err),
+                        SecureError::Tampered => write!(f, "secure channel frame failed to authenticate"),
+                        SecureError::Replayed { expected, received } => {
+                                write!(f, "secure channel frame {} received while expecting frame {}", received, expected)
+                        }
+                }
+        }
+}
+impl Error for SecureError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+                match self {
+                        SecureError::Io(err) => Some(err),
+                        _ => None,
+                }
+        }
+}
+impl From<io::Error> for SecureError {
+        fn from(err: io::Error) -> Self { SecureError::Io(err) }
+}
+impl From<NamedPipeWinError> for SecureError {
+        fn from(err: NamedPipeWinError) -> Self { SecureError::Io(err.into()) }
+}
+impl From<SecureError> for io::Error {
+        fn from(err: SecureError) -> Self {
+                match err {
+                        SecureError::Io(err) => err,
+                        err => io::Error::new(io::ErrorKind::InvalidData, err),
+                }
+        }
+}
+
+/// Encrypted and authenticated messages over a connected server or client.
+/// <br>Reads and writes fail with [`InvalidData`](io::ErrorKind::InvalidData) wrapping the [`SecureError`] for a tampered or
+/// replayed frame. After any error the channel is best dropped.
+/// ```no_run
+/// # use windows_named_pipes::*;
+/// # fn main() -> Result<(), SecureError> {
+/// let key = [7u8; 32];
+/// let client = DuplexClient::open(std::path::Path::new(r"\\.\pipe\secure"))?;
+/// let mut channel = SecureChannel::pre_shared(client, &key)?;
+/// channel.send(b"secret")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct SecureChannel<P> {
+        framed: Framed<P>,
+        /// whether the pipe was in message read mode when the channel was made
+        message: bool,
+        sealer: ChaCha20Poly1305,
+        opener: ChaCha20Poly1305,
+        /// sequence number of the next frame sent
+        sent: u64,
+        /// sequence number of the next frame expected
+        received: u64,
+        /// data of the last message not yet taken by `read`
+        buf: Vec<u8>,
+        pos: usize,
+}
+impl<P: Read + Write + AsHandle> SecureChannel<P> {
+        /// Set up a channel keyed by a secret both ends already share, every connection still gets fresh keys.
+        /// <br>Ends using different keys fail with [`SecureError::Tampered`].
+        pub fn pre_shared(pipe: P, key: &[u8; 32]) -> Result<Self, SecureError> { Self::with_codec(pipe, Some(key), FrameCodec::default()) }
+        /// Set up a channel keyed by an ephemeral X25519 key exchange.
+        pub fn key_exchange(pipe: P) -> Result<Self, SecureError> { Self::with_codec(pipe, None, FrameCodec::default()) }
+        /// A channel with frames formatted by `codec` in byte read mode, in message read mode only its max frame size applies.
+        /// A pre-shared `key` or `None` for a key exchange.
+        pub fn with_codec(pipe: P, key: Option<&[u8; 32]>, codec: FrameCodec) -> Result<Self, SecureError> {
+                let handle = borrow_handle(&pipe);
+                let message = handle.get_state(false).map_err(io_error)?.read_mode == ReadMode::Message;
+                let server = handle.get_info().map_err(io_error)?.end == PipeEnd::Server;
+                let mut framed = Framed::with_codec(pipe, codec);
+
+                let mut secret = [0u8; 32];
+                getrandom::getrandom(&mut secret).map_err(io::Error::from)?;
+                let mut hello = [0u8; HELLO_LEN];
+                hello[..4].copy_from_slice(&MAGIC);
+                hello[4] = match key {
+                        Some(_) => PRE_SHARED,
+                        None => KEY_EXCHANGE,
+                };
+                hello[5..].copy_from_slice(&match key {
+                        Some(_) => secret,
+                        None => x25519_dalek::x25519(secret, x25519_dalek::X25519_BASEPOINT_BYTES),
+                });
+                send(&mut framed, message, &hello)?;
+                let peer = recv(&mut framed, message)?;
+                if peer.len() != HELLO_LEN || peer[..4] != MAGIC || peer[4] != hello[4] {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "peer did not send a matching secure channel hello").into());
+                }
+                let ikm = match key {
+                        Some(key) => *key,
+                        None => {
+                                let shared = x25519_dalek::x25519(secret, peer[5..].try_into().unwrap());
+                                // a low order public key from the peer would make the secret predictable
+                                if shared == [0u8; 32] {
+                                        return Err(io::Error::new(io::ErrorKind::InvalidData, "peer sent an invalid public key").into());
+                                }
+                                shared
+                        }
+                };
+                let (client_hello, server_hello) = match server {
+                        true => (&peer[..], &hello[..]),
+                        false => (&hello[..], &peer[..]),
+                };
+                let hkdf = Hkdf::<Sha256>::new(Some(&[client_hello, server_hello].concat()), &ikm);
+                let (mut to_server, mut to_client) = ([0u8; 32], [0u8; 32]);
+                hkdf.expand(b"windows-named-pipes client to server", &mut to_server).unwrap();
+                hkdf.expand(b"windows-named-pipes server to client", &mut to_client).unwrap();
+                let (sealer, opener) = match server {
+                        true => (to_client, to_server),
+                        false => (to_server, to_client),
+                };
+                let mut channel = Self {
+                        framed,
+                        message,
+                        sealer: ChaCha20Poly1305::new(&sealer.into()),
+                        opener: ChaCha20Poly1305::new(&opener.into()),
+                        sent: 0,
+                        received: 0,
+                        buf: Vec::new(),
+                        pos: 0,
+                };
+                // an empty first frame each way, so different keys fail here instead of on the first message
+                channel.send(&[])?;
+                channel.recv()?;
+                Ok(channel)
+        }
+}
+impl<P> SecureChannel<P> {
+        pub fn get_ref(&self) -> &P { self.framed.get_ref() }
+        pub fn get_mut(&mut self) -> &mut P { self.framed.get_mut() }
+        pub fn into_inner(self) -> P { self.framed.into_inner() }
+}
+impl<P: Read + Write> SecureChannel<P> {
+        /// Encrypt and send `data` as one message.
+        pub fn send(&mut self, data: &[u8]) -> Result<(), SecureError> {
+                let max = self.framed.codec().max_frame_size.saturating_sub(8 + TAG_LEN);
+                if data.len() > max {
+                        return Err(io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                format!("message of {} bytes is larger than the limit of {} bytes", data.len(), max),
+                        )
+                        .into());
+                }
+                let sealed = self
+                        .sealer
+                        .encrypt(&nonce(self.sent), data)
+                        .map_err(|_| io::Error::other("encryption failed"))?;
+                let mut frame = Vec::with_capacity(8 + sealed.len());
+                frame.extend_from_slice(&self.sent.to_be_bytes());
+                frame.extend_from_slice(&sealed);
+                send(&mut self.framed, self.message, &frame)?;
+                self.sent += 1;
+                Ok(())
+        }
+        /// Receive and decrypt the next message.
+        pub fn recv(&mut self) -> Result<Vec<u8>, SecureError> {
+                let frame = recv(&mut self.framed, self.message)?;
+                if frame.len() < 8 + TAG_LEN {
+                        return Err(SecureError::Tampered);
+                }
+                let received = u64::from_be_bytes(frame[..8].try_into().unwrap());
+                if received != self.received {
+                        return Err(SecureError::Replayed {
+                                expected: self.received,
+                                received,
+                        });
+                }
+                let data = self.opener.decrypt(&nonce(received), &frame[8..]).map_err(|_| SecureError::Tampered)?;
+                self.received += 1;
+                Ok(data)
+        }
+}
+impl<P: Read + Write> Read for SecureChannel<P> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                while self.pos == self.buf.len() {
+                        self.buf = self.recv()?;
+                        self.pos = 0;
+                }
+                let n = buf.len().min(self.buf.len() - self.pos);
+                buf[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+                self.pos += n;
+                Ok(n)
+        }
+}
+impl<P: Read + Write> Write for SecureChannel<P> {
+        /// Sends at most the max message size of `buf` as one message.
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if buf.is_empty() {
+                        return Ok(0);
+                }
+                let n = buf.len().min(self.framed.codec().max_frame_size.saturating_sub(8 + TAG_LEN));
+                self.send(&buf[..n])?;
+                Ok(n)
+        }
+        fn flush(&mut self) -> io::Result<()> { self.framed.get_mut().flush() }
+}
+impl<P> fmt::Debug for SecureChannel<P> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("SecureChannel")
+                        .field("message", &self.message)
+                        .field("sent", &self.sent)
+                        .field("received", &self.received)
+                        .finish_non_exhaustive()
+        }
+}
+
+/// the sequence number in the last 8 bytes, each direction has its own key so both can count from 0
+fn nonce(sequence: u64) -> Nonce {
+        let mut nonce = Nonce::default();
+        nonce[4..].copy_from_slice(&sequence.to_be_bytes());
+        nonce
+}
+fn send<P: Write>(framed: &mut Framed<P>, message: bool, frame: &[u8]) -> io::Result<()> {
+        match message {
+                true => write_message(framed.get_mut(), frame),
+                false => framed.send_frame(frame),
+        }
+}
+fn recv<P: Read>(framed: &mut Framed<P>, message: bool) -> io::Result<Vec<u8>> {
+        match message {
+                true => {
+                        let max = framed.codec().max_frame_size;
+                        read_message(framed.get_mut(), max)
+                }
+                false => framed.recv_frame(),
+        }
+}
@@ -5,7 +5,7 @@ pub(super) fn _open<Server: ServerImpl>(path: &Path, access: AccessMode) -> Resu
         unsafe {
                 match UnsafeServer::create(
                         path,
-                        access.into(),
+                        access.into() | FILE_FLAG_FIRST_PIPE_INSTANCE,
                         PIPE_TYPE_MESSAGE | PIPE_READMODE_MESSAGE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
                         1,
                         DEFAULT_BUFSIZE,
@@ -41,9 +41,12 @@ pub(super) fn _close<Server: ServerImpl>(s: Server) -> Result<(), (Server, Named
 }
 
 #[inline]
-pub(super) fn _connect<Server: ServerImpl, ConnectedServer: ConnectedServerImpl>(s: &Server) -> Result<ConnectedServer, NamedPipeWinError> {
+pub(super) fn _connect<'a, Server: ServerImpl, ConnectedServer: ConnectedServerImpl<'a>>(
+        s: &'a mut Server,
+) -> Result<ConnectedServer, NamedPipeWinError> {
         unsafe {
-                let us = s.as_inner();
+                let us = s.as_mut_inner();
+                let path = us.path.clone();
                 match us.connect() {
                         Ok(ucs) => Ok(ConnectedServer::from_inner(ucs)),
                         Err(error) => Err(NamedPipeWinError::from_inner(
@@ -51,7 +54,7 @@ pub(super) fn _connect<Server: ServerImpl, ConnectedServer: ConnectedServerImpl>
                                 format!(
                                         r#"{} {{path: "{}",..}} connect() failed with"#,
                                         ::std::any::type_name::<Server>(),
-                                        us.path.display()
+                                        path.display()
                                 ),
                         )),
                 }
@@ -59,7 +62,7 @@ pub(super) fn _connect<Server: ServerImpl, ConnectedServer: ConnectedServerImpl>
 }
 
 #[inline]
-pub(super) fn _disconnect<ConnectedServer: ConnectedServerImpl>(cs: ConnectedServer) -> Result<(), (ConnectedServer, NamedPipeWinError)> {
+pub(super) fn _disconnect<'a, ConnectedServer: ConnectedServerImpl<'a>>(cs: ConnectedServer) -> Result<(), (ConnectedServer, NamedPipeWinError)> {
         unsafe {
                 let ucs = cs.into_inner();
                 if let Err((ucs, error)) = ucs.disconnect() {
@@ -81,7 +84,7 @@ pub(super) fn _disconnect<ConnectedServer: ConnectedServerImpl>(cs: ConnectedSer
 }
 
 #[inline]
-pub(super) fn _read<ConnectedServer: ConnectedServerImpl>(cs: &mut ConnectedServer, buf: &mut [u8]) -> Result<u32, NamedPipeWinError> {
+pub(super) fn _read<'a, ConnectedServer: ConnectedServerImpl<'a>>(cs: &mut ConnectedServer, buf: &mut [u8]) -> Result<u32, NamedPipeWinError> {
         unsafe {
                 let ucs = cs.as_mut_inner();
                 match ucs.read(buf) {
@@ -99,7 +102,7 @@ pub(super) fn _read<ConnectedServer: ConnectedServerImpl>(cs: &mut ConnectedServ
 }
 
 #[inline]
-pub(super) fn _write<ConnectedServer: ConnectedServerImpl>(cs: &mut ConnectedServer, buf: &[u8]) -> Result<u32, NamedPipeWinError> {
+pub(super) fn _write<'a, ConnectedServer: ConnectedServerImpl<'a>>(cs: &mut ConnectedServer, buf: &[u8]) -> Result<u32, NamedPipeWinError> {
         unsafe {
                 let ucs = cs.as_mut_inner();
                 match ucs.write(buf) {
@@ -117,7 +120,7 @@ pub(super) fn _write<ConnectedServer: ConnectedServerImpl>(cs: &mut ConnectedSer
 }
 
 #[inline]
-pub(super) fn _flush<ConnectedServer: ConnectedServerImpl>(cs: &mut ConnectedServer) -> Result<(), NamedPipeWinError> {
+pub(super) fn _flush<'a, ConnectedServer: ConnectedServerImpl<'a>>(cs: &mut ConnectedServer) -> Result<(), NamedPipeWinError> {
         unsafe {
                 let ucs = cs.as_mut_inner();
                 if let Err(error) = ucs.flush() {
@@ -134,8 +137,9 @@ pub(super) fn _flush<ConnectedServer: ConnectedServerImpl>(cs: &mut ConnectedSer
         }
 }
 
+#[cfg(feature = "stdio")]
 #[inline]
-pub(super) fn _set_stdin<ConnectedServer: ConnectedServerImpl>(cs: &ConnectedServer) -> Result<OldStdinHandle, NamedPipeWinError> {
+pub(super) fn _set_stdin<'a, ConnectedServer: ConnectedServerImpl<'a>>(cs: &ConnectedServer) -> Result<OldStdinHandle, NamedPipeWinError> {
         unsafe {
                 let ucs = cs.as_inner();
                 match ucs.set_stdin() {
@@ -152,8 +156,9 @@ pub(super) fn _set_stdin<ConnectedServer: ConnectedServerImpl>(cs: &ConnectedSer
         }
 }
 
+#[cfg(feature = "stdio")]
 #[inline]
-pub(super) fn _set_stdout<ConnectedServer: ConnectedServerImpl>(cs: &ConnectedServer) -> Result<OldStdoutHandle, NamedPipeWinError> {
+pub(super) fn _set_stdout<'a, ConnectedServer: ConnectedServerImpl<'a>>(cs: &ConnectedServer) -> Result<OldStdoutHandle, NamedPipeWinError> {
         unsafe {
                 let ucs = cs.as_inner();
                 match ucs.set_stdout() {
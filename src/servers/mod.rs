@@ -5,14 +5,59 @@ mod generics;
 use generics::*;
 pub mod unsafes;
 use unsafes::*;
+mod runtime;
+pub use runtime::*;
 
 // Using macro for repetitive code, expand macros once stable.
 
+/// What [`DuplexServer::serve_with`] does when serving a client fails.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ClientErrorPolicy {
+        /// disconnect the client and wait for the next one.
+        Continue,
+        /// disconnect the client and return the error from `serve_with`.
+        Stop,
+}
+
 def_server_structs!(
         ACCESS_MODE = AccessMode::Duplex,
         /// Dual direction server (server ↔ client).
         /// <br>Must `connect()` to a client before using I/O.
-        DuplexServer,
+        DuplexServer
+        impl {
+                /// Serve clients one after another on this instance: connect, run `handler`, flush, disconnect, repeat.
+                /// <br>Failing clients are dropped and serving goes on, only a failing `connect()` returns.
+                pub fn serve<F: FnMut(&mut ConnectedDuplexServer<'_>) -> io::Result<()>>(&mut self, handler: F) -> io::Result<()> {
+                        self.serve_with(ClientErrorPolicy::Continue, handler)
+                }
+                /// Serve clients one after another on this instance like [`serve`](Self::serve), with `policy` deciding what a failing client does.
+                /// <br>A client that closed before it was connected (`ERROR_NO_DATA`) is disconnected and skipped.
+                pub fn serve_with<F: FnMut(&mut ConnectedDuplexServer<'_>) -> io::Result<()>>(
+                        &mut self,
+                        policy: ClientErrorPolicy,
+                        mut handler: F,
+                ) -> io::Result<()> {
+                        loop {
+                                let err = match self.connect() {
+                                        Ok(mut connected) => {
+                                                let res = handler(&mut connected)
+                                                        .and_then(|()| connected.flush().map_err(io::Error::from))
+                                                        .and_then(|()| connected.disconnect().map_err(|(_, err)| io::Error::from(err)));
+                                                match (res, policy) {
+                                                        (Err(err), ClientErrorPolicy::Stop) => return Err(err),
+                                                        _ => continue,
+                                                }
+                                        }
+                                        Err(err) => err,
+                                };
+                                if WIN32_ERROR::from_error(err.inner()) != Some(ERROR_NO_DATA) {
+                                        return Err(err.into());
+                                }
+                                // the client closed before it was connected, free the instance for the next one
+                                let _ = self.0.handle.disconnect();
+                        }
+                }
+        },
         /// Dual direction connected server (server ↔ client)
         ConnectedDuplexServer
         impl Read {
@@ -25,14 +70,33 @@ def_server_structs!(
         impl {
                 #[doc = "read from pipe into buffer, returning number of bytes read"]
                 pub fn read(&mut self, buf: &mut [u8]) -> Result<u32, NamedPipeWinError> { _read(self,buf) }
-                #[doc = "Set the named pipe as current process's stdin pipe.<br>Returns old stdin handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur."]
+                #[cfg(feature = "stdio")]
+                #[doc = "Set the named pipe as current process's stdin pipe.<br>Returns old stdin handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur.<br>To give a child process a pipe as stdio, use [`into_stdio`](Self::into_stdio) or [`stdin_pipe`]/[`stdout_pipe`] instead."]
                 pub unsafe fn set_stdin(&self) -> Result<OldStdinHandle, NamedPipeWinError> { _set_stdin(self) }
                 #[doc = "write into pipe from buffer, returning number of bytes written"]
                 pub fn write(&mut self, buf: &mut [u8]) -> Result<u32, NamedPipeWinError> { _write(self,buf) }
                 #[doc = "flush the I/O buffers and if wait mode, wait until all messages are received"]
                 pub fn flush(&mut self) -> Result<(), NamedPipeWinError> { _flush(self) }
-                #[doc = "Set the named pipe as current process's stdout pipe.<br>Returns old stdout handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur."]
+                #[cfg(feature = "stdio")]
+                #[doc = "Set the named pipe as current process's stdout pipe.<br>Returns old stdout handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur.<br>To give a child process a pipe as stdio, use [`into_stdio`](Self::into_stdio) or [`stdin_pipe`]/[`stdout_pipe`] instead."]
                 pub unsafe fn set_stdout(&self) -> Result<OldStdoutHandle, NamedPipeWinError> { _set_stdout(self) }
+                #[doc = "Borrow the pipe as a read half and a write half that can be used from two threads at once.<br>The halves start out with the pipe's read and write timeouts.<br>Fails with `ERROR_NOT_SUPPORTED` unless the pipe was opened with `overlapped(true)`."]
+                pub fn split(&mut self) -> Result<(ReadHalf<'_>, WriteHalf<'_>), NamedPipeWinError> {
+                        if !self.0.overlapped {
+                                return Err(pipe_err::<Self>(self.0.path, "split()")(split::not_overlapped()));
+                        }
+                        Ok((ReadHalf::new(self.0.handle, self.0.read_timeout), WriteHalf::new(self.0.handle, self.0.write_timeout)))
+                }
+                #[doc = "Split the pipe into an owned read half and write half, see [`split`](Self::split).<br>[`OwnedReadHalf::reunite`] gives the pipe back."]
+                #[allow(clippy::result_large_err)]
+                pub fn into_split(self) -> Result<(OwnedReadHalf<Self>, OwnedWriteHalf<Self>), (Self, NamedPipeWinError)> {
+                        if !self.0.overlapped {
+                                let err = pipe_err::<Self>(self.0.path, "into_split()")(split::not_overlapped());
+                                return Err((self, err));
+                        }
+                        let (read_timeout, write_timeout) = (self.0.read_timeout, self.0.write_timeout);
+                        Ok(split::into_split(self, read_timeout, write_timeout))
+                }
         }
 );
 
@@ -49,7 +113,8 @@ def_server_structs!(
         impl {
                 #[doc = "read from pipe into buffer, returning number of bytes read"]
                 pub fn read(&mut self, buf: &mut [u8]) -> Result<u32, NamedPipeWinError> { _read(self,buf) }
-                #[doc = "Set the named pipe as current process's stdin pipe.<br>Returns old stdin handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur."]
+                #[cfg(feature = "stdio")]
+                #[doc = "Set the named pipe as current process's stdin pipe.<br>Returns old stdin handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur.<br>To give a child process a pipe as stdio, use [`into_stdio`](Self::into_stdio) or [`stdin_pipe`]/[`stdout_pipe`] instead."]
                 pub unsafe fn set_stdin(&self) -> Result<OldStdinHandle, NamedPipeWinError> { _set_stdin(self) }
         }
 );
@@ -70,7 +135,8 @@ def_server_structs!(
                 pub fn write(&mut self, buf: &mut [u8]) -> Result<u32, NamedPipeWinError> { _write(self,buf) }
                 #[doc = "flush the I/O buffers and if wait mode, wait until all messages are received"]
                 pub fn flush(&mut self) -> Result<(), NamedPipeWinError> { _flush(self) }
-                #[doc = "Set the named pipe as current process's stdout pipe.<br>Returns old stdout handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur."]
+                #[cfg(feature = "stdio")]
+                #[doc = "Set the named pipe as current process's stdout pipe.<br>Returns old stdout handle upon success<br><br>Experimental, may be removed in future. rust stdlib stdin/stdout/stderr are not designed for named pipes in mind, so UB may occur.<br>To give a child process a pipe as stdio, use [`into_stdio`](Self::into_stdio) or [`stdin_pipe`]/[`stdout_pipe`] instead."]
                 pub unsafe fn set_stdout(&self) -> Result<OldStdoutHandle, NamedPipeWinError> { _set_stdout(self) }
         }
 );
@@ -0,0 +1,292 @@
+//! Multi-instance duplex server runtime.
+//! Every pipe instance gets a listener thread that connects clients and hands the instance to a worker pool,
+//! the instance comes back to its listener once the handler is done and the client is disconnected.
+
+use super::*;
+use ::std::{
+        panic::{self, AssertUnwindSafe},
+        sync::{
+                atomic::{AtomicBool, Ordering},
+                mpsc,
+        },
+        thread::JoinHandle,
+        time::Instant,
+};
+
+type Handler = dyn Fn(&mut ConnectedDuplexServer<'_>) -> io::Result<()> + Send + Sync;
+type ErrorHook = dyn Fn(io::Error) + Send + Sync;
+
+/// A connected instance on its way to a worker, and where to send it back.
+struct Job {
+        server: DuplexServer,
+        done: mpsc::Sender<DuplexServer>,
+}
+
+/// Shutdown flag and number of clients being served, guarded together so listeners can wait for a free slot.
+#[derive(Default)]
+struct State {
+        shutdown: bool,
+        active: usize,
+        /// slots held by listeners while they connect, so they can't connect more than `max_clients` between them
+        reserved: usize,
+}
+
+struct Listener {
+        thread: JoinHandle<()>,
+        /// raw handle of the listener's instance, only used to cancel its pending connect
+        pipe: isize,
+        /// set while the listener may be blocked in `ConnectNamedPipe`
+        connecting: Arc<AtomicBool>,
+}
+
+/// Builder for a [`PipeServer`].
+pub struct PipeServerBuilder {
+        pub(crate) pipe: ServerBuilder<DuplexServer>,
+        /// number of pipe instances listening for clients
+        pub(crate) instances: usize,
+        /// number of threads running handlers
+        pub(crate) workers: usize,
+        /// maximum number of clients connected at once
+        pub(crate) max_clients: usize,
+        /// run on every client before its handler
+        pub(crate) authenticator: Option<Arc<dyn Authenticator>>,
+        /// gets the errors that dropped a client
+        pub(crate) on_error: Option<Arc<ErrorHook>>,
+}
+impl PipeServerBuilder {
+        /// Pipe options for every instance, `max_instances` is raised to the number of instances if needed.
+        pub fn pipe(&mut self, pipe: ServerBuilder<DuplexServer>) -> &mut Self {
+                self.pipe = pipe;
+                self
+        }
+        pub fn instances(&mut self, instances: usize) -> &mut Self {
+                self.instances = instances.max(1);
+                self
+        }
+        pub fn workers(&mut self, workers: usize) -> &mut Self {
+                self.workers = workers.max(1);
+                self
+        }
+        pub fn max_clients(&mut self, max_clients: usize) -> &mut Self {
+                self.max_clients = max_clients.max(1);
+                self
+        }
+        /// Check every client with `authenticator` before handing it to the handler, rejected clients are disconnected.
+        /// <br>An error while authenticating, like a client that timed out, drops the client and goes to [`on_error`](Self::on_error).
+        pub fn authenticator<A: Authenticator + 'static>(&mut self, authenticator: A) -> &mut Self {
+                self.authenticator = Some(Arc::new(authenticator));
+                self
+        }
+        /// Called on a worker thread with every error returned by a handler, a panicking handler is reported as an
+        /// [`Other`](io::ErrorKind::Other) error. Without it these errors are dropped along with their client.
+        pub fn on_error<F: Fn(io::Error) + Send + Sync + 'static>(&mut self, on_error: F) -> &mut Self {
+                self.on_error = Some(Arc::new(on_error));
+                self
+        }
+        pub fn new() -> Self { Self::default() }
+        /// Create the pipe instances and start serving clients with `handler`.
+        /// <br>An error returned by `handler` drops the client and goes to [`on_error`](Self::on_error), nowhere else.
+        pub fn spawn<F>(&self, path: &Path, handler: F) -> Result<PipeServer, NamedPipeWinError>
+        where
+                F: Fn(&mut ConnectedDuplexServer<'_>) -> io::Result<()> + Send + Sync + 'static,
+        {
+                let mut pipe = self.pipe.clone();
+                pipe.max_instances = pipe.max_instances.max(self.instances.min(PIPE_UNLIMITED_INSTANCES as usize - 1) as u32);
+                let mut servers = vec![pipe.open(path)?];
+                for _ in 1..self.instances {
+                        servers.push(servers[0].new_instance()?);
+                }
+                let handler: Arc<Handler> = Arc::new(handler);
+                let state = Arc::new((Mutex::new(State::default()), Condvar::new()));
+                let (jobs, queue) = mpsc::sync_channel::<Job>(self.workers);
+                let queue = Arc::new(Mutex::new(queue));
+                let workers = (0..self.workers)
+                        .map(|_| {
+                                let (queue, handler, state) = (queue.clone(), handler.clone(), state.clone());
+                                let (authenticator, on_error) = (self.authenticator.clone(), self.on_error.clone());
+                                thread::spawn(move || work(&queue, &*handler, authenticator.as_deref(), on_error.as_deref(), &state))
+                        })
+                        .collect();
+                let listeners = servers
+                        .into_iter()
+                        .map(|server| {
+                                let connecting = Arc::new(AtomicBool::new(false));
+                                let pipe = server.0.handle.as_inner();
+                                let (jobs, state, max_clients) = (jobs.clone(), state.clone(), self.max_clients);
+                                let thread = thread::spawn({
+                                        let connecting = connecting.clone();
+                                        move || listen(server, &jobs, &state, &connecting, max_clients)
+                                });
+                                Listener { thread, pipe, connecting }
+                        })
+                        .collect();
+                Ok(PipeServer { state, listeners, workers })
+        }
+}
+impl Default for PipeServerBuilder {
+        fn default() -> Self {
+                // REVIEW: good defaults?
+                PipeServerBuilder {
+                        pipe: ServerBuilder::new(),
+                        instances: 4,
+                        workers: 4,
+                        max_clients: 4,
+                        authenticator: None,
+                        on_error: None,
+                }
+        }
+}
+
+/// Duplex server keeping several instances of a pipe listening and serving clients on a bounded worker pool.
+/// <br>Dropping it shuts down and waits for in-flight handlers.
+pub struct PipeServer {
+        state: Arc<(Mutex<State>, Condvar)>,
+        listeners: Vec<Listener>,
+        workers: Vec<JoinHandle<()>>,
+}
+impl PipeServer {
+        pub fn builder() -> PipeServerBuilder { PipeServerBuilder::new() }
+        /// Number of clients currently being served.
+        pub fn active_clients(&self) -> usize { self.state.0.lock().unwrap().active }
+        /// Stop accepting clients, cancel pending connects and wait for in-flight handlers to finish.
+        pub fn shutdown(mut self) { self.stop(None); }
+        /// Like [`shutdown`](Self::shutdown), but give up waiting after `timeout`.
+        /// <br>Returns false if handlers were still running, they finish in the background.
+        pub fn shutdown_timeout(mut self, timeout: Duration) -> bool { self.stop(Some(Instant::now() + timeout)) }
+        fn stop(&mut self, deadline: Option<Instant>) -> bool {
+                {
+                        let (lock, cvar) = &*self.state;
+                        lock.lock().unwrap().shutdown = true;
+                        cvar.notify_all();
+                }
+                let timed_out = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+                // listeners check the flag after raising `connecting`, so each one either sees the flag or gets cancelled here
+                for listener in &self.listeners {
+                        while listener.connecting.load(Ordering::SeqCst) && !listener.thread.is_finished() && !timed_out() {
+                                // an overlapped connect is cancelled through the pipe, a synchronous one through the thread
+                                unsafe {
+                                        let _ = CancelIoEx(HANDLE(listener.pipe), ::core::ptr::null());
+                                        let _ = CancelSynchronousIo(HANDLE(listener.thread.as_raw_handle() as isize));
+                                }
+                                thread::sleep(Duration::from_millis(1));
+                        }
+                }
+                // listeners own the job senders, workers stop once every listener is gone
+                let threads = self.listeners.drain(..).map(|listener| listener.thread).chain(self.workers.drain(..));
+                let mut finished = true;
+                for thread in threads {
+                        while !thread.is_finished() && !timed_out() {
+                                thread::sleep(Duration::from_millis(1));
+                        }
+                        match thread.is_finished() {
+                                true => drop(thread.join()),
+                                false => finished = false,
+                        }
+                }
+                finished
+        }
+}
+impl Drop for PipeServer {
+        fn drop(&mut self) {
+                if !self.listeners.is_empty() || !self.workers.is_empty() {
+                        self.stop(None);
+                }
+        }
+}
+
+fn listen(mut server: DuplexServer, jobs: &mpsc::SyncSender<Job>, state: &(Mutex<State>, Condvar), connecting: &AtomicBool, max_clients: usize) {
+        let (lock, cvar) = state;
+        loop {
+                {
+                        let mut guard = cvar
+                                .wait_while(lock.lock().unwrap(), |state| {
+                                        !state.shutdown && state.active + state.reserved >= max_clients
+                                })
+                                .unwrap();
+                        if guard.shutdown {
+                                return;
+                        }
+                        guard.reserved += 1;
+                }
+                connecting.store(true, Ordering::SeqCst);
+                let res = match lock.lock().unwrap().shutdown {
+                        true => Err(ERROR_OPERATION_ABORTED.into()),
+                        false => server.0.handle.connect_as(None, server.0.overlapped),
+                };
+                connecting.store(false, Ordering::SeqCst);
+                {
+                        // the reserved slot becomes the client's, or is given back
+                        let mut guard = lock.lock().unwrap();
+                        guard.reserved -= 1;
+                        if res.is_ok() {
+                                guard.active += 1;
+                        }
+                        cvar.notify_all();
+                }
+                if let Err(err) = res {
+                        if lock.lock().unwrap().shutdown {
+                                return;
+                        }
+                        // the client closed before it was connected, free the instance for the next one
+                        if WIN32_ERROR::from_error(&err) == Some(ERROR_NO_DATA) {
+                                let _ = server.0.handle.disconnect();
+                        } else {
+                                thread::sleep(Duration::from_millis(10));
+                        }
+                        continue;
+                }
+                let (done, back) = mpsc::channel();
+                if jobs.send(Job { server, done }).is_err() {
+                        lock.lock().unwrap().active -= 1;
+                        return;
+                }
+                server = match back.recv() {
+                        Ok(server) => server,
+                        Err(_) => return,
+                };
+        }
+}
+
+fn work(
+        queue: &Mutex<mpsc::Receiver<Job>>,
+        handler: &Handler,
+        authenticator: Option<&dyn Authenticator>,
+        on_error: Option<&ErrorHook>,
+        state: &(Mutex<State>, Condvar),
+) {
+        loop {
+                let job = match queue.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        Err(_) => return,
+                };
+                let Job { server, done } = job;
+                {
+                        let mut connected = ConnectedDuplexServer(UnsafeConnectedServer::from_inner(
+                                &server.0.handle,
+                                &server.0.path,
+                                server.0.info.clone(),
+                                server.0.overlapped,
+                        ));
+                        // a panicking handler only loses its client, the instance goes back to its listener
+                        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+                                if let Some(authenticator) = authenticator {
+                                        if !authenticator.authenticate(&mut connected)? {
+                                                return Ok(());
+                                        }
+                                }
+                                handler(&mut connected).and_then(|()| Write::flush(&mut connected))
+                        }));
+                        let err = match res {
+                                Ok(res) => res.err(),
+                                Err(_) => Some(io::Error::other("handler panicked")),
+                        };
+                        if let (Some(on_error), Some(err)) = (on_error, err) {
+                                on_error(err);
+                        }
+                }
+                let (lock, cvar) = state;
+                lock.lock().unwrap().active -= 1;
+                cvar.notify_all();
+                let _ = done.send(server);
+        }
+}
@@ -1,16 +1,35 @@
 use super::*;
 
+/// What an instance was created with, so further instances of the same pipe can be created alike.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct InstanceSettings {
+        pub(crate) open_mode: FILE_FLAGS_AND_ATTRIBUTES,
+        pub(crate) pipe_mode: NAMED_PIPE_MODE,
+        pub(crate) max_instances: u32,
+        pub(crate) out_buffer_size: u32,
+        pub(crate) in_buffer_size: u32,
+        pub(crate) default_timeout: u32,
+        /// `bInheritHandle`, the security descriptor belongs to the pipe and is set by its first instance
+        pub(crate) inheritable: bool,
+}
+
 pub struct UnsafeServer {
         pub(crate) handle: UnsafeHandle,
         pub(crate) path: ::std::path::PathBuf,
         pub(crate) info: PipeInfo,
+        /// how long `connect` waits for a client, `None` waits forever
+        pub(crate) connect_timeout: Option<Duration>,
+        /// created with `FILE_FLAG_OVERLAPPED`, so its I/O must go through an `OVERLAPPED`
+        pub(crate) overlapped: bool,
+        /// `None` for a handle the crate didn't create
+        pub(crate) settings: Option<InstanceSettings>,
 }
 impl AsRawHandle for UnsafeServer {
         #[inline]
         fn as_raw_handle(&self) -> RawHandle { self.handle.as_raw_handle() }
 }
 impl IntoRawHandle for UnsafeServer {
-        fn into_raw_handle(self) -> RawHandle { UnsafeHandle::from(self).0 as RawHandle }
+        fn into_raw_handle(self) -> RawHandle { UnsafeHandle::from(self).into_raw_handle() }
 }
 impl AsHandle for UnsafeServer {
         #[inline]
@@ -22,7 +41,7 @@ impl From<UnsafeServer> for UnsafeHandle {
                         let mut that = ::std::mem::ManuallyDrop::new(that);
                         ::std::ptr::drop_in_place(&mut that.path);
                         ::std::ptr::drop_in_place(&mut that.info);
-                        that.handle
+                        ::std::ptr::read(&that.handle)
                 }
         }
 }
@@ -44,8 +63,19 @@ impl UnsafeServer {
         pub unsafe fn host(&self) -> &str { &self.path.parent().unwrap().parent().unwrap().to_str().unwrap() }
         #[inline]
         pub fn as_win_handle(&self) -> HANDLE { self.handle.as_win_handle() }
-        pub(crate) unsafe fn from_inner(handle: UnsafeHandle, path: PathBuf, info: PipeInfo) -> Self { Self { handle, path, info } }
-        /// create a new named pipe server with the given path or open an exisiting path
+        pub(crate) unsafe fn from_inner(handle: UnsafeHandle, path: PathBuf, info: PipeInfo, overlapped: bool) -> Self {
+                Self {
+                        handle,
+                        path,
+                        info,
+                        connect_timeout: None,
+                        overlapped,
+                        settings: None,
+                }
+        }
+        /// create a new named pipe server with the given path, or another instance of an existing one
+        /// <br>With `FILE_FLAG_FIRST_PIPE_INSTANCE` in `dwopenmode` it fails if the pipe already exists.
+        /// <br>With `FILE_FLAG_OVERLAPPED` in `dwopenmode` all I/O on the server goes through an `OVERLAPPED`.
         pub unsafe fn create(
                 path: &Path,
                 dwopenmode: FILE_FLAGS_AND_ATTRIBUTES,
@@ -77,73 +107,160 @@ impl UnsafeServer {
                         handle,
                         path: path.to_owned(),
                         info,
+                        connect_timeout: None,
+                        overlapped: dwopenmode.0 & FILE_FLAG_OVERLAPPED.0 != 0,
+                        settings: Some(InstanceSettings {
+                                open_mode: dwopenmode,
+                                pipe_mode: dwpipemode,
+                                max_instances: nmaxinstances,
+                                out_buffer_size: noutbuffersize,
+                                in_buffer_size: ninbuffersize,
+                                default_timeout: ndefaulttimeout,
+                                inheritable: lpsecurityattributes.bInheritHandle.as_bool(),
+                        }),
                 })
         }
+        /// create another instance of this server's pipe with the same settings and connect timeout, without
+        /// `FILE_FLAG_FIRST_PIPE_INSTANCE`.
+        /// <br>Fails with `ERROR_NOT_SUPPORTED` on a server the crate didn't create, and with `ERROR_PIPE_BUSY` once the pipe
+        /// has its max instances.
+        pub fn new_instance(&self) -> win::core::Result<Self> {
+                let settings = self.settings.ok_or_else(|| WinError::from(win::Win32::Foundation::ERROR_NOT_SUPPORTED))?;
+                let security = SECURITY_ATTRIBUTES {
+                        nLength: ::core::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+                        lpSecurityDescriptor: ::core::ptr::null_mut(),
+                        bInheritHandle: settings.inheritable.into(),
+                };
+                let mut instance = unsafe {
+                        Self::create(
+                                &self.path,
+                                FILE_FLAGS_AND_ATTRIBUTES(settings.open_mode.0 & !FILE_FLAG_FIRST_PIPE_INSTANCE.0),
+                                settings.pipe_mode,
+                                settings.max_instances,
+                                settings.out_buffer_size,
+                                settings.in_buffer_size,
+                                settings.default_timeout,
+                                security,
+                        )?
+                };
+                instance.connect_timeout = self.connect_timeout;
+                Ok(instance)
+        }
 
         /// close the server's pipe handle, capturing error if it fails and wrapping with given msg
+        #[allow(clippy::result_large_err)]
         pub fn close(self) -> Result<(), (Self, WinError)> {
-                if let Err(err) = self.handle.close() {
-                        return Err((self, err));
-                }
-                let _ = UnsafeHandle::from(self); // already closed
-                Ok(())
+                let (path, info, connect_timeout, overlapped, settings) =
+                        (self.path.clone(), self.info.clone(), self.connect_timeout, self.overlapped, self.settings);
+                UnsafeHandle::from(self).close().map_err(|(handle, err)| {
+                        (
+                                Self {
+                                        handle,
+                                        path,
+                                        info,
+                                        connect_timeout,
+                                        overlapped,
+                                        settings,
+                                },
+                                err,
+                        )
+                })
         }
         /// wait for client to connect to the pipe and get IO enabled server.
-        pub unsafe fn connect(&self) -> ::windows::core::Result<UnsafeConnectedServer> {
-                if let Err(err) = self.handle.connect() {
-                        return Err(err);
-                }
-                Ok(UnsafeConnectedServer {
-                        handle: self.handle.clone(),
-                        path: self.path.clone(),
-                        info: self.info.clone(),
-                })
+        /// <br>The connected server borrows this instance, so the instance can't be closed or connected again until it is dropped.
+        /// <br>In non-blocking mode fails right away with `ERROR_PIPE_LISTENING` until a client has connected.
+        pub unsafe fn connect(&mut self) -> ::windows::core::Result<UnsafeConnectedServer<'_>> {
+                self.handle.connect_as(self.connect_timeout, self.overlapped)?;
+                Ok(UnsafeConnectedServer::from_inner(
+                        &self.handle,
+                        &self.path,
+                        self.info.clone(),
+                        self.overlapped,
+                ))
+        }
+        #[inline]
+        pub fn info(&self) -> &PipeInfo { &self.info }
+        /// query `GetNamedPipeInfo` again and update the cached info.
+        pub fn refresh_info(&mut self) -> win::core::Result<&PipeInfo> {
+                self.info = self.handle.get_info()?;
+                Ok(&self.info)
+        }
+        #[inline]
+        pub fn set_read_mode(&self, read_mode: ReadMode) -> win::core::Result<()> { self.handle.set_read_mode(read_mode) }
+        /// `GetNamedPipeHandleState`, without the client user name when the client can't be impersonated.
+        pub fn handle_state(&self) -> win::core::Result<HandleState> { self.handle.get_state(true).or_else(|_| self.handle.get_state(false)) }
+        /// switch the handle between blocking and non-blocking mode.
+        #[inline]
+        pub fn set_nonblocking(&self, nonblocking: bool) -> win::core::Result<()> { self.handle.set_nonblocking(nonblocking) }
+        #[inline]
+        pub fn set_inheritable(&self, inheritable: bool) -> win::core::Result<()> { self.handle.set_inheritable(inheritable) }
+        /// whether the pipe was created with `FILE_FLAG_OVERLAPPED`.
+        #[inline]
+        pub fn is_overlapped(&self) -> bool { self.overlapped }
+        #[inline]
+        pub fn connect_timeout(&self) -> Option<Duration> { self.connect_timeout }
+        /// set how long `connect` waits for a client, `None` waits forever.
+        pub fn set_connect_timeout(&mut self, dur: Option<Duration>) -> io::Result<()> {
+                check_timeout(dur)?;
+                self.connect_timeout = dur;
+                Ok(())
         }
 }
 
-pub struct UnsafeConnectedServer {
-        pub(crate) handle: crate::handle::UnsafeHandle,
-        pub(crate) path: ::std::path::PathBuf,
+/// Win32 named pipe server instance with a connected client, borrowed from its [`UnsafeServer`].
+/// <br>Disconnects on drop, the server closes the handle once it is dropped itself.
+/// It owns no handle, so unlike the server it has no `IntoRawHandle` or `From` conversion into an [`OwnedHandle`] or
+/// [`fs::File`]: dropping it disconnects the client from every copy of the handle.
+/// To hand a connection over, convert the server instead, its handle works for I/O as soon as a client has opened the pipe,
+/// `connect()` only waits for that.
+///
+/// The server can't be closed, dropped or connected again while the connected server lives:
+/// ```compile_fail,E0505
+/// # use windows_named_pipes::*;
+/// # let path = std::path::PathBuf::from(r"\\.\pipe\LOCAL\compile_fail");
+/// let mut server = DuplexServer::open(&path).unwrap();
+/// let connected = server.connect().unwrap();
+/// drop(server);
+/// drop(connected);
+/// ```
+/// ```compile_fail,E0499
+/// # use windows_named_pipes::*;
+/// # let path = std::path::PathBuf::from(r"\\.\pipe\LOCAL\compile_fail");
+/// let mut server = DuplexServer::open(&path).unwrap();
+/// let connected = server.connect().unwrap();
+/// let again = server.connect().unwrap();
+/// drop((connected, again));
+/// ```
+pub struct UnsafeConnectedServer<'a> {
+        pub(crate) handle: &'a UnsafeHandle,
+        pub(crate) path: &'a Path,
         pub(crate) info: crate::handle::PipeInfo,
+        /// how long a read may block, `None` blocks forever
+        pub(crate) read_timeout: Option<Duration>,
+        /// how long a write or flush may block, `None` blocks forever
+        pub(crate) write_timeout: Option<Duration>,
+        /// the server was created with `FILE_FLAG_OVERLAPPED`
+        pub(crate) overlapped: bool,
 }
-impl AsRawHandle for UnsafeConnectedServer {
+impl AsRawHandle for UnsafeConnectedServer<'_> {
         #[inline]
         fn as_raw_handle(&self) -> RawHandle { self.handle.as_raw_handle() }
 }
-impl IntoRawHandle for UnsafeConnectedServer {
-        fn into_raw_handle(self) -> RawHandle { UnsafeHandle::from(self).0 as RawHandle }
-}
-impl AsHandle for UnsafeConnectedServer {
+impl AsHandle for UnsafeConnectedServer<'_> {
         #[inline]
-        fn as_handle(&self) -> BorrowedHandle { unsafe { BorrowedHandle::borrow_raw(self.handle.as_raw_handle()) } }
-}
-impl From<UnsafeConnectedServer> for UnsafeHandle {
-        fn from(that: UnsafeConnectedServer) -> Self {
-                unsafe {
-                        let mut that = ::std::mem::ManuallyDrop::new(that);
-                        ::std::ptr::drop_in_place(&mut that.path);
-                        ::std::ptr::drop_in_place(&mut that.info);
-                        that.handle
-                }
-        }
-}
-impl From<UnsafeConnectedServer> for OwnedHandle {
-        fn from(that: UnsafeConnectedServer) -> Self { unsafe { FromRawHandle::from_raw_handle(that.into_raw_handle()) } }
+        fn as_handle(&self) -> BorrowedHandle<'_> { unsafe { BorrowedHandle::borrow_raw(self.handle.as_raw_handle()) } }
 }
-impl From<UnsafeConnectedServer> for fs::File {
-        fn from(that: UnsafeConnectedServer) -> Self { unsafe { FromRawHandle::from_raw_handle(that.into_raw_handle()) } }
-}
-impl ::std::io::Read for UnsafeConnectedServer {
+impl ::std::io::Read for UnsafeConnectedServer<'_> {
         #[inline]
-        fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> { ::std::io::Read::read(&mut &self.handle, buf) }
+        fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> { UnsafeConnectedServer::read(self, buf).map(|r| r as _).map_err(io_error) }
 }
-impl ::std::io::Write for UnsafeConnectedServer {
+impl ::std::io::Write for UnsafeConnectedServer<'_> {
         #[inline]
-        fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> { ::std::io::Write::write(&mut &self.handle, buf) }
+        fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> { UnsafeConnectedServer::write(self, buf).map(|w| w as _).map_err(io_error) }
         #[inline]
-        fn flush(&mut self) -> ::std::io::Result<()> { ::std::io::Write::flush(&mut &self.handle) }
+        fn flush(&mut self) -> ::std::io::Result<()> { UnsafeConnectedServer::flush(self).map_err(io_error) }
 }
-impl Drop for UnsafeConnectedServer {
+impl Drop for UnsafeConnectedServer<'_> {
         fn drop(&mut self) {
                 unsafe {
                         let h = self.as_win_handle();
@@ -153,15 +270,26 @@ impl Drop for UnsafeConnectedServer {
                 }
         }
 }
-impl UnsafeConnectedServer {
+impl<'a> UnsafeConnectedServer<'a> {
         pub unsafe fn name(&self) -> &str { &self.path.file_name().unwrap().to_str().unwrap() }
         pub unsafe fn host(&self) -> &str { &self.path.parent().unwrap().parent().unwrap().to_str().unwrap() }
         #[inline]
         pub fn as_win_handle(&self) -> HANDLE { self.handle.as_win_handle() }
         #[inline]
-        pub(crate) unsafe fn from_inner(handle: UnsafeHandle, path: PathBuf, info: PipeInfo) -> Self { Self { handle, path, info } }
+        pub(crate) fn from_inner(handle: &'a UnsafeHandle, path: &'a Path, info: PipeInfo, overlapped: bool) -> Self {
+                Self {
+                        handle,
+                        path,
+                        info,
+                        read_timeout: None,
+                        write_timeout: None,
+                        overlapped,
+                }
+        }
+        #[cfg(feature = "stdio")]
         #[inline]
         pub unsafe fn set_stdout(&self) -> win::core::Result<OldStdoutHandle> { self.handle.set_stdout() }
+        #[cfg(feature = "stdio")]
         #[inline]
         pub unsafe fn set_stdin(&self) -> win::core::Result<OldStdinHandle> { self.handle.set_stdin() }
         // REVIEW: any UB with the drop?
@@ -169,13 +297,48 @@ impl UnsafeConnectedServer {
                 if let Err(err) = self.handle.disconnect() {
                         return Err((self, err));
                 }
-                let _ = UnsafeHandle::from(self); // no need to disconnect again.
+                ::std::mem::forget(self); // no need to disconnect again, nothing else to drop.
                 Ok(())
         }
         #[inline]
-        pub fn read(&mut self, buf: &mut [u8]) -> win::core::Result<u32> { self.handle.read(buf) }
+        pub fn read(&mut self, buf: &mut [u8]) -> win::core::Result<u32> { self.handle.read_as(buf, self.read_timeout, self.overlapped) }
+        #[inline]
+        pub fn write(&mut self, buf: &[u8]) -> win::core::Result<u32> { self.handle.write_as(buf, self.write_timeout, self.overlapped) }
+        #[inline]
+        pub fn flush(&mut self) -> win::core::Result<()> { with_deadline(self.write_timeout, || self.handle.flush()) }
+        #[inline]
+        pub fn info(&self) -> &PipeInfo { &self.info }
+        /// query `GetNamedPipeInfo` again and update the cached info.
+        pub fn refresh_info(&mut self) -> win::core::Result<&PipeInfo> {
+                self.info = self.handle.get_info()?;
+                Ok(&self.info)
+        }
+        #[inline]
+        pub fn set_read_mode(&self, read_mode: ReadMode) -> win::core::Result<()> { self.handle.set_read_mode(read_mode) }
+        /// `GetNamedPipeHandleState`, without the client user name when the client can't be impersonated.
+        pub fn handle_state(&self) -> win::core::Result<HandleState> { self.handle.get_state(true).or_else(|_| self.handle.get_state(false)) }
+        /// switch the handle between blocking and non-blocking mode.
+        #[inline]
+        pub fn set_nonblocking(&self, nonblocking: bool) -> win::core::Result<()> { self.handle.set_nonblocking(nonblocking) }
         #[inline]
-        pub fn write(&mut self, buf: &[u8]) -> win::core::Result<u32> { self.handle.write(buf) }
+        pub fn set_inheritable(&self, inheritable: bool) -> win::core::Result<()> { self.handle.set_inheritable(inheritable) }
+        /// whether the pipe was created with `FILE_FLAG_OVERLAPPED`.
         #[inline]
-        pub fn flush(&mut self) -> win::core::Result<()> { self.handle.flush() }
+        pub fn is_overlapped(&self) -> bool { self.overlapped }
+        #[inline]
+        pub fn read_timeout(&self) -> Option<Duration> { self.read_timeout }
+        #[inline]
+        pub fn write_timeout(&self) -> Option<Duration> { self.write_timeout }
+        /// set how long a read may block, `None` blocks forever.
+        pub fn set_read_timeout(&mut self, dur: Option<Duration>) -> io::Result<()> {
+                check_timeout(dur)?;
+                self.read_timeout = dur;
+                Ok(())
+        }
+        /// set how long a write or flush may block, `None` blocks forever.
+        pub fn set_write_timeout(&mut self, dur: Option<Duration>) -> io::Result<()> {
+                check_timeout(dur)?;
+                self.write_timeout = dur;
+                Ok(())
+        }
 }
@@ -0,0 +1,150 @@
+//! Read and write halves of a duplex pipe.
+//! Only pipes opened with [`overlapped`](ServerBuilder::overlapped) can be split: I/O on a synchronous handle is serialized,
+//! so a read blocked on one thread would hold up every write on another.
+
+use super::*;
+use ::std::{error::Error, fmt};
+
+/// Borrowed read half of a duplex pipe, from `split()` on [`DuplexClient`] or [`ConnectedDuplexServer`].
+#[derive(Debug)]
+pub struct ReadHalf<'a> {
+        handle: &'a UnsafeHandle,
+        /// how long a read may block, `None` blocks forever
+        timeout: Option<Duration>,
+}
+/// Borrowed write half of a duplex pipe, from `split()` on [`DuplexClient`] or [`ConnectedDuplexServer`].
+#[derive(Debug)]
+pub struct WriteHalf<'a> {
+        handle: &'a UnsafeHandle,
+        /// how long a write or flush may block, `None` blocks forever
+        timeout: Option<Duration>,
+}
+impl<'a> ReadHalf<'a> {
+        pub(crate) fn new(handle: &'a UnsafeHandle, timeout: Option<Duration>) -> Self { Self { handle, timeout } }
+        /// Starts out as the read timeout of the split pipe.
+        pub fn read_timeout(&self) -> Option<Duration> { self.timeout }
+        pub fn set_read_timeout(&mut self, dur: Option<Duration>) -> io::Result<()> {
+                check_timeout(dur)?;
+                self.timeout = dur;
+                Ok(())
+        }
+}
+impl<'a> WriteHalf<'a> {
+        pub(crate) fn new(handle: &'a UnsafeHandle, timeout: Option<Duration>) -> Self { Self { handle, timeout } }
+        /// Starts out as the write timeout of the split pipe.
+        pub fn write_timeout(&self) -> Option<Duration> { self.timeout }
+        pub fn set_write_timeout(&mut self, dur: Option<Duration>) -> io::Result<()> {
+                check_timeout(dur)?;
+                self.timeout = dur;
+                Ok(())
+        }
+}
+impl Read for ReadHalf<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { half_read(self.handle, buf, self.timeout) }
+}
+impl Write for WriteHalf<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> { half_write(self.handle, buf, self.timeout) }
+        fn flush(&mut self) -> io::Result<()> { half_flush(self.handle, self.timeout) }
+}
+
+/// Owned read half of a duplex pipe, from `into_split()` on [`DuplexClient`] or [`ConnectedDuplexServer`].
+/// <br>The pipe is dropped once both halves are, or taken back with [`reunite`](Self::reunite).
+pub struct OwnedReadHalf<T> {
+        pipe: Arc<T>,
+        timeout: Option<Duration>,
+}
+/// Owned write half of a duplex pipe, from `into_split()` on [`DuplexClient`] or [`ConnectedDuplexServer`].
+/// <br>The pipe is dropped once both halves are, or taken back with [`reunite`](Self::reunite).
+pub struct OwnedWriteHalf<T> {
+        pipe: Arc<T>,
+        timeout: Option<Duration>,
+}
+pub(crate) fn into_split<T>(pipe: T, read_timeout: Option<Duration>, write_timeout: Option<Duration>) -> (OwnedReadHalf<T>, OwnedWriteHalf<T>) {
+        let pipe = Arc::new(pipe);
+        (
+                OwnedReadHalf {
+                        pipe: pipe.clone(),
+                        timeout: read_timeout,
+                },
+                OwnedWriteHalf {
+                        pipe,
+                        timeout: write_timeout,
+                },
+        )
+}
+impl<T> OwnedReadHalf<T> {
+        /// Starts out as the read timeout of the split pipe.
+        pub fn read_timeout(&self) -> Option<Duration> { self.timeout }
+        pub fn set_read_timeout(&mut self, dur: Option<Duration>) -> io::Result<()> {
+                check_timeout(dur)?;
+                self.timeout = dur;
+                Ok(())
+        }
+        /// Put the pipe back together, fails if the halves came from different pipes.
+        /// <br>Timeouts set on the halves are not carried over to the pipe.
+        pub fn reunite(self, other: OwnedWriteHalf<T>) -> Result<T, ReuniteError<T>> { reunite(self, other) }
+}
+impl<T> OwnedWriteHalf<T> {
+        /// Starts out as the write timeout of the split pipe.
+        pub fn write_timeout(&self) -> Option<Duration> { self.timeout }
+        pub fn set_write_timeout(&mut self, dur: Option<Duration>) -> io::Result<()> {
+                check_timeout(dur)?;
+                self.timeout = dur;
+                Ok(())
+        }
+        /// Put the pipe back together, fails if the halves came from different pipes.
+        /// <br>Timeouts set on the halves are not carried over to the pipe.
+        pub fn reunite(self, other: OwnedReadHalf<T>) -> Result<T, ReuniteError<T>> { reunite(other, self) }
+}
+impl<T: AsHandle> Read for OwnedReadHalf<T> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { half_read(&borrow_handle(&*self.pipe), buf, self.timeout) }
+}
+impl<T: AsHandle> Write for OwnedWriteHalf<T> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> { half_write(&borrow_handle(&*self.pipe), buf, self.timeout) }
+        fn flush(&mut self) -> io::Result<()> { half_flush(&borrow_handle(&*self.pipe), self.timeout) }
+}
+impl<T> fmt::Debug for OwnedReadHalf<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("OwnedReadHalf").field("timeout", &self.timeout).finish_non_exhaustive()
+        }
+}
+impl<T> fmt::Debug for OwnedWriteHalf<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("OwnedWriteHalf").field("timeout", &self.timeout).finish_non_exhaustive()
+        }
+}
+
+/// The halves given to `reunite` came from different pipes, both are handed back.
+pub struct ReuniteError<T>(pub OwnedReadHalf<T>, pub OwnedWriteHalf<T>);
+impl<T> fmt::Debug for ReuniteError<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.debug_tuple("ReuniteError").field(&self.0).field(&self.1).finish() }
+}
+impl<T> fmt::Display for ReuniteError<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.write_str("tried to reunite halves that are not from the same pipe") }
+}
+impl<T> Error for ReuniteError<T> {}
+
+fn reunite<T>(read: OwnedReadHalf<T>, write: OwnedWriteHalf<T>) -> Result<T, ReuniteError<T>> {
+        if !Arc::ptr_eq(&read.pipe, &write.pipe) {
+                return Err(ReuniteError(read, write));
+        }
+        drop(read);
+        // the read half is gone, so this is the last reference
+        Ok(Arc::try_unwrap(write.pipe).unwrap_or_else(|_| unreachable!()))
+}
+
+/// why a synchronous pipe can't be split.
+pub(crate) fn not_overlapped() -> WinError {
+        WinError::new(
+                win::Win32::Foundation::ERROR_NOT_SUPPORTED.to_hresult(),
+                ::windows::w!("only pipes opened with overlapped(true) can be split").to_owned(),
+        )
+}
+
+fn half_read(handle: &UnsafeHandle, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+        handle.read_overlapped(buf, timeout).map(|read| read as usize).map_err(io_error)
+}
+fn half_write(handle: &UnsafeHandle, buf: &[u8], timeout: Option<Duration>) -> io::Result<usize> {
+        handle.write_overlapped(buf, timeout).map(|written| written as usize).map_err(io_error)
+}
+fn half_flush(handle: &UnsafeHandle, timeout: Option<Duration>) -> io::Result<()> { with_deadline(timeout, || handle.flush()).map_err(io_error) }
@@ -0,0 +1,86 @@
+//! Temporarily redirect the current process's std handles to a pipe.
+
+use super::*;
+use ::std::marker::PhantomData;
+use windows::Win32::System::Console::{GetStdHandle, SetStdHandle, STD_ERROR_HANDLE, STD_HANDLE, STD_INPUT_HANDLE, STD_OUTPUT_HANDLE};
+
+/// Guard redirecting stdin, stdout and stderr of the current process to pipes, restoring the previous handles when dropped,
+/// also while unwinding from a panic.
+/// <br>Rust's stdout and stderr are flushed before every switch, so buffered output ends up where it was written.
+/// Input `io::stdin()` has already buffered is still read first.
+/// <br>The std handles are process-wide: other threads reading or writing them during the redirect use the pipe too.
+/// To give a child process a pipe as stdio, use `try_stdio()` on a client, `into_stdio()` on a connected server or
+/// [`stdin_pipe`]/[`stdout_pipe`] instead.
+/// ```no_run
+/// # use windows_named_pipes::*;
+/// # fn main() -> std::io::Result<()> {
+/// let client = DuplexClient::open(std::path::Path::new(r"\\.\pipe\log"))?;
+/// {
+///         let _redirect = StdioRedirect::new().stdout(&client)?.stderr(&client)?;
+///         println!("into the pipe");
+/// }
+/// println!("back on the console");
+/// # Ok(())
+/// # }
+/// ```
+pub struct StdioRedirect<'a> {
+        /// replaced std handles and their previous values, in the order they were redirected
+        old: Vec<(STD_HANDLE, HANDLE)>,
+        /// the pipes must stay open while they are std handles
+        pipes: PhantomData<BorrowedHandle<'a>>,
+}
+impl<'a> StdioRedirect<'a> {
+        /// A guard that has not redirected anything yet.
+        pub fn new() -> Self {
+                Self {
+                        old: Vec::new(),
+                        pipes: PhantomData,
+                }
+        }
+        /// Redirect stdin to `pipe`, on failure everything redirected so far is restored.
+        pub fn stdin<P: AsHandle>(mut self, pipe: &'a P) -> io::Result<Self> {
+                self.redirect(STD_INPUT_HANDLE, pipe.as_handle())?;
+                Ok(self)
+        }
+        /// Redirect stdout to `pipe`, on failure everything redirected so far is restored.
+        pub fn stdout<P: AsHandle>(mut self, pipe: &'a P) -> io::Result<Self> {
+                self.redirect(STD_OUTPUT_HANDLE, pipe.as_handle())?;
+                Ok(self)
+        }
+        /// Redirect stderr to `pipe`, on failure everything redirected so far is restored.
+        pub fn stderr<P: AsHandle>(mut self, pipe: &'a P) -> io::Result<Self> {
+                self.redirect(STD_ERROR_HANDLE, pipe.as_handle())?;
+                Ok(self)
+        }
+        fn redirect(&mut self, std: STD_HANDLE, pipe: BorrowedHandle<'_>) -> io::Result<()> {
+                flush_std();
+                unsafe {
+                        // a process without a console has no std handles, that null handle is what gets restored
+                        let old = GetStdHandle(std).unwrap_or_default();
+                        if !SetStdHandle(std, HANDLE(pipe.as_raw_handle() as isize)).as_bool() {
+                                return Err(io::Error::last_os_error());
+                        }
+                        self.old.push((std, old));
+                }
+                Ok(())
+        }
+}
+impl Default for StdioRedirect<'_> {
+        fn default() -> Self { Self::new() }
+}
+impl Drop for StdioRedirect<'_> {
+        fn drop(&mut self) {
+                flush_std();
+                // restore in reverse, so a handle redirected twice gets its original value back
+                for (std, old) in self.old.drain(..).rev() {
+                        unsafe {
+                                let _ = SetStdHandle(std, old);
+                        }
+                }
+        }
+}
+
+fn flush_std() {
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+}
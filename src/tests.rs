@@ -16,3 +16,1178 @@ fn example_test() {
         // let path = PathBuf::from(format!(r"\\.\pipe\LOCAL\rust_testing_exmaple_{}", ::std::process::id()));
         // let server = DuplexServer::open(&path);
 }
+#[test]
+fn timeout_millis_roundtrip() {
+        assert_eq!(Timeout::Forever.into(), NMPWAIT_WAIT_FOREVER);
+        assert_eq!(Timeout::UseDefault.into(), NMPWAIT_USE_DEFAULT_WAIT);
+        assert_eq!(Timeout::Duration(Duration::ZERO).into(), 1);
+        assert_eq!(Timeout::from(250), Timeout::Duration(Duration::from_millis(250)));
+        assert_eq!(Timeout::from(Timeout::Forever.into()), Timeout::Forever);
+}
+#[test]
+fn read_timeout_ends_blocked_read() {
+        let path = PathBuf::from(format!(r"{}rust_testing_read_timeout_{}", LOCAL_PREFIX, ::std::process::id()));
+        let mut server = DuplexServer::open(&path).unwrap();
+        let client = thread::spawn({
+                let path = path.clone();
+                move || DuplexClient::open(&path).unwrap()
+        });
+        let mut connected = server.connect().unwrap();
+        let _client = client.join().unwrap();
+        assert!(connected.set_read_timeout(Some(Duration::ZERO)).is_err());
+        connected.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+        let err = Read::read(&mut connected, &mut [0u8; 8]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+}
+#[test]
+fn client_open_waits_for_a_free_instance_until_its_timeout() {
+        let path = unique_path();
+        let mut server = DuplexServer::builder().create_new(true).open(&path).unwrap();
+        let first = DuplexClient::open(&path).unwrap();
+        let connected = server.connect().unwrap();
+        let start = ::std::time::Instant::now();
+        let err = DuplexClient::builder()
+                .timeout(Timeout::Duration(Duration::from_millis(100)))
+                .open(&path)
+                .err()
+                .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() >= Duration::from_millis(100) && start.elapsed() < Duration::from_secs(5));
+        let busy = DuplexClient::open(&path).err().unwrap();
+        assert_eq!(WIN32_ERROR::from_error(busy.inner()), Some(ERROR_PIPE_BUSY));
+        let waiting = thread::spawn({
+                let path = path.clone();
+                move || {
+                        DuplexClient::builder()
+                                .timeout(Timeout::Duration(Duration::from_secs(5)))
+                                .open(&path)
+                                .is_ok()
+                }
+        });
+        thread::sleep(Duration::from_millis(50));
+        drop((connected, first));
+        let _connected = server.connect().unwrap();
+        assert!(waiting.join().unwrap());
+}
+#[test]
+fn nonblocking_connect_read_and_poll() {
+        let path = PathBuf::from(format!(r"{}rust_testing_nonblocking_{}", LOCAL_PREFIX, ::std::process::id()));
+        let mut server = DuplexServer::open(&path).unwrap();
+        server.set_nonblocking(true).unwrap();
+        assert_eq!(server.connect().err().unwrap().kind(), io::ErrorKind::WouldBlock);
+        let mut client = DuplexClient::open(&path).unwrap();
+        let mut connected = server.connect().unwrap();
+        assert_eq!(Read::read(&mut connected, &mut [0u8; 8]).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(poll(&mut [PollPipe::new(&connected)], Some(Duration::from_millis(10))).unwrap(), 0);
+        Write::write_all(&mut client, b"ping").unwrap();
+        let mut pipes = [PollPipe::new(&connected)];
+        assert_eq!(poll(&mut pipes, Some(Duration::from_secs(1))).unwrap(), 1);
+        assert!(pipes[0].readable());
+        let mut buf = [0u8; 8];
+        assert_eq!(Read::read(&mut connected, &mut buf).unwrap(), 4);
+        assert_eq!(&buf[..4], b"ping");
+}
+#[test]
+fn collection_settings_only_apply_to_remote_clients() {
+        let (_server, client) = message_pair().unwrap();
+        let invalid = |err: NamedPipeWinError| WIN32_ERROR::from_error(err.inner()) == Some(win::Win32::Foundation::ERROR_INVALID_PARAMETER);
+        // client and server share this machine, so nothing is buffered
+        assert!(invalid(client.set_max_collection_count(4096).unwrap_err()));
+        assert!(invalid(client.set_collect_data_timeout(Duration::from_millis(50)).unwrap_err()));
+}
+#[test]
+fn set_read_mode_shows_in_handle_state() {
+        let path = PathBuf::from(format!(r"{}rust_testing_handle_state_{}", LOCAL_PREFIX, ::std::process::id()));
+        let server = DuplexServer::open(&path).unwrap();
+        let client = DuplexClient::open(&path).unwrap();
+        let state = client.handle_state().unwrap();
+        assert_eq!(
+                (state.instances, state.read_mode, state.wait_mode),
+                (1, ReadMode::Message, WaitMode::Wait)
+        );
+        assert_eq!(state.client_user_name, None);
+        client.set_read_mode(ReadMode::Byte).unwrap();
+        server.set_nonblocking(true).unwrap();
+        assert_eq!(client.handle_state().unwrap().read_mode, ReadMode::Byte);
+        assert_eq!(server.handle_state().unwrap().wait_mode, WaitMode::NoWait);
+}
+#[test]
+fn info_decodes_pipe_info() {
+        let path = PathBuf::from(format!(r"{}rust_testing_info_{}", LOCAL_PREFIX, ::std::process::id()));
+        let mut server = DuplexServer::builder()
+                .type_mode(TypeMode::Byte)
+                .read_mode(ReadMode::Byte)
+                .max_instances(PIPE_UNLIMITED_INSTANCES)
+                .open(&path)
+                .unwrap();
+        let client = DuplexClient::builder().read_mode(ReadMode::Byte).open(&path).unwrap();
+        let info = server.info().clone();
+        // the OS may round buffer sizes, so only check the decoded flags
+        assert_eq!(
+                (info.type_mode, info.end, info.max_instances),
+                (TypeMode::Byte, PipeEnd::Server, MaxInstances::Unlimited)
+        );
+        assert_eq!(client.info().end, PipeEnd::Client);
+        assert_eq!(server.refresh_info().unwrap(), &info);
+}
+#[test]
+fn connected_server_disconnects_before_server_closes() {
+        let path = PathBuf::from(format!(r"{}rust_testing_ownership_{}", LOCAL_PREFIX, ::std::process::id()));
+        let mut server = DuplexServer::open(&path).unwrap();
+        for _ in 0..2 {
+                let client = thread::spawn({
+                        let path = path.clone();
+                        move || {
+                                let mut client = DuplexClient::open(&path).unwrap();
+                                Write::write_all(&mut client, b"hi").unwrap();
+                                client
+                        }
+                });
+                let mut connected = server.connect().unwrap();
+                let mut buf = [0u8; 2];
+                Read::read_exact(&mut connected, &mut buf).unwrap();
+                assert_eq!(&buf, b"hi");
+                // drop order is enforced by the borrow, the instance is free for the next client afterwards
+                drop(connected);
+                drop(client.join().unwrap());
+        }
+        server.close().map_err(|(_, err)| err).unwrap();
+}
+#[test]
+fn dropping_the_connection_disconnects_without_closing_the_instance() {
+        let path = unique_path();
+        let mut server = DuplexServer::builder().create_new(true).open(&path).unwrap();
+        let mut first = DuplexClient::open(&path).unwrap();
+        let connected = server.connect().unwrap();
+        drop(connected);
+        // disconnected: the client is cut off, but the instance handle is still open and takes the next client
+        assert!(Read::read(&mut first, &mut [0u8; 1]).is_err());
+        server.refresh_info().unwrap();
+        let mut second = DuplexClient::open(&path).unwrap();
+        let mut connected = server.connect().unwrap();
+        Write::write_all(&mut second, b"ok").unwrap();
+        let mut buf = [0u8; 2];
+        Read::read_exact(&mut connected, &mut buf).unwrap();
+        assert_eq!(&buf, b"ok");
+        // closing comes last, once the connection is gone
+        drop(connected);
+        server.close().map_err(|(_, err)| err).unwrap();
+        assert!(Read::read(&mut second, &mut [0u8; 1]).is_err());
+        assert!(DuplexClient::open(&path).is_err());
+}
+#[test]
+fn serve_reuses_instance_until_policy_stops() {
+        let path = PathBuf::from(format!(r"{}rust_testing_serve_{}", LOCAL_PREFIX, ::std::process::id()));
+        let mut server = DuplexServer::open(&path).unwrap();
+        let clients = thread::spawn({
+                let path = path.clone();
+                move || {
+                        for msg in [b"one", b"two"] {
+                                let mut client = DuplexClient::builder().timeout(Timeout::Forever).open(&path).unwrap();
+                                Write::write_all(&mut client, msg).unwrap();
+                                let mut buf = [0u8; 3];
+                                let _ = Read::read(&mut client, &mut buf);
+                        }
+                }
+        });
+        let mut served = 0;
+        let err = server
+                .serve_with(ClientErrorPolicy::Stop, |connected| {
+                        let mut buf = [0u8; 3];
+                        Read::read_exact(connected, &mut buf)?;
+                        served += 1;
+                        match &buf {
+                                b"one" => Write::write_all(connected, &buf),
+                                _ => Err(io::Error::other("stop")),
+                        }
+                })
+                .unwrap_err();
+        assert_eq!((served, err.to_string().as_str()), (2, "stop"));
+        clients.join().unwrap();
+}
+#[test]
+fn pipe_server_serves_concurrent_clients_and_shuts_down() {
+        let path = PathBuf::from(format!(r"{}rust_testing_pipe_server_{}", LOCAL_PREFIX, ::std::process::id()));
+        let server = PipeServer::builder()
+                .instances(2)
+                .workers(2)
+                .spawn(&path, |connected| {
+                        let mut buf = [0u8; 4];
+                        Read::read_exact(connected, &mut buf)?;
+                        Write::write_all(connected, &buf)
+                })
+                .unwrap();
+        let clients: Vec<_> = (0..4u8)
+                .map(|i| {
+                        let path = path.clone();
+                        thread::spawn(move || {
+                                let mut client = DuplexClient::builder().timeout(Timeout::Forever).open(&path).unwrap();
+                                Write::write_all(&mut client, &[i; 4]).unwrap();
+                                let mut buf = [0u8; 4];
+                                Read::read_exact(&mut client, &mut buf).unwrap();
+                                assert_eq!(buf, [i; 4]);
+                        })
+                })
+                .collect();
+        for client in clients {
+                client.join().unwrap();
+        }
+        assert!(server.shutdown_timeout(Duration::from_secs(5)));
+}
+#[test]
+fn pipe_server_limits_clients_and_cancels_pending_connects() {
+        use ::std::sync::atomic::{AtomicUsize, Ordering};
+        let path = PathBuf::from(format!(r"{}rust_testing_pipe_server_limit_{}", LOCAL_PREFIX, ::std::process::id()));
+        let (running, peak, errors) = (
+                Arc::new(AtomicUsize::new(0)),
+                Arc::new(AtomicUsize::new(0)),
+                Arc::new(AtomicUsize::new(0)),
+        );
+        let server = PipeServer::builder()
+                .instances(3)
+                .workers(3)
+                .max_clients(1)
+                .on_error({
+                        let errors = errors.clone();
+                        move |_| {
+                                errors.fetch_add(1, Ordering::SeqCst);
+                        }
+                })
+                .spawn(&path, {
+                        let (running, peak) = (running.clone(), peak.clone());
+                        move |connected| {
+                                peak.fetch_max(running.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                                let mut buf = [0u8; 1];
+                                let res = Read::read_exact(connected, &mut buf);
+                                thread::sleep(Duration::from_millis(20));
+                                running.fetch_sub(1, Ordering::SeqCst);
+                                res?;
+                                match buf[0] {
+                                        0 => Err(io::Error::new(io::ErrorKind::InvalidData, "rejected")),
+                                        _ => Write::write_all(connected, &buf),
+                                }
+                        }
+                })
+                .unwrap();
+        let clients: Vec<_> = (0..3u8)
+                .map(|i| {
+                        let path = path.clone();
+                        thread::spawn(move || {
+                                let mut client = DuplexClient::builder().timeout(Timeout::Forever).open(&path).unwrap();
+                                Write::write_all(&mut client, &[i]).unwrap();
+                                if i != 0 {
+                                        let mut buf = [0u8; 1];
+                                        Read::read_exact(&mut client, &mut buf).unwrap();
+                                        assert_eq!(buf, [i]);
+                                }
+                        })
+                })
+                .collect();
+        for client in clients {
+                client.join().unwrap();
+        }
+        assert_eq!(peak.load(Ordering::SeqCst), 1);
+        // every listener is back in a blocking connect without a client, shutting down has to cancel them
+        let start = ::std::time::Instant::now();
+        assert!(server.shutdown_timeout(Duration::from_secs(5)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(errors.load(Ordering::SeqCst), 1);
+}
+#[test]
+fn split_halves_read_and_write_concurrently() {
+        let path = PathBuf::from(format!(r"{}rust_testing_split_{}", LOCAL_PREFIX, ::std::process::id()));
+        let mut server = DuplexServer::builder().overlapped(true).open(&path).unwrap();
+        let client = thread::spawn({
+                let path = path.clone();
+                move || {
+                        let client = DuplexClient::builder().timeout(Timeout::Forever).overlapped(true).open(&path).unwrap();
+                        let (mut read, mut write) = client.into_split().map_err(|(_, err)| err).unwrap();
+                        // the read half blocks until the server answers the write below
+                        let reader = thread::spawn(move || {
+                                let mut buf = [0u8; 4];
+                                read.read_exact(&mut buf).unwrap();
+                                (read, buf)
+                        });
+                        write.write_all(b"ping").unwrap();
+                        let (read, buf) = reader.join().unwrap();
+                        assert_eq!(&buf, b"pong");
+                        read.reunite(write).unwrap()
+                }
+        });
+        let mut connected = server.connect().unwrap();
+        let (mut read, mut write) = connected.split().unwrap();
+        thread::scope(|scope| {
+                let reader = scope.spawn(move || {
+                        let mut buf = [0u8; 4];
+                        read.read_exact(&mut buf).unwrap();
+                        buf
+                });
+                assert_eq!(&reader.join().unwrap(), b"ping");
+                write.write_all(b"pong").unwrap();
+        });
+        drop(client.join().unwrap());
+}
+#[test]
+fn synchronous_pipes_refuse_to_split() {
+        let path = unique_path();
+        let mut server = DuplexServer::builder().create_new(true).open(&path).unwrap();
+        let client = DuplexClient::open(&path).unwrap();
+        assert!(!server.is_overlapped() && !client.is_overlapped());
+        let not_supported = |err: &NamedPipeWinError| WIN32_ERROR::from_error(err.inner()) == Some(win::Win32::Foundation::ERROR_NOT_SUPPORTED);
+        let (mut client, err) = client.into_split().err().unwrap();
+        assert!(not_supported(&err));
+        let mut connected = server.connect().unwrap();
+        assert!(not_supported(&connected.split().err().unwrap()));
+        // still a working synchronous pipe, usable as a file
+        Write::write_all(&mut client, b"sync").unwrap();
+        let mut buf = [0u8; 4];
+        Read::read_exact(&mut connected, &mut buf).unwrap();
+        assert_eq!(&buf, b"sync");
+}
+#[test]
+fn cloned_handles_share_the_connection() {
+        let path = PathBuf::from(format!(r"{}rust_testing_try_clone_{}", LOCAL_PREFIX, ::std::process::id()));
+        let mut server = DuplexServer::open(&path).unwrap();
+        let client = thread::spawn({
+                let path = path.clone();
+                move || {
+                        let mut client = DuplexClient::builder().timeout(Timeout::Forever).open(&path).unwrap();
+                        let mut clone = client.try_clone().unwrap();
+                        Write::write_all(&mut clone, b"ping").unwrap();
+                        let mut buf = [0u8; 4];
+                        Read::read_exact(&mut client, &mut buf).unwrap();
+                        assert_eq!(&buf, b"pong");
+                        let handle = UnsafeHandle::from(client.into_inheritable().map_err(|(_, err)| err).unwrap());
+                        assert!(handle.is_inheritable().unwrap());
+                        let _ = handle.close();
+                }
+        });
+        let mut connected = server.connect().unwrap();
+        let mut buf = [0u8; 4];
+        Read::read_exact(&mut connected, &mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+        Write::write_all(&mut connected, b"pong").unwrap();
+        client.join().unwrap();
+}
+#[test]
+fn child_process_reads_and_writes_pipes() {
+        let (mut stdin, child_stdin) = stdin_pipe().unwrap();
+        let (mut stdout, child_stdout) = stdout_pipe().unwrap();
+        assert!(!stdin.is_overlapped() && !stdout.is_overlapped());
+        let mut child = ::std::process::Command::new("findstr")
+                .arg("^")
+                .stdin(child_stdin)
+                .stdout(child_stdout)
+                .spawn()
+                .unwrap();
+        Write::write_all(&mut stdin, b"hello\r\n").unwrap();
+        drop(stdin);
+        let mut buf = [0u8; 7];
+        Read::read_exact(&mut stdout, &mut buf).unwrap();
+        assert_eq!(&buf, b"hello\r\n");
+        assert!(child.wait().unwrap().success());
+}
+#[test]
+fn connected_server_as_child_stdout() {
+        let path = unique_path();
+        let mut server = DuplexServer::builder()
+                .type_mode(TypeMode::Byte)
+                .read_mode(ReadMode::Byte)
+                .create_new(true)
+                .open(&path)
+                .unwrap();
+        let mut client = DuplexClient::builder().read_mode(ReadMode::Byte).open(&path).unwrap();
+        let stdout = server.connect().unwrap().into_stdio().map_err(|(_, err)| err).unwrap();
+        let mut child = ::std::process::Command::new("cmd")
+                .args(["/C", "echo hello"])
+                .stdout(stdout)
+                .spawn()
+                .unwrap();
+        // the child's copy keeps the client connected
+        drop(server);
+        let mut buf = [0u8; 7];
+        Read::read_exact(&mut client, &mut buf).unwrap();
+        assert_eq!(&buf, b"hello\r\n");
+        assert!(child.wait().unwrap().success());
+}
+#[cfg(feature = "stdio")]
+#[test]
+fn stdio_redirect_restores_handles_after_panic() {
+        use windows::Win32::System::Console::{GetStdHandle, STD_OUTPUT_HANDLE};
+        let path = PathBuf::from(format!(r"{}rust_testing_stdio_redirect_{}", LOCAL_PREFIX, ::std::process::id()));
+        let mut server = InboundServer::builder()
+                .type_mode(TypeMode::Byte)
+                .read_mode(ReadMode::Byte)
+                .open(&path)
+                .unwrap();
+        let client = InboundClient::builder().read_mode(ReadMode::Byte).open(&path).unwrap();
+        let before = unsafe { GetStdHandle(STD_OUTPUT_HANDLE) }.unwrap_or_default();
+        let res = ::std::panic::catch_unwind(|| {
+                let _redirect = StdioRedirect::new().stdout(&client).unwrap();
+                io::stdout().write_all(b"redirected").unwrap();
+                panic!("restore anyway");
+        });
+        assert!(res.is_err());
+        assert_eq!(unsafe { GetStdHandle(STD_OUTPUT_HANDLE) }.unwrap_or_default(), before);
+        let mut connected = server.connect().unwrap();
+        let mut buf = [0u8; 10];
+        Read::read_exact(&mut connected, &mut buf).unwrap();
+        assert_eq!(&buf, b"redirected");
+}
+#[test]
+fn pipe_pairs_are_connected_and_unique() {
+        let (mut server, mut client) = message_pair().unwrap();
+        let (other, _) = pipe_pair().unwrap();
+        assert_ne!(server.path(), other.path());
+        let mut connected = server.connect().unwrap();
+        Write::write_all(&mut connected, b"message").unwrap();
+        let mut buf = [0u8; 16];
+        assert_eq!(Read::read(&mut client, &mut buf).unwrap(), 7);
+        let (mut server, mut client) = byte_pair().unwrap();
+        assert!(server.info().type_mode == TypeMode::Byte && client.info().type_mode == TypeMode::Byte);
+        Write::write_all(&mut client, b"bytes").unwrap();
+        let mut connected = server.connect().unwrap();
+        Read::read_exact(&mut connected, &mut buf[..5]).unwrap();
+        assert_eq!(&buf[..5], b"bytes");
+}
+#[test]
+fn squatted_names_and_untrusted_servers_are_refused() {
+        let path = PathBuf::from(format!(r"{}rust_testing_squatting_{}", LOCAL_PREFIX, ::std::process::id()));
+        let server = DuplexServer::builder().max_instances(2).open(&path).unwrap();
+        assert!(DuplexServer::builder().max_instances(2).open(&path).is_err());
+        let _second = server.new_instance().unwrap();
+        assert!(server.new_instance().is_err());
+        let err = DuplexClient::builder()
+                .expect_server(ServerIdentity::ProcessId(::std::process::id() + 1))
+                .open(&path)
+                .map(drop)
+                .unwrap_err();
+        assert_eq!(
+                err.untrusted_server(),
+                Some(&UntrustedServer {
+                        expected: ServerIdentity::ProcessId(::std::process::id() + 1),
+                        process_id: ::std::process::id(),
+                })
+        );
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        for (i, identity) in [ServerIdentity::ProcessId(::std::process::id()), ServerIdentity::CurrentUser]
+                .into_iter()
+                .enumerate()
+        {
+                let path = PathBuf::from(format!(r"{}rust_testing_squatting_{}_{}", LOCAL_PREFIX, ::std::process::id(), i));
+                let _server = DuplexServer::open(&path).unwrap();
+                DuplexClient::builder().expect_server(identity).open(&path).unwrap();
+        }
+}
+#[test]
+fn framed_roundtrip_with_every_prefix() {
+        for width in [PrefixWidth::U8, PrefixWidth::U16, PrefixWidth::U32, PrefixWidth::U64] {
+                for endian in [Endian::Big, Endian::Little] {
+                        let mut codec = FrameCodec::new();
+                        codec.prefix_width(width).endian(endian).max_frame_size(200);
+                        let mut framed = Framed::with_codec(Vec::new(), codec.clone());
+                        framed.send_frame(b"").unwrap();
+                        framed.send_frame(&[7u8; 200]).unwrap();
+                        assert_eq!(framed.send_frame(&[0u8; 201]).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+                        let bytes = framed.into_inner();
+                        assert_eq!(bytes.len(), 2 * width.len() + 200);
+                        let mut framed = Framed::with_codec(io::Cursor::new(bytes), codec);
+                        assert_eq!(framed.recv_frame().unwrap(), b"");
+                        assert_eq!(framed.recv_frame().unwrap(), [7u8; 200]);
+                        assert_eq!(framed.recv_frame().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+                }
+        }
+        // big endian u16 prefix of 0x0102 against a limit of 256
+        let mut codec = FrameCodec::new();
+        codec.prefix_width(PrefixWidth::U16).max_frame_size(256);
+        let mut framed = Framed::with_codec(&[1u8, 2][..], codec);
+        assert_eq!(framed.recv_frame().unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
+#[test]
+fn framed_over_byte_pipe() {
+        let (mut server, client) = byte_pair().unwrap();
+        let connected = server.connect().unwrap();
+        let mut client = Framed::new(client);
+        let mut connected = Framed::new(connected);
+        let frame = vec![3u8; MAX_TRANSACTION as usize + 1];
+        let sender = thread::spawn(move || {
+                client.send_frame(&frame).unwrap();
+                client
+        });
+        assert_eq!(connected.recv_frame().unwrap().len(), MAX_TRANSACTION as usize + 1);
+        sender.join().unwrap();
+}
+#[test]
+fn read_message_refuses_one_byte_over_the_limit() {
+        let (mut server, mut client) = message_pair().unwrap();
+        let mut connected = server.connect().unwrap();
+        Write::write_all(&mut client, &[1u8; 300]).unwrap();
+        Write::write_all(&mut client, &[2u8; 301]).unwrap();
+        assert_eq!(read_message(&mut connected, 300).unwrap(), [1u8; 300]);
+        assert_eq!(read_message(&mut connected, 300).unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
+#[cfg(feature = "tokio")]
+#[test]
+fn frame_codec_decodes_partial_input() {
+        use tokio_util::codec::{Decoder, Encoder};
+        let mut codec = FrameCodec::new();
+        let mut buf = bytes::BytesMut::new();
+        codec.encode(&b"hello"[..], &mut buf).unwrap();
+        let mut partial = buf.split_to(6);
+        assert_eq!(codec.decode(&mut partial).unwrap(), None);
+        partial.unsplit(buf);
+        assert_eq!(&codec.decode(&mut partial).unwrap().unwrap()[..], b"hello");
+        assert!(partial.is_empty());
+}
+#[cfg(any(feature = "json", feature = "bincode", feature = "msgpack"))]
+#[test]
+fn typed_channels_in_both_read_modes() {
+        let formats = [
+                #[cfg(feature = "json")]
+                Format::Json,
+                #[cfg(feature = "bincode")]
+                Format::Bincode,
+                #[cfg(feature = "msgpack")]
+                Format::MessagePack,
+        ];
+        for format in formats {
+                for (mut server, client) in [message_pair().unwrap(), byte_pair().unwrap()] {
+                        let connected = server.connect().unwrap();
+                        let mut service = TypedChannel::<Vec<u64>, (u32, String), _>::new(connected, format).unwrap();
+                        let mut client = TypedChannel::<(u32, String), Vec<u64>, _>::new(client, format).unwrap();
+                        thread::scope(|scope| {
+                                scope.spawn(|| {
+                                        let (count, _) = service.recv().unwrap();
+                                        // larger than one read buffer, so a message has to be read in parts
+                                        service.send(&vec![u64::MAX; count as usize]).unwrap();
+                                });
+                                assert_eq!(client.call(&(1000, "numbers".to_string())).unwrap(), vec![u64::MAX; 1000]);
+                        });
+                        let mut codec = FrameCodec::new();
+                        codec.max_frame_size(4);
+                        let mut limited = TypedChannel::<String, (), _>::with_codec(client.into_inner(), format, codec).unwrap();
+                        assert_eq!(limited.send(&"too long".to_string()).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+                }
+        }
+}
+#[cfg(any(feature = "json", feature = "bincode", feature = "msgpack"))]
+#[test]
+fn rpc_calls_match_replies_and_time_out() {
+        #[cfg(feature = "json")]
+        let format = Format::Json;
+        #[cfg(all(not(feature = "json"), feature = "bincode"))]
+        let format = Format::Bincode;
+        #[cfg(all(not(feature = "json"), not(feature = "bincode")))]
+        let format = Format::MessagePack;
+        let mut router = Router::new(format);
+        router.route("add", |(a, b): (i64, i64)| Ok(a + b))
+                .route("fail", |code: i32| Err::<(), _>(RemoteError::new(code, "failed")))
+                .route("sleep", |millis: u64| {
+                        thread::sleep(Duration::from_millis(millis));
+                        Ok(())
+                });
+        for (mut server, client) in [message_pair().unwrap(), byte_pair().unwrap()] {
+                let client = Arc::new(RpcClient::new(client, format).unwrap());
+                thread::scope(|scope| {
+                        scope.spawn(|| router.serve_connection(&mut server.connect().unwrap()).unwrap());
+                        let adders: Vec<_> = (0..4i64)
+                                .map(|i| {
+                                        let client = client.clone();
+                                        thread::spawn(move || (0..10i64).all(|j| client.call::<_, i64>("add", &(i, j)).unwrap() == i + j))
+                                })
+                                .collect();
+                        assert!(adders.into_iter().all(|adder| adder.join().unwrap()));
+                        match client.call::<_, ()>("fail", &7) {
+                                Err(RpcError::Remote(err)) => assert_eq!(err, RemoteError::new(7, "failed")),
+                                res => panic!("{:?}", res),
+                        }
+                        match client.call::<_, ()>("missing", &()) {
+                                Err(RpcError::Remote(err)) => assert_eq!(err.code, RemoteError::METHOD_NOT_FOUND),
+                                res => panic!("{:?}", res),
+                        }
+                        match client.call_timeout::<_, ()>("sleep", &200u64, Some(Duration::from_millis(20))) {
+                                Err(RpcError::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::TimedOut),
+                                res => panic!("{:?}", res),
+                        }
+                        // the late reply to the timed out call is not taken for this one
+                        assert_eq!(client.call::<_, i64>("add", &(1, 2)).unwrap(), 3);
+                        // disconnecting ends serve_connection
+                        drop(Arc::try_unwrap(client).unwrap());
+                });
+        }
+}
+#[cfg(feature = "json")]
+#[test]
+fn rpc_embeds_json_params_and_results_as_they_are() {
+        let mut router = Router::new(Format::Json);
+        router.route("add", |(a, b): (i64, i64)| Ok(a + b));
+        let (mut server, mut client) = message_pair().unwrap();
+        thread::scope(|scope| {
+                scope.spawn(|| router.serve_connection(&mut server.connect().unwrap()).unwrap());
+                Write::write_all(&mut client, br#"{"id":7,"method":"add","params":[1,2]}"#).unwrap();
+                let response = read_message(&mut client, MAX_TRANSACTION as usize).unwrap();
+                assert_eq!(response, br#"{"id":7,"result":{"Ok":3}}"#);
+                drop(client);
+        });
+}
+#[cfg(feature = "json")]
+#[test]
+fn rpc_serve_skips_clients_that_left_before_connect() {
+        let mut router = Router::new(Format::Json);
+        router.route("add", |(a, b): (i64, i64)| Ok(a + b));
+        let (mut server, client) = message_pair().unwrap();
+        let path = server.path().to_owned();
+        // connecting this one fails with ERROR_NO_DATA
+        drop(client);
+        thread::spawn(move || router.serve(&mut server));
+        let client = DuplexClient::builder().timeout(Timeout::Forever).overlapped(true).open(&path).unwrap();
+        let client = RpcClient::new(client, Format::Json).unwrap();
+        assert_eq!(client.call::<_, i64>("add", &(1, 2)).unwrap(), 3);
+}
+#[cfg(feature = "jsonrpc")]
+#[test]
+fn jsonrpc_server_answers_batches_and_notifications() {
+        use serde_json::{json, Value};
+        let mut server = JsonRpcServer::new();
+        server.method("subtract", |(a, b): (i64, i64)| Ok(a - b))
+                .method("notice", |_: Value| Ok(()));
+        let handle = |request: Value| server.handle(request.to_string().as_bytes());
+        assert_eq!(
+                handle(json!({"jsonrpc": "2.0", "method": "subtract", "params": [42, 23], "id": 1})),
+                Some(json!({"jsonrpc": "2.0", "result": 19, "id": 1}))
+        );
+        assert_eq!(handle(json!({"jsonrpc": "2.0", "method": "notice", "params": [1]})), None);
+        assert_eq!(handle(json!({"jsonrpc": "2.0", "method": "missing"})), None);
+        assert_eq!(
+                server.handle(b"{\"jsonrpc\": \"2.0\", \"method\"").unwrap()["error"]["code"],
+                JsonRpcErrorObject::PARSE_ERROR
+        );
+        assert_eq!(handle(json!([])).unwrap()["error"]["code"], JsonRpcErrorObject::INVALID_REQUEST);
+        let batch = handle(json!([
+                {"jsonrpc": "2.0", "method": "subtract", "params": [1, 2], "id": "a"},
+                {"jsonrpc": "2.0", "method": "notice"},
+                {"jsonrpc": "2.0", "method": "subtract", "params": {"a": 1}, "id": 2},
+                {"jsonrpc": "2.0", "method": "missing", "id": 3},
+                1,
+        ]))
+        .unwrap();
+        let codes: Vec<_> = batch
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|response| response["error"]["code"].clone())
+                .collect();
+        assert_eq!(
+                codes,
+                [
+                        Value::Null,
+                        json!(JsonRpcErrorObject::INVALID_PARAMS),
+                        json!(JsonRpcErrorObject::METHOD_NOT_FOUND),
+                        json!(JsonRpcErrorObject::INVALID_REQUEST)
+                ]
+        );
+        assert_eq!(batch[0]["result"], -1);
+        assert_eq!(handle(json!([{"jsonrpc": "2.0", "method": "notice"}])), None);
+}
+#[cfg(feature = "jsonrpc")]
+#[test]
+fn jsonrpc_client_in_both_framings() {
+        let mut server = JsonRpcServer::new();
+        server.method("echo", |(text,): (String,)| Ok(text)).method("fail", |()| {
+                Err::<(), _>(JsonRpcErrorObject::new(1, "failed").with_data(serde_json::json!("why")))
+        });
+        for (mut pipe, client) in [message_pair().unwrap(), byte_pair().unwrap()] {
+                let mut client = JsonRpcClient::new(client).unwrap();
+                thread::scope(|scope| {
+                        scope.spawn(|| server.serve_connection(&mut pipe.connect().unwrap()).unwrap());
+                        let long = "x".repeat(2000);
+                        assert_eq!(client.call::<_, String>("echo", &(&long,)).unwrap(), long);
+                        client.notify("echo", &("ignored",)).unwrap();
+                        match client.call::<_, ()>("fail", &()) {
+                                Err(JsonRpcError::Remote(err)) => assert_eq!(err.data, Some(serde_json::json!("why"))),
+                                res => panic!("{:?}", res),
+                        }
+                        let mut batch = JsonRpcBatch::new();
+                        batch.call("echo", &("a",))
+                                .unwrap()
+                                .notify("echo", &("b",))
+                                .unwrap()
+                                .call("missing", &())
+                                .unwrap();
+                        let results = client.batch(&batch).unwrap();
+                        assert_eq!(results[0], Ok(serde_json::json!("a")));
+                        assert_eq!(results[1].as_ref().unwrap_err().code, JsonRpcErrorObject::METHOD_NOT_FOUND);
+                        drop(client);
+                });
+        }
+}
+#[cfg(feature = "jsonrpc")]
+#[test]
+fn jsonrpc_client_takes_a_null_id_error_for_its_call() {
+        let (mut server, client) = message_pair().unwrap();
+        let mut client = JsonRpcClient::new(client).unwrap();
+        thread::scope(|scope| {
+                scope.spawn(|| {
+                        let mut connected = server.connect().unwrap();
+                        read_message(&mut connected, MAX_TRANSACTION as usize).unwrap();
+                        Write::write_all(
+                                &mut connected,
+                                br#"{"jsonrpc":"2.0","error":{"code":-32700,"message":"parse error"},"id":null}"#,
+                        )
+                        .unwrap();
+                });
+                match client.call::<_, ()>("echo", &()) {
+                        Err(JsonRpcError::Remote(err)) => assert_eq!(err.code, JsonRpcErrorObject::PARSE_ERROR),
+                        res => panic!("{:?}", res),
+                }
+        });
+}
+#[cfg(feature = "jsonrpc")]
+#[test]
+fn jsonrpc_serve_skips_clients_that_left_before_connect() {
+        let mut server = JsonRpcServer::new();
+        server.method("echo", |(text,): (String,)| Ok(text));
+        let (errors, errored) = ::std::sync::mpsc::channel();
+        server.on_error(move |err| errors.send(err.kind()).unwrap());
+        let (mut pipe, client) = message_pair().unwrap();
+        let path = pipe.path().to_owned();
+        // connecting this one fails with ERROR_NO_DATA
+        drop(client);
+        thread::spawn(move || server.serve(&mut pipe));
+        let mut client = DuplexClient::builder().timeout(Timeout::Forever).open_jsonrpc(&path).unwrap();
+        assert_eq!(client.call::<_, String>("echo", &("hi",)).unwrap(), "hi");
+        // a message over the limit drops the client and is reported
+        client.set_max_message_size(usize::MAX);
+        client.call::<_, String>("echo", &("x".repeat(DEFAULT_MAX_FRAME_SIZE + 1),)).unwrap_err();
+        assert_eq!(errored.recv().unwrap(), io::ErrorKind::InvalidData);
+}
+#[test]
+fn mux_refuses_synchronous_pipes() {
+        let (server, client) = pair::<DuplexServer, DuplexClient>(TypeMode::Message, false).unwrap();
+        assert_eq!(Mux::new(client).unwrap_err().kind(), io::ErrorKind::Unsupported);
+        assert_eq!(Mux::new(server).unwrap_err().kind(), io::ErrorKind::Unsupported);
+}
+#[test]
+fn mux_streams_are_independent() {
+        for (server, client) in [message_pair().unwrap(), byte_pair().unwrap()] {
+                let mut builder = Mux::builder();
+                builder.window(1024).max_frame_size(256);
+                let client = builder.spawn(client).unwrap();
+                let server = builder.accept(server).unwrap();
+                // nobody reads this one, it takes at most its window on each side
+                let mut stalled = client.open().unwrap();
+                stalled.set_write_timeout(Some(Duration::from_millis(100))).unwrap();
+                assert_eq!(stalled.write_all(&[0u8; 4096]).unwrap_err().kind(), io::ErrorKind::TimedOut);
+                thread::scope(|scope| {
+                        scope.spawn(|| {
+                                let _stalled = server.accept().unwrap();
+                                for _ in 0..3 {
+                                        let mut stream = server.accept().unwrap();
+                                        scope.spawn(move || {
+                                                let mut data = Vec::new();
+                                                stream.read_to_end(&mut data).unwrap();
+                                                stream.write_all(&data).unwrap();
+                                        });
+                                }
+                        });
+                        let echoes: Vec<_> = (0..3u8)
+                                .map(|i| {
+                                        let mut stream = client.open().unwrap();
+                                        scope.spawn(move || {
+                                                stream.write_all(&[i; 10_000]).unwrap();
+                                                stream.close_write();
+                                                let mut data = Vec::new();
+                                                stream.read_to_end(&mut data).unwrap();
+                                                data == [i; 10_000]
+                                        })
+                                })
+                                .collect();
+                        assert!(echoes.into_iter().all(|echo| echo.join().unwrap()));
+                });
+                let mut aborted = client.open().unwrap();
+                aborted.write_all(b"abort").unwrap();
+                server.accept().unwrap().reset();
+                let mut buf = [0u8; 8];
+                assert_eq!(aborted.read(&mut buf).unwrap_err().kind(), io::ErrorKind::ConnectionReset);
+        }
+}
+#[test]
+fn pubsub_overflow_policies() {
+        for overflow in [Overflow::DropOldest, Overflow::DropNewest, Overflow::Disconnect] {
+                let path = unique_path();
+                let server = PubSubServer::builder().queue_size(2).overflow(overflow).spawn(&path).unwrap();
+                let mut client = PubSubClient::open(&path).unwrap();
+                client.subscribe("a").unwrap();
+                client.subscribe("b").unwrap();
+                client.unsubscribe("b").unwrap();
+                while server.subscribers("a") == 0 {
+                        thread::sleep(Duration::from_millis(1));
+                }
+                assert_eq!(server.subscribers("b"), 0);
+                // the first message fills the pipe buffer, once the writer took it the rest queue up behind it
+                server.publish("a", &vec![1; 1 << 20]).unwrap();
+                while server.queued() != 0 {
+                        thread::sleep(Duration::from_millis(1));
+                }
+                let queued = (2..=5u8).map(|i| server.publish("a", &[i]).unwrap()).collect::<Vec<_>>();
+                assert_eq!(server.queued(), if overflow == Overflow::Disconnect { 0 } else { 2 });
+                assert_eq!(client.recv().unwrap(), ("a".to_string(), vec![1; 1 << 20]));
+                let expected = match overflow {
+                        Overflow::DropOldest => {
+                                assert_eq!(queued, [1, 1, 1, 1]);
+                                [4, 5]
+                        }
+                        Overflow::DropNewest => {
+                                assert_eq!(queued, [1, 1, 0, 0]);
+                                [2, 3]
+                        }
+                        Overflow::Disconnect => {
+                                assert_eq!(queued, [1, 1, 0, 0]);
+                                assert!(client.recv().is_err());
+                                continue;
+                        }
+                };
+                assert_eq!(client.recv().unwrap().1, [expected[0]]);
+                assert_eq!(client.recv().unwrap().1, [expected[1]]);
+                client.publish("a", b"own").unwrap();
+                assert_eq!(client.recv().unwrap(), ("a".to_string(), b"own".to_vec()));
+        }
+}
+#[test]
+fn handshake_negotiates_or_reports_the_same_mismatch() {
+        let mut service = Handshake::new("test", 1..=3);
+        service.capability("zstd").capability("lz4").max_frame_size(1024);
+        let mut matching = Handshake::new("test", 2..=5);
+        matching.capability("lz4").capability("deflate").capability("zstd");
+        let mut old = Handshake::new("test", 0..=0);
+        old.capability("zstd");
+        let mut picky = Handshake::new("test", 1..=1);
+        picky.require("deflate");
+        for (mut server, mut client) in [message_pair().unwrap(), byte_pair().unwrap()] {
+                let mut connected = server.connect().unwrap();
+                thread::scope(|scope| {
+                        let served = scope.spawn(|| service.server(&mut connected).unwrap());
+                        let negotiated = matching.client(&mut client).unwrap();
+                        assert_eq!(negotiated, served.join().unwrap());
+                        assert_eq!((negotiated.version, negotiated.max_frame_size), (3, 1024));
+                        assert_eq!(negotiated.capabilities, ["lz4", "zstd"]);
+                        assert!(negotiated.has("zstd") && !negotiated.has("deflate"));
+                });
+                for (client_side, expected) in [
+                        (&old, Mismatch::Version { local: 0..=0, remote: 1..=3 }),
+                        (&picky, Mismatch::Capability("deflate".to_string())),
+                        (
+                                &Handshake::new("other", 1..=3),
+                                Mismatch::Protocol {
+                                        local: "other".to_string(),
+                                        remote: "test".to_string(),
+                                },
+                        ),
+                ] {
+                        thread::scope(|scope| {
+                                let served = scope.spawn(|| service.server(&mut connected).unwrap_err());
+                                match (client_side.client(&mut client).unwrap_err(), served.join().unwrap()) {
+                                        (HandshakeError::Mismatch(client), HandshakeError::Mismatch(server)) => {
+                                                assert_eq!(client, expected);
+                                                match server {
+                                                        Mismatch::Version { local, remote } => assert_eq!((remote, local), (0..=0, 1..=3)),
+                                                        Mismatch::Protocol { local, remote } => {
+                                                                assert_eq!((remote, local), ("other".to_string(), "test".to_string()))
+                                                        }
+                                                        server => assert_eq!(server, expected),
+                                                }
+                                        }
+                                        res => panic!("{:?}", res),
+                                }
+                        });
+                }
+                client.write_all(b"not a handshake").unwrap();
+                assert!(matches!(service.server(&mut connected), Err(HandshakeError::Mismatch(Mismatch::Magic))));
+                // a reject with reason 0, which a handshake client reads as Mismatch::Magic
+                let mut reply = [0u8; 8];
+                client.read_exact(&mut reply).unwrap();
+                assert_eq!(&reply, b"WNPH\x00\x02\x01\x00");
+        }
+}
+#[test]
+fn keepalive_hides_heartbeats_and_times_out() {
+        let mut config = KeepaliveConfig::new();
+        config.interval(Duration::from_millis(10)).idle_timeout(Duration::from_millis(100));
+        for (mut server, client) in [message_pair().unwrap(), byte_pair().unwrap()] {
+                let mut connected = server.connect().unwrap();
+                let mut service = Keepalive::with_config(&mut connected, config.clone()).unwrap();
+                let mut client = Keepalive::with_config(client, config.clone()).unwrap();
+                thread::scope(|scope| {
+                        scope.spawn(|| {
+                                // longer than the idle timeout, only heartbeats keep both ends alive
+                                let start = ::std::time::Instant::now();
+                                while start.elapsed() < Duration::from_millis(300) {
+                                        client.tick().unwrap();
+                                        thread::sleep(Duration::from_millis(2));
+                                }
+                                client.write_all(b"hello").unwrap();
+                        });
+                        let mut hello = [0u8; 5];
+                        service.read_exact(&mut hello).unwrap();
+                        assert_eq!(&hello, b"hello");
+                });
+                // the client stopped sending heartbeats
+                assert_eq!(service.recv().unwrap_err().kind(), io::ErrorKind::TimedOut);
+                assert_eq!(service.send(b"late").unwrap_err().kind(), io::ErrorKind::TimedOut);
+        }
+}
+#[test]
+fn keepalive_reads_to_the_end_once_the_peer_closes() {
+        for (mut server, client) in [message_pair().unwrap(), byte_pair().unwrap()] {
+                let mut connected = server.connect().unwrap();
+                let mut service = Keepalive::new(&mut connected).unwrap();
+                let mut client = Keepalive::new(client).unwrap();
+                thread::scope(|scope| {
+                        scope.spawn(move || {
+                                client.write_all(b"hello").unwrap();
+                                client.write_all(b" world").unwrap();
+                        });
+                        let mut received = Vec::new();
+                        service.read_to_end(&mut received).unwrap();
+                        assert_eq!(received, b"hello world");
+                });
+        }
+}
+#[test]
+fn keepalive_times_out_a_peer_stuck_in_a_frame() {
+        let mut config = KeepaliveConfig::new();
+        config.idle_timeout(Duration::from_millis(100));
+        let (mut server, mut client) = pair::<DuplexServer, DuplexClient>(TypeMode::Byte, false).unwrap();
+        let mut connected = server.connect().unwrap();
+        let mut service = Keepalive::with_config(&mut connected, config).unwrap();
+        // a data frame announcing 10 bytes, only 3 of them ever come
+        Write::write_all(&mut client, &[0, 0, 0, 0, 10, 1, 2, 3]).unwrap();
+        let start = ::std::time::Instant::now();
+        assert_eq!(service.recv().unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        // the server end disconnected, the client finds out
+        assert!(Read::read(&mut client, &mut [0u8; 8]).is_err());
+}
+#[cfg(any(feature = "zstd", feature = "lz4", feature = "deflate"))]
+#[test]
+fn compressed_frames_in_memory() {
+        let telemetry = (0..2000)
+                .map(|i| format!(r#"{{"sensor":"temperature","unit":"celsius","value":{}}}"#, i % 7))
+                .collect::<Vec<_>>()
+                .join(",");
+        for &compression in Compression::ALL {
+                let mut codec = FrameCodec::new();
+                codec.compression(Some(compression)).compress_threshold(64);
+                let mut sender = Framed::with_codec(Vec::new(), codec.clone());
+                sender.send_frame(b"short").unwrap();
+                // below the threshold: prefix, flag and the frame as is
+                assert_eq!(sender.get_ref().len(), 4 + 1 + 5);
+                sender.send_frame(telemetry.as_bytes()).unwrap();
+                let compressed = sender.get_ref().len() - 10;
+                assert!(
+                        compressed * 10 < telemetry.len(),
+                        "{:?} compressed {} bytes to {}",
+                        compression,
+                        telemetry.len(),
+                        compressed
+                );
+                let mut receiver = Framed::with_codec(io::Cursor::new(sender.get_ref().clone()), codec.clone());
+                assert_eq!(receiver.recv_frame().unwrap(), b"short");
+                assert_eq!(receiver.recv_frame().unwrap(), telemetry.as_bytes());
+                // the limit applies to the decompressed frame as well
+                codec.max_frame_size(telemetry.len() - 1);
+                let mut limited = Framed::with_codec(io::Cursor::new(sender.into_inner()), codec.clone());
+                limited.recv_frame().unwrap();
+                assert_eq!(limited.recv_frame().unwrap_err().kind(), io::ErrorKind::InvalidData);
+                // a frame that isn't compressed goes out stored, exactly at the limit plus its flag byte
+                codec.max_frame_size(256).compress_threshold(usize::MAX);
+                let mut sender = Framed::with_codec(Vec::new(), codec.clone());
+                sender.send_frame(&[7u8; 256]).unwrap();
+                assert_eq!(sender.get_ref().len(), 4 + 1 + 256);
+                assert_eq!(sender.send_frame(&[7u8; 257]).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+                let mut receiver = Framed::with_codec(io::Cursor::new(sender.into_inner()), codec.clone());
+                assert_eq!(receiver.recv_frame().unwrap(), [7u8; 256]);
+                // a stored frame over the limit is refused
+                let mut larger = codec.clone();
+                larger.max_frame_size(257);
+                let mut oversized = Framed::with_codec(Vec::new(), larger);
+                oversized.send_frame(&[7u8; 257]).unwrap();
+                assert_eq!(oversized.get_ref()[4], 0);
+                assert_eq!(
+                        codec.decompress_frame(oversized.get_ref()[4..].to_vec()).unwrap_err().kind(),
+                        io::ErrorKind::InvalidData
+                );
+        }
+        // without compression there is no flag byte and no allowance for one
+        let mut codec = FrameCodec::new();
+        codec.max_frame_size(8);
+        let mut receiver = Framed::with_codec(&[0u8, 0, 0, 9, 1, 2, 3, 4, 5, 6, 7, 8, 9][..], codec);
+        assert_eq!(receiver.recv_frame().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let mut service = Handshake::new("telemetry", 1..=1);
+        service.capability("deflate").capability("lz4");
+        let mut client = Handshake::new("telemetry", 1..=1);
+        client.compressions();
+        let (mut server, mut pipe) = message_pair().unwrap();
+        let mut connected = server.connect().unwrap();
+        thread::scope(|scope| {
+                scope.spawn(|| service.server(&mut connected).unwrap());
+                let negotiated = client.client(&mut pipe).unwrap();
+                let expected = Compression::ALL.iter().copied().find(|compression| compression.name() != "zstd");
+                assert_eq!(Compression::negotiated(&negotiated), expected);
+        });
+}
+#[cfg(feature = "encryption")]
+#[test]
+fn secure_channel_rejects_tampered_and_replayed_frames() {
+        let key = [7u8; 32];
+        for (message, (mut server, client)) in [(true, message_pair().unwrap()), (false, byte_pair().unwrap())] {
+                let mut connected = server.connect().unwrap();
+                let (mut service, mut client) = thread::scope(|scope| {
+                        let service = scope.spawn(|| SecureChannel::pre_shared(&mut connected, &key).unwrap());
+                        let client = SecureChannel::pre_shared(client, &key).unwrap();
+                        (service.join().unwrap(), client)
+                });
+                client.send(b"hello").unwrap();
+                assert_eq!(service.recv().unwrap(), b"hello");
+                service.write_all(b"reply").unwrap();
+                let mut reply = [0u8; 5];
+                client.read_exact(&mut reply).unwrap();
+                assert_eq!(&reply, b"reply");
+                // frames 0 and 1 were the setup frame and "hello"
+                let mut raw = |frame: &[u8]| match message {
+                        true => client.get_mut().write_all(frame).unwrap(),
+                        false => Framed::new(client.get_mut()).send_frame(frame).unwrap(),
+                };
+                raw(&[&2u64.to_be_bytes()[..], &[0u8; 32]].concat());
+                assert!(matches!(service.recv(), Err(SecureError::Tampered)));
+                raw(&[&1u64.to_be_bytes()[..], &[0u8; 32]].concat());
+                assert!(matches!(service.recv(), Err(SecureError::Replayed { expected: 2, received: 1 })));
+        }
+        let (mut server, client) = message_pair().unwrap();
+        let mut connected = server.connect().unwrap();
+        thread::scope(|scope| {
+                scope.spawn(|| {
+                        let mut service = SecureChannel::key_exchange(&mut connected).unwrap();
+                        let request = service.recv().unwrap();
+                        service.send(&request).unwrap();
+                });
+                let mut client = SecureChannel::key_exchange(client).unwrap();
+                client.send(b"exchanged").unwrap();
+                assert_eq!(client.recv().unwrap(), b"exchanged");
+        });
+        let (mut server, client) = byte_pair().unwrap();
+        let mut connected = server.connect().unwrap();
+        thread::scope(|scope| {
+                let service = scope.spawn(|| SecureChannel::pre_shared(&mut connected, &[8u8; 32]).map(drop));
+                assert!(matches!(SecureChannel::pre_shared(client, &key), Err(SecureError::Tampered)));
+                assert!(matches!(service.join().unwrap(), Err(SecureError::Tampered)));
+        });
+}
+#[test]
+fn authenticator_rejects_clients_before_the_handler() {
+        fn check<A: Authenticator + 'static>(authenticator: A, good: Credential, bad: Credential) {
+                let path = unique_path();
+                let handled = Arc::new(::std::sync::atomic::AtomicUsize::new(0));
+                let _server = PipeServer::builder()
+                        .authenticator(authenticator)
+                        .spawn(&path, {
+                                let handled = handled.clone();
+                                move |pipe| {
+                                        handled.fetch_add(1, ::std::sync::atomic::Ordering::SeqCst);
+                                        let mut buf = [0u8; 4];
+                                        Read::read_exact(pipe, &mut buf)?;
+                                        Write::write_all(pipe, &buf)
+                                }
+                        })
+                        .unwrap();
+                let err = DuplexClient::builder()
+                        .credential(bad)
+                        .timeout(Timeout::Forever)
+                        .open(&path)
+                        .err()
+                        .unwrap();
+                assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+                let mut client = DuplexClient::builder().credential(good).timeout(Timeout::Forever).open(&path).unwrap();
+                Write::write_all(&mut client, b"ping").unwrap();
+                let mut buf = [0u8; 4];
+                Read::read_exact(&mut client, &mut buf).unwrap();
+                assert_eq!((&buf, handled.load(::std::sync::atomic::Ordering::SeqCst)), (b"ping", 1));
+        }
+        check(
+                SharedSecret::new("open sesame"),
+                Credential::SharedSecret(b"open sesame".to_vec()),
+                Credential::SharedSecret(b"open barley".to_vec()),
+        );
+        #[cfg(feature = "hmac")]
+        check(
+                HmacChallenge::new("key"),
+                Credential::Hmac(b"key".to_vec()),
+                Credential::SharedSecret(b"key".to_vec()),
+        );
+}
+#[test]
+fn authentication_times_out_a_silent_peer() {
+        let path = unique_path();
+        let (errors, errored) = ::std::sync::mpsc::channel();
+        let mut authenticator = SharedSecret::new("open sesame");
+        authenticator.timeout(Some(Duration::from_millis(50)));
+        let _server = PipeServer::builder()
+                .authenticator(authenticator)
+                .on_error(move |err| errors.send(err.kind()).unwrap())
+                .spawn(&path, |_| panic!("a client that never answered got through"))
+                .unwrap();
+        // never answers the challenge
+        let _client = DuplexClient::builder().timeout(Timeout::Forever).open(&path).unwrap();
+        assert_eq!(errored.recv().unwrap(), io::ErrorKind::TimedOut);
+
+        let path = unique_path();
+        let _server = PipeServer::builder().spawn(&path, |pipe| Read::read_exact(pipe, &mut [0u8; 1])).unwrap();
+        // the server never sends a challenge
+        let err = DuplexClient::builder()
+                .credential(Credential::SharedSecret(b"open sesame".to_vec()))
+                .auth_timeout(Some(Duration::from_millis(50)))
+                .timeout(Timeout::Forever)
+                .open(&path)
+                .err()
+                .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+}
+#[test]
+fn chunked_messages_larger_than_the_buffer() {
+        let large = (0..1_000_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        for (mut server, client) in [message_pair().unwrap(), byte_pair().unwrap()] {
+                let mut connected = server.connect().unwrap();
+                let mut service = Chunked::new(&mut connected).unwrap();
+                let mut config = ChunkedConfig::new();
+                config.max_message_size(large.len() - 1);
+                let mut client = Chunked::with_config(client, config).unwrap();
+                assert_eq!(
+                        (service.chunk_size(), client.chunk_size()),
+                        (DEFAULT_BUFSIZE as usize, DEFAULT_BUFSIZE as usize)
+                );
+                assert_eq!(client.send(&large).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+                let large = &large;
+                thread::scope(|scope| {
+                        scope.spawn(move || {
+                                client.send(&large[..large.len() - 1]).unwrap();
+                                client.send(b"").unwrap();
+                                // the whole message goes over the client's limit, it fails before any more of it is read
+                                assert_eq!(client.recv().unwrap_err().kind(), io::ErrorKind::InvalidData);
+                        });
+                        assert_eq!(service.recv().unwrap(), &large[..large.len() - 1]);
+                        assert_eq!(service.recv().unwrap(), b"");
+                        // the client hangs up instead of taking the rest
+                        assert!(service.send(large).is_err());
+                });
+        }
+}
+#[test]
+fn chunked_refuses_an_oversized_final_piece() {
+        let (mut server, mut client) = message_pair().unwrap();
+        let mut connected = server.connect().unwrap();
+        let mut service = Chunked::new(&mut connected).unwrap();
+        // announces 10 bytes, sends 4 in the first piece and 10 instead of the 6 left in the last
+        Write::write_all(&mut client, &[&10u64.to_be_bytes()[..], b"abcd"].concat()).unwrap();
+        Write::write_all(&mut client, b"efghijklmn").unwrap();
+        assert_eq!(service.recv().unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
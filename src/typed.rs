@@ -0,0 +1,131 @@
+//! Serde values over pipes, one value per message in message read mode and one length-prefixed frame per value in byte read mode.
+
+use super::*;
+use ::std::{fmt, marker::PhantomData};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Serialization format of a [`TypedChannel`], each behind the cargo feature of its name.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Format {
+        /// `serde_json`, feature `json`
+        #[cfg(feature = "json")]
+        Json,
+        /// `bincode` with its default options, feature `bincode`
+        #[cfg(feature = "bincode")]
+        Bincode,
+        /// `rmp-serde` with struct fields by name, feature `msgpack`
+        #[cfg(feature = "msgpack")]
+        MessagePack,
+}
+impl Format {
+        /// Serialize `value`, failing with [`InvalidInput`](io::ErrorKind::InvalidInput).
+        pub fn to_vec<T: Serialize + ?Sized>(self, value: &T) -> io::Result<Vec<u8>> {
+                match self {
+                        #[cfg(feature = "json")]
+                        Format::Json => serde_json::to_vec(value).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err)),
+                        #[cfg(feature = "bincode")]
+                        Format::Bincode => bincode::serialize(value).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err)),
+                        #[cfg(feature = "msgpack")]
+                        Format::MessagePack => rmp_serde::to_vec_named(value).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err)),
+                }
+        }
+        /// Deserialize a value, failing with [`InvalidData`](io::ErrorKind::InvalidData).
+        pub fn from_slice<T: DeserializeOwned>(self, bytes: &[u8]) -> io::Result<T> {
+                match self {
+                        #[cfg(feature = "json")]
+                        Format::Json => serde_json::from_slice(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+                        #[cfg(feature = "bincode")]
+                        Format::Bincode => bincode::deserialize(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+                        #[cfg(feature = "msgpack")]
+                        Format::MessagePack => rmp_serde::from_slice(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+                }
+        }
+}
+
+/// Sends `Req` and receives `Resp` values over a connected server or client.
+/// <br>In message read mode every value is one pipe message, in byte read mode one [`Framed`] frame.
+/// Both ends have to use the same read mode and [`Format`], the server's channel has the types the other way around.
+/// ```no_run
+/// # use windows_named_pipes::*;
+/// # fn main() -> std::io::Result<()> {
+/// let client = DuplexClient::open(std::path::Path::new(r"\\.\pipe\typed"))?;
+/// let mut channel = TypedChannel::<String, usize, _>::new(client, Format::Json)?;
+/// let len = channel.call(&"hello".to_string())?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TypedChannel<Req, Resp, P> {
+        framed: Framed<P>,
+        format: Format,
+        /// whether the pipe was in message read mode when the channel was made
+        message: bool,
+        types: PhantomData<fn(Req) -> Resp>,
+}
+impl<Req, Resp, P: AsHandle> TypedChannel<Req, Resp, P> {
+        /// A channel framing values by the current read mode of `pipe`.
+        pub fn new(pipe: P, format: Format) -> io::Result<Self> { Self::with_codec(pipe, format, FrameCodec::default()) }
+        /// A channel with frames formatted by `codec` in byte read mode, in message read mode only its max frame size and compression apply.
+        pub fn with_codec(pipe: P, format: Format, codec: FrameCodec) -> io::Result<Self> {
+                let message = borrow_handle(&pipe).get_state(false).map_err(io_error)?.read_mode == ReadMode::Message;
+                Ok(Self::from_parts(pipe, format, codec, message))
+        }
+}
+impl<Req, Resp, P> TypedChannel<Req, Resp, P> {
+        /// A channel on a pipe that can't be asked for its read mode, like a split half.
+        pub(crate) fn from_parts(pipe: P, format: Format, codec: FrameCodec, message: bool) -> Self {
+                Self {
+                        framed: Framed::with_codec(pipe, codec),
+                        format,
+                        message,
+                        types: PhantomData,
+                }
+        }
+        pub fn format(&self) -> Format { self.format }
+        pub fn get_ref(&self) -> &P { self.framed.get_ref() }
+        pub fn get_mut(&mut self) -> &mut P { self.framed.get_mut() }
+        pub fn into_inner(self) -> P { self.framed.into_inner() }
+}
+impl<Req: Serialize, Resp, P: Write> TypedChannel<Req, Resp, P> {
+        /// Serialize and send `value`, nothing is sent if it fails to serialize or is larger than the max frame size.
+        pub fn send(&mut self, value: &Req) -> io::Result<()> {
+                let bytes = self.format.to_vec(value)?;
+                match self.message {
+                        true => {
+                                let message = self.framed.codec().compress_frame(&bytes)?.into_owned();
+                                write_message(self.framed.get_mut(), &message)
+                        }
+                        false => self.framed.send_frame(&bytes),
+                }
+        }
+}
+impl<Req, Resp: DeserializeOwned, P: Read> TypedChannel<Req, Resp, P> {
+        /// Receive and deserialize the next value, blocking until all of it has arrived.
+        pub fn recv(&mut self) -> io::Result<Resp> {
+                let bytes = match self.message {
+                        true => {
+                                let codec = self.framed.codec();
+                                let max = codec.max_frame_size.saturating_add(codec.flag_len());
+                                let message = read_message(self.framed.get_mut(), max)?;
+                                self.framed.codec().decompress_frame(message)?
+                        }
+                        false => self.framed.recv_frame()?,
+                };
+                self.format.from_slice(&bytes)
+        }
+}
+impl<Req: Serialize, Resp: DeserializeOwned, P: Read + Write> TypedChannel<Req, Resp, P> {
+        /// Send `value` and wait for the value sent back.
+        pub fn call(&mut self, value: &Req) -> io::Result<Resp> {
+                self.send(value)?;
+                self.recv()
+        }
+}
+impl<Req, Resp, P> fmt::Debug for TypedChannel<Req, Resp, P> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("TypedChannel")
+                        .field("format", &self.format)
+                        .field("message", &self.message)
+                        .field("codec", self.framed.codec())
+                        .finish_non_exhaustive()
+        }
+}
@@ -13,7 +13,7 @@ pub trait UnsafeWrapper {
 pub trait ServerImpl: UnsafeWrapper<Inner = UnsafeServer> {
         const ACCESS_MODE: AccessMode;
 }
-pub(crate) trait ConnectedServerImpl: UnsafeWrapper<Inner = UnsafeConnectedServer> {}
+pub(crate) trait ConnectedServerImpl<'a>: UnsafeWrapper<Inner = UnsafeConnectedServer<'a>> {}
 pub trait ClientImpl: UnsafeWrapper<Inner = UnsafeClient> {
         const ACCESS_MODE: ClientAccessMode;
 }